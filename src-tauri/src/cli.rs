@@ -0,0 +1,390 @@
+use crate::{
+    scheduler::{self, ScheduleConfig},
+    server,
+    tvbox::{
+        check::{self, CheckStream, ConnectionStatus},
+        checkpoint::Resume,
+        diff::Diff,
+        filter::Filter,
+        playlist::LivePlaylist,
+        source::{
+            lint,
+            origin::Origin,
+            writer::{Style, WriteOptions},
+            Source,
+        },
+        uptime, Connection,
+    },
+    utils,
+};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// 默认复用1小时内的检测结果
+const DEFAULT_FRESH: u64 = 3600;
+
+const USAGE: &str = r#"用法: tvbox-provider <命令> [参数]
+
+命令:
+  check <uri>   检测tvbox配置中的点播、直播与解析，每个结果输出一行json
+                --kind=all|sites|lives|parses  --quick  --skip-ipv6  --base=<地址>
+  urls <uri>    检测直播源(txt/m3u)中的地址，每个结果输出一行json
+                --quick  --skip-ipv6  --m3u8
+  lint <uri>    静态检查tvbox配置，有错误时退出码为1
+                --base=<地址>  --json
+  format <uri>  按参考配置的字段顺序输出tvbox配置，省略空值
+                --style=pretty|minified|json5  --indent=<空格数>  --keep-defaults
+  diff <旧> <新> 比较两个版本的tvbox配置或直播源
+                --kind=tvbox|playlist  --json  --charset=<编码>
+  serve         启动局域网服务，按计划刷新订阅的配置与直播源
+                --schedule=<配置文件>  --now
+  help          显示帮助
+
+通用参数:
+  --charset=<编码>         指定内容编码，优先于BOM与声明的编码，默认自动检测
+  --resume[=<秒>]          中断后继续检测，跳过该时间内已检测的项目，默认为3600秒
+  --checkpoint-dir=<目录>  检查点文件所在目录，默认为系统临时目录
+  --history=<文件>         记录检测结果的历史数据库
+  --filter=<文件>          检测或输出前执行的过滤与转换规则(json/json5)
+"#;
+
+/// 命令行参数，`--key=value`为选项，`--key`为开关
+pub struct Args {
+    pub command: String,
+    pub positional: Vec<String>,
+    pub options: HashMap<String, String>,
+}
+
+impl Args {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Option<Self> {
+        let mut args = args.into_iter();
+        let command = args.next()?;
+        let mut positional = vec![];
+        let mut options = HashMap::new();
+        for a in args {
+            match a.strip_prefix("--") {
+                Some(opt) => {
+                    let (k, v) = opt.split_once('=').unwrap_or((opt, ""));
+                    options.insert(k.to_string(), v.to_string());
+                }
+                None => positional.push(a),
+            }
+        }
+        Some(Self {
+            command,
+            positional,
+            options,
+        })
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options
+            .get(name)
+            .map(|v| v.is_empty() || v == "true" || v == "1")
+            .unwrap_or_default()
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .get(name)
+            .map(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+    }
+
+    /// 断点续检配置，没有`--resume`时为空
+    fn resume(&self) -> Result<Option<Resume>> {
+        let Some(fresh) = self.options.get("resume") else {
+            return Ok(None);
+        };
+        let fresh = if fresh.is_empty() {
+            DEFAULT_FRESH
+        } else {
+            fresh
+                .parse()
+                .map_err(|_| anyhow!("无效的--resume: {}，应为秒数", fresh))?
+        };
+        let dir = self
+            .option("checkpoint-dir")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                std::env::temp_dir()
+                    .join("tvbox-provider")
+                    .join("checkpoints")
+            });
+        Ok(Some(Resume::new(dir, fresh)))
+    }
+
+    /// 过滤与转换规则
+    async fn filter(&self) -> Result<Filter> {
+        match self.option("filter") {
+            Some(path) => Filter::parse(&utils::read_content(path).await?),
+            None => Ok(Filter::default()),
+        }
+    }
+
+    fn uri(&self) -> Result<&str> {
+        self.positional
+            .first()
+            .map(|u| u.as_str())
+            .ok_or(anyhow!("缺少参数: <uri>"))
+    }
+}
+
+/// 命令行模式，不是命令行调用时返回空，否则返回退出码
+pub fn run() -> Option<i32> {
+    let args = Args::parse(std::env::args().skip(1))?;
+    if !matches!(
+        args.command.as_str(),
+        "check" | "urls" | "lint" | "format" | "diff" | "serve" | "help" | "--help"
+    ) {
+        return None;
+    }
+    if let Some(path) = args.option("history") {
+        if let Err(e) = uptime::open(&PathBuf::from(path)) {
+            eprintln!("{}", e);
+            return Some(1);
+        }
+    }
+    let rt = tokio::runtime::Runtime::new().ok()?;
+    let code = rt.block_on(async {
+        match dispatch(&args).await {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        }
+    });
+    Some(code)
+}
+
+async fn dispatch(args: &Args) -> Result<()> {
+    match args.command.as_str() {
+        "check" => check_source(args).await,
+        "urls" => check_urls(args).await,
+        "lint" => lint_source(args).await,
+        "format" => format_source(args).await,
+        "diff" => diff(args).await,
+        "serve" => serve(args).await,
+        _ => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
+
+/// 输出的一行检测结果
+#[derive(Serialize)]
+struct Line<'a, T> {
+    kind: &'a str,
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(flatten)]
+    status: &'a ConnectionStatus<T>,
+}
+
+/// 按结果确定的顺序逐行输出json
+async fn print_stream<T, F>(kind: &str, mut stream: CheckStream<T>, name: F) -> Result<()>
+where
+    T: serde::Serialize,
+    F: Fn(usize) -> Option<String>,
+{
+    while let Some((index, status)) = stream.next().await {
+        let name = name(index);
+        let line = Line {
+            kind,
+            index,
+            name: name.as_deref(),
+            status: &status,
+        };
+        let mut out = io::stdout().lock();
+        writeln!(out, "{}", serde_json::to_string(&line)?)?;
+        out.flush()?;
+    }
+    crate::tvbox::uptime::save().await;
+    Ok(())
+}
+
+fn stream<T>(items: Vec<T>, args: &Args) -> Result<CheckStream<T>>
+where
+    T: Connection + serde::Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    let threads = check::threads(items.len(), num_cpus::get() / 2);
+    let (quick, skip_ipv6) = (args.flag("quick"), args.flag("skip-ipv6"));
+    Ok(match args.resume()? {
+        Some(r) => check::resume_stream(items, threads, quick, skip_ipv6, "", &r),
+        None => check::check_stream(items, threads, quick, skip_ipv6),
+    })
+}
+
+async fn check_source(args: &Args) -> Result<()> {
+    let uri = args.uri()?;
+    let content = utils::read_content_with(uri, args.option("charset")).await?;
+    let mut source = Source::parse(&content, '#')?;
+    source.tag(&Origin::new(uri, &content));
+    let base = args
+        .option("base")
+        .map(|b| b.to_string())
+        .or_else(|| utils::base_uri(uri));
+    if let Some(base) = base {
+        source.base(&base)?;
+    }
+    args.filter().await?.apply_source(&mut source);
+    let kind = args.option("kind").unwrap_or("all");
+    let none = |_| None;
+    if matches!(kind, "all" | "sites") {
+        print_stream("site", stream(source.sites, args)?, none).await?;
+    }
+    if matches!(kind, "all" | "lives") {
+        print_stream("live", stream(source.lives, args)?, none).await?;
+    }
+    if matches!(kind, "all" | "parses") {
+        let parses = source.parses.unwrap_or_default();
+        print_stream("parse", stream(parses, args)?, none).await?;
+    }
+    Ok(())
+}
+
+async fn check_urls(args: &Args) -> Result<()> {
+    let uri = args.uri()?;
+    let resume = args.resume()?;
+    let content = utils::read_content_with(uri, args.option("charset")).await?;
+    let mut playlist = LivePlaylist::parse(&content);
+    args.filter().await?.apply_playlist(&mut playlist);
+    let mut names = vec![];
+    let mut urls = vec![];
+    for c in playlist.channels {
+        for u in c.urls {
+            names.push(c.name.to_string());
+            urls.push(u);
+        }
+    }
+    let stream = crate::tvbox::urls_stream(
+        urls,
+        args.flag("quick"),
+        args.flag("skip-ipv6"),
+        args.flag("m3u8"),
+        resume.as_ref(),
+    );
+    print_stream("url", stream, |i| names.get(i).cloned()).await
+}
+
+/// 输出配置中的问题，`--json`为true时输出json
+async fn lint_source(args: &Args) -> Result<()> {
+    let uri = args.uri()?;
+    let content = utils::read_content_with(uri, args.option("charset")).await?;
+    let mut source = Source::parse(&content, '#')?;
+    let base = args
+        .option("base")
+        .map(|b| b.to_string())
+        .or_else(|| utils::base_uri(uri));
+    if let Some(base) = base {
+        source.base(&base)?;
+    }
+    let report = lint::lint(&source);
+    if args.flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.to_text());
+    }
+    if !report.is_ok() {
+        return Err(anyhow!("配置中有 {} 个错误", report.errors));
+    }
+    Ok(())
+}
+
+/// 输出格式化后的配置，json5时在开头注明来源
+async fn format_source(args: &Args) -> Result<()> {
+    let uri = args.uri()?;
+    let content = utils::read_content_with(uri, args.option("charset")).await?;
+    let mut source = Source::parse(&content, '#')?;
+    args.filter().await?.apply_source(&mut source);
+    let mut options = WriteOptions {
+        omit_defaults: !args.flag("keep-defaults"),
+        ..Default::default()
+    };
+    if let Some(style) = args.option("style") {
+        options.style = serde_json::from_value(serde_json::Value::from(style))
+            .map_err(|_| anyhow!("无效的输出样式: {}", style))?;
+    }
+    if let Some(indent) = args.option("indent") {
+        options.indent = indent.parse()?;
+    }
+    if options.style == Style::Json5 {
+        options
+            .comments
+            .insert("$".to_string(), format!("来源: {}", uri));
+    }
+    print!("{}", source.to_string_with(&options)?);
+    Ok(())
+}
+
+/// 输出两个版本之间的差异，`--json`为true时输出json
+async fn diff(args: &Args) -> Result<()> {
+    let (Some(before), Some(after)) = (args.positional.first(), args.positional.get(1)) else {
+        return Err(anyhow!("缺少参数: <旧> <新>"));
+    };
+    let charset = args.option("charset");
+    let before = utils::read_content_with(before, charset).await?;
+    let after = utils::read_content_with(after, charset).await?;
+    let diff = Diff::parse(&before, &after, args.option("kind"))?;
+    if args.flag("json") {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{}", diff.to_text());
+    }
+    Ok(())
+}
+
+/// 无界面模式，`--now`为true时启动后立即刷新一次
+async fn serve(args: &Args) -> Result<()> {
+    if let Some(path) = args.option("schedule") {
+        let content = utils::read_content(path).await?;
+        let config: ScheduleConfig = json5::from_str(&content)?;
+        scheduler::start(Some(config.clone()))?;
+        if args.flag("now") {
+            tokio::spawn(async move { scheduler::run(&config).await });
+        }
+    }
+    server::run().await;
+    Ok(())
+}
+
+#[test]
+fn test_args() {
+    let args = Args::parse(
+        [
+            "check",
+            "a.json",
+            "--quick",
+            "--kind=sites",
+            "--skip-ipv6=false",
+        ]
+        .iter()
+        .map(|a| a.to_string()),
+    )
+    .unwrap();
+    assert_eq!(args.command, "check");
+    assert_eq!(args.uri().unwrap(), "a.json");
+    assert!(args.flag("quick"));
+    assert!(!args.flag("skip-ipv6"));
+    assert!(!args.flag("m3u8"));
+    assert_eq!(args.option("kind"), Some("sites"));
+    assert!(args.resume().unwrap().is_none());
+
+    let args = Args::parse(["urls", "--resume=60"].iter().map(|a| a.to_string())).unwrap();
+    assert_eq!(args.resume().unwrap().unwrap().fresh.as_secs(), 60);
+    let args = Args::parse(["urls", "--resume"].iter().map(|a| a.to_string())).unwrap();
+    assert_eq!(
+        args.resume().unwrap().unwrap().fresh.as_secs(),
+        DEFAULT_FRESH
+    );
+    let args = Args::parse(["urls", "--resume=abc"].iter().map(|a| a.to_string())).unwrap();
+    assert!(args.resume().is_err());
+}
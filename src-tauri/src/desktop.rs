@@ -1,6 +1,7 @@
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 
 use crate::{
+    player::{PlayStatus, PlayerProfile},
     tvbox::{
         self,
         check::ConnectionStatus,
@@ -8,23 +9,36 @@ use crate::{
     },
     utils,
 };
-use tauri::{Result, Runtime, Window};
+use tauri::{Manager, Result, Runtime, Window};
 
 #[tauri::command]
 pub async fn parse_playlist(
     uri: String,
     threads: Option<u16>,
     skip_ipv6: Option<bool>,
+    group: Option<bool>,
+    logo: Option<String>,
+    charset: Option<String>,
 ) -> Result<tvbox::playlist::PlaylistCheckResult> {
-    let content = utils::read_content(&uri).await.map_err(|e| {
+    let content = utils::read_content_with(&uri, charset.as_deref()).await.map_err(|e| {
         println!("utils::read_content:{:?}", e);
         tauri::Error::AssetNotFound(e.to_string())
     })?;
-    let source = tvbox::playlist::PlaylistSource {
+    crate::server::allow_local_files(&uri, &content);
+    let mut source = tvbox::playlist::PlaylistSource {
         threads,
         skip_ipv6,
         content,
     };
+    if group.unwrap_or_default() || logo.is_some() {
+        let classifier = tvbox::group::Classifier::new(
+            tvbox::group::GroupRule::defaults(),
+            logo,
+            tvbox::channel::Normalizer::default(),
+        )
+        .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))?;
+        source.classify(&classifier, false);
+    }
     let res = source
         .check()
         .await
@@ -32,9 +46,90 @@ pub async fn parse_playlist(
     Ok(res)
 }
 
+/// 归一化频道名称并合并同一频道，`m3u`为true时输出m3u格式，否则输出txt格式
+#[tauri::command]
+pub async fn dedup_playlist(
+    content: String,
+    aliases: Option<std::collections::HashMap<String, String>>,
+    m3u: Option<bool>,
+    prefer_stable: Option<bool>,
+) -> String {
+    let mut normalizer = tvbox::channel::Normalizer::default();
+    normalizer.extend(aliases.unwrap_or_default());
+    let mut playlist = tvbox::playlist::LivePlaylist::parse(&content);
+    if prefer_stable.unwrap_or_default() {
+        playlist.dedup_stable(&normalizer).await;
+    } else {
+        playlist.dedup(&normalizer);
+    }
+    if m3u.unwrap_or_default() {
+        playlist.to_m3u()
+    } else {
+        playlist.to_txt()
+    }
+}
+
+/// 自动分组并设置台标，`rules`为空时使用默认分组规则
+#[tauri::command]
+pub async fn group_playlist(
+    content: String,
+    rules: Option<Vec<tvbox::group::GroupRule>>,
+    logo: Option<String>,
+    force: Option<bool>,
+    m3u: Option<bool>,
+) -> Result<String> {
+    let rules = rules.unwrap_or_else(tvbox::group::GroupRule::defaults);
+    let classifier =
+        tvbox::group::Classifier::new(rules, logo, tvbox::channel::Normalizer::default())
+            .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))?;
+    let mut playlist = tvbox::playlist::LivePlaylist::parse(&content);
+    playlist.classify(&classifier, force.unwrap_or_default());
+    if m3u.unwrap_or_default() {
+        Ok(playlist.to_m3u())
+    } else {
+        Ok(playlist.to_txt())
+    }
+}
+
+/// 按规则过滤与转换tvbox配置
+#[tauri::command]
+pub async fn filter_tvbox(
+    mut source: tvbox::source::Source,
+    rules: Vec<tvbox::filter::FilterRule>,
+) -> Result<tvbox::source::Source> {
+    let filter = tvbox::filter::Filter::new(rules)
+        .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))?;
+    source.restore_origins();
+    filter.apply_source(&mut source);
+    source.remember_origins();
+    Ok(source)
+}
+
+/// 按规则过滤与转换直播源中的频道与地址
 #[tauri::command]
-pub async fn parse_tvbox(uri: String, base: Option<String>) -> Result<tvbox::source::Source> {
-    let content = utils::read_content(&uri).await.map_err(|e| {
+pub async fn filter_playlist(
+    content: String,
+    rules: Vec<tvbox::filter::FilterRule>,
+    m3u: Option<bool>,
+) -> Result<String> {
+    let filter = tvbox::filter::Filter::new(rules)
+        .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))?;
+    let mut playlist = tvbox::playlist::LivePlaylist::parse(&content);
+    filter.apply_playlist(&mut playlist);
+    if m3u.unwrap_or_default() {
+        Ok(playlist.to_m3u())
+    } else {
+        Ok(playlist.to_txt())
+    }
+}
+
+#[tauri::command]
+pub async fn parse_tvbox(
+    uri: String,
+    base: Option<String>,
+    charset: Option<String>,
+) -> Result<tvbox::source::Source> {
+    let content = utils::read_content_with(&uri, charset.as_deref()).await.map_err(|e| {
         println!("err:{:?}", e);
         tauri::Error::AssetNotFound(e.to_string())
     })?;
@@ -42,17 +137,129 @@ pub async fn parse_tvbox(uri: String, base: Option<String>) -> Result<tvbox::sou
         println!("err:{:?}", e);
         tauri::Error::ApiNotAllowlisted(e.to_string())
     })?;
-    if uri.starts_with("http://") || uri.starts_with("https://") {
-        source.base(&uri).ok();
-    } else if let Some(base) = base {
+    source.tag(&tvbox::source::origin::Origin::new(&uri, &content));
+    // 本地配置中的相对地址以配置文件所在目录为准
+    let base = if utils::is_http_url(&uri) {
+        Some(uri.to_string())
+    } else {
+        base.or_else(|| utils::base_uri(&uri))
+    };
+    if let Some(base) = base {
         source.base(&base).ok();
     }
+    if let Ok(content) = serde_json::to_string(&source) {
+        crate::server::allow_local_files(&uri, &content);
+    }
+    // 返回给前端的配置不包含来源
+    source.remember_origins();
     Ok(source)
 }
 
+/// 合并多个tvbox配置，`prefer_stable`为true时历史上更稳定的站点与直播排在前面
 #[tauri::command]
-pub async fn get_content(uri: String) -> String {
-    utils::read_content(&uri).await.unwrap_or_default()
+pub async fn merge_tvbox(
+    mut sources: Vec<tvbox::source::Source>,
+    prefer_stable: Option<bool>,
+) -> tvbox::source::Source {
+    sources.iter_mut().for_each(|s| s.restore_origins());
+    let mut source = tvbox::source::Source::merge(sources);
+    if prefer_stable.unwrap_or(true) {
+        source.sort_by_stability().await;
+    }
+    source.remember_origins();
+    source
+}
+
+/// 静态检查tvbox配置，返回问题所在的json路径与严重程度
+#[tauri::command]
+pub async fn lint_tvbox(
+    uri: String,
+    base: Option<String>,
+    charset: Option<String>,
+) -> Result<tvbox::source::lint::Report> {
+    let source = parse_tvbox(uri, base, charset).await?;
+    Ok(tvbox::source::lint::lint(&source))
+}
+
+/// 按选项输出tvbox配置，默认为按参考配置排序并省略空值的json
+#[tauri::command]
+pub async fn format_tvbox(
+    mut source: tvbox::source::Source,
+    options: Option<tvbox::source::writer::WriteOptions>,
+) -> Result<String> {
+    source.restore_origins();
+    source
+        .to_string_with(&options.unwrap_or_default())
+        .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))
+}
+
+/// 按来源统计配置中的站点、直播与解析
+#[tauri::command]
+pub async fn tvbox_providers(
+    mut source: tvbox::source::Source,
+) -> Vec<tvbox::source::origin::Provider> {
+    source.restore_origins();
+    source.providers()
+}
+
+/// 移除来自该提供者(配置地址或域名)的所有站点、直播与解析
+#[tauri::command]
+pub async fn remove_provider(
+    mut source: tvbox::source::Source,
+    provider: String,
+) -> tvbox::source::Source {
+    source.restore_origins();
+    source.remove_provider(&provider);
+    source
+}
+
+/// 从链接列表(markdown/文本/json)中导入配置与直播源
+/// `uri`与`content`二选一，`expand`为true时会继续导入仓库索引中的地址
+#[tauri::command]
+pub async fn import_links(
+    uri: Option<String>,
+    content: Option<String>,
+    expand: Option<bool>,
+) -> Result<tvbox::importer::Manifest> {
+    let content = match (content, uri) {
+        (Some(content), _) => content,
+        (None, Some(uri)) => utils::read_content(&uri)
+            .await
+            .map_err(|e| tauri::Error::AssetNotFound(e.to_string()))?,
+        (None, None) => return Ok(Default::default()),
+    };
+    Ok(tvbox::importer::import(&content, expand.unwrap_or_default()).await)
+}
+
+/// 读取内容，`charset`为指定的编码，优先于BOM与声明的编码
+#[tauri::command]
+pub async fn get_content(uri: String, charset: Option<String>) -> String {
+    utils::read_content_with(&uri, charset.as_deref())
+        .await
+        .unwrap_or_default()
+}
+
+/// 比较两个版本的tvbox配置或直播源，`kind`为tvbox或playlist，为空时自动判断
+#[tauri::command]
+pub async fn diff(
+    before: String,
+    after: String,
+    kind: Option<String>,
+    charset: Option<String>,
+) -> Result<tvbox::diff::DiffReport> {
+    let read = |uri: String| {
+        let charset = charset.clone();
+        async move {
+            utils::read_content_with(&uri, charset.as_deref())
+                .await
+                .map_err(|e| tauri::Error::AssetNotFound(e.to_string()))
+        }
+    };
+    let before = read(before).await?;
+    let after = read(after).await?;
+    tvbox::diff::Diff::parse(&before, &after, kind.as_deref())
+        .map(|d| d.report())
+        .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))
 }
 
 #[tauri::command]
@@ -62,14 +269,71 @@ pub async fn urls_accessibility<R: Runtime>(
     quick_mode: Option<bool>,
     skip_ipv6: Option<bool>,
     check_m3u8: Option<bool>,
+    job_id: Option<String>,
+    fresh: Option<u64>,
 ) -> Vec<String> {
-    tvbox::urls_accessibility(window, urls, quick_mode.unwrap_or_default(), skip_ipv6,check_m3u8).await
+    let job = tvbox::job::Job::start(&window, "urls", job_id);
+    let resume = resume(&window, fresh);
+    let task = tvbox::urls_accessibility(
+        window,
+        job.clone(),
+        urls,
+        quick_mode.unwrap_or_default(),
+        skip_ipv6,
+        check_m3u8,
+        resume,
+    );
+    job.run(task).await.unwrap_or_default()
 }
 
-/// 执行
+/// 使用本地播放器播放，不经过shell，播放器启动后返回进程id，退出时发送`play://exit`
 #[tauri::command]
-pub async fn exec(args: String) -> String {
-    inline_exec(args).await
+pub async fn play<R: Runtime>(
+    window: Window<R>,
+    url: String,
+    profile: Option<PlayerProfile>,
+) -> Result<Option<u32>> {
+    let profile = profile.unwrap_or_default();
+    crate::player::play(&url, &profile, move |status: PlayStatus| {
+        window.emit("play://exit", status).ok();
+    })
+    .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))
+}
+
+/// 在系统对话框中选择自定义播放器并保存，返回选择的路径，取消时返回空
+#[tauri::command]
+pub async fn choose_player<R: Runtime>(window: Window<R>) -> Result<Option<String>> {
+    let path = tokio::task::spawn_blocking(move || {
+        tauri::api::dialog::blocking::FileDialogBuilder::new()
+            .set_title("选择播放器")
+            .set_parent(&window)
+            .pick_file()
+    })
+    .await
+    .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))?;
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let path = path.to_string_lossy().to_string();
+    // 重新选择路径时保留原有的参数模板
+    crate::player::set_custom(Some(crate::player::CustomPlayer {
+        path: path.to_string(),
+        args: crate::player::custom().and_then(|p| p.args),
+    }))
+    .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))?;
+    Ok(Some(path))
+}
+
+/// 当前的自定义播放器路径
+#[tauri::command]
+pub async fn custom_player() -> Option<String> {
+    crate::player::custom().map(|p| p.path)
+}
+
+/// 设置自定义播放器的参数模板，只支持{url}占位符，为空时只传入播放地址
+#[tauri::command]
+pub async fn set_player_args(args: Option<Vec<String>>) -> Result<()> {
+    crate::player::set_custom_args(args).map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))
 }
 
 #[tauri::command]
@@ -78,13 +342,22 @@ pub async fn vods_connectivity<R: Runtime>(
     items: Vec<Vod>,
     quick_mode: Option<bool>,
     skip_ipv6: Option<bool>,
+    job_id: Option<String>,
+    fresh: Option<u64>,
 ) -> Vec<ConnectionStatus<Vod>>
 where
 {
-    let items =
-        tvbox::check::check_connections(window, items, quick_mode.unwrap_or_default(), skip_ipv6)
-            .await;
-    items
+    let job = tvbox::job::Job::start(&window, "vods", job_id);
+    let resume = resume(&window, fresh);
+    let task = tvbox::check::check_connections(
+        window,
+        job.clone(),
+        items,
+        quick_mode.unwrap_or_default(),
+        skip_ipv6,
+        resume,
+    );
+    job.run(task).await.unwrap_or_default()
 }
 #[tauri::command]
 pub async fn live_connectivity<R: Runtime>(
@@ -92,13 +365,22 @@ pub async fn live_connectivity<R: Runtime>(
     items: Vec<Live>,
     quick_mode: Option<bool>,
     skip_ipv6: Option<bool>,
+    job_id: Option<String>,
+    fresh: Option<u64>,
 ) -> Vec<ConnectionStatus<Live>>
 where
 {
-    let items =
-        tvbox::check::check_connections(window, items, quick_mode.unwrap_or_default(), skip_ipv6)
-            .await;
-    items
+    let job = tvbox::job::Job::start(&window, "lives", job_id);
+    let resume = resume(&window, fresh);
+    let task = tvbox::check::check_connections(
+        window,
+        job.clone(),
+        items,
+        quick_mode.unwrap_or_default(),
+        skip_ipv6,
+        resume,
+    );
+    job.run(task).await.unwrap_or_default()
 }
 
 #[tauri::command]
@@ -107,18 +389,77 @@ pub async fn parses_connectivity<R: Runtime>(
     items: Vec<Parse>,
     quick_mode: Option<bool>,
     skip_ipv6: Option<bool>,
+    job_id: Option<String>,
+    fresh: Option<u64>,
 ) -> Vec<ConnectionStatus<Parse>>
 where
 {
-    let items =
-        tvbox::check::check_connections(window, items, quick_mode.unwrap_or_default(), skip_ipv6)
-            .await;
-    items
+    let job = tvbox::job::Job::start(&window, "parses", job_id);
+    let resume = resume(&window, fresh);
+    let task = tvbox::check::check_connections(
+        window,
+        job.clone(),
+        items,
+        quick_mode.unwrap_or_default(),
+        skip_ipv6,
+        resume,
+    );
+    job.run(task).await.unwrap_or_default()
+}
+
+/// 断点续检配置，检查点保存在应用数据目录中，`fresh`(秒)为空时不使用检查点
+fn resume<R: Runtime>(window: &Window<R>, fresh: Option<u64>) -> Option<tvbox::checkpoint::Resume> {
+    let dir = window
+        .app_handle()
+        .path_resolver()
+        .app_data_dir()?
+        .join("checkpoints");
+    fresh.map(|f| tvbox::checkpoint::Resume::new(dir, f))
+}
+
+/// 地址的历史可用性，没有检测记录的地址为空
+#[tauri::command]
+pub async fn uptime(urls: Vec<String>) -> Vec<Option<tvbox::uptime::Uptime>> {
+    // 查询数据库前会写入未保存的记录，在阻塞线程中执行
+    tokio::task::spawn_blocking(move || urls.iter().map(|u| tvbox::uptime::url(u)).collect())
+        .await
+        .unwrap_or_default()
+}
+
+/// 主机的历史可用性，没有检测记录的主机为空
+#[tauri::command]
+pub async fn host_uptime(hosts: Vec<String>) -> Vec<Option<tvbox::uptime::Uptime>> {
+    tokio::task::spawn_blocking(move || hosts.iter().map(|h| tvbox::uptime::host(h)).collect())
+        .await
+        .unwrap_or_default()
+}
+
+/// 取消检测任务
+#[tauri::command]
+pub async fn cancel_job(id: String) -> bool {
+    tvbox::job::cancel(&id)
+}
+
+/// 检测任务的状态与进度
+#[tauri::command]
+pub async fn job_status(id: String) -> Option<tvbox::job::JobInfo> {
+    tvbox::job::status(&id)
+}
+
+/// 正在执行的检测任务
+#[tauri::command]
+pub async fn list_jobs() -> Vec<tvbox::job::JobInfo> {
+    tvbox::job::running()
 }
 
+/// 保存文件，仅允许写入应用数据目录或通过对话框选择的路径
 #[tauri::command]
-pub async fn save(path: String, content: String) -> bool {
-    std::fs::write(path, content).is_ok()
+pub async fn save<R: Runtime>(window: Window<R>, path: String, content: String) -> bool {
+    let res = match crate::storage::allowed_path(&window, &path) {
+        Ok(path) => crate::storage::write_atomic(&path, content.as_bytes()).await,
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| println!("save.error: {:?}", e)).is_ok()
 }
 
 #[tauri::command]
@@ -126,70 +467,151 @@ pub async fn cache(key: String, value: String) {
     crate::server::updata_cache(&key, value).await;
 }
 
+/// 设置HLS中转时需要注入的请求头
 #[tauri::command]
-pub async fn lan_ip() -> Option<Vec<String>> {
-    crate::utils::lan_ip()
+pub async fn relay_headers(src: String, headers: std::collections::HashMap<String, String>) {
+    crate::server::set_relay_headers(&src, headers).await;
 }
 
+/// 设置GitHub镜像前缀，`mirrors`为空时只返回当前的镜像前缀
 #[tauri::command]
-pub async fn is_install(application: String) -> bool {
-    crate::utils::is_installed(&application)
+pub async fn github_mirrors(mirrors: Option<Vec<String>>) -> Vec<String> {
+    if let Some(mirrors) = mirrors {
+        crate::mirror::set_mirrors(mirrors);
+    }
+    crate::mirror::mirrors()
 }
 
-pub async fn inline_exec(args: String) -> String {
-    if args.is_empty() {
-        return args;
+/// 设置定时刷新订阅的配置与直播源，`config`为空时停止
+#[tauri::command]
+pub async fn schedule(
+    config: Option<crate::scheduler::ScheduleConfig>,
+) -> Result<crate::scheduler::ScheduleStatus> {
+    if let Some(c) = config.as_ref() {
+        reject_command(&c.notify)?;
     }
-    let (shell, first) = if cfg!(windows) {
-        ("cmd", "/c")
-    } else {
-        ("sh", "-c")
-    };
-    println!("args: {}", args);
-    let child = tokio::process::Command::new(shell)
-        .args([first])
-        .args(&[args])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .kill_on_drop(true)
-        .spawn();
-    if let Ok(child) = child {
-        let one_minute = std::time::Duration::from_secs(60);
-        tokio::time::timeout(one_minute, child.wait_with_output())
-            .await
-            .ok()
-            .and_then(|out| out.map_err(|e| println!("shell.error: {:?}", e)).ok())
-            .and_then(|out| {
-                println!("out: {:?}", out);
-                String::from_utf8(out.stdout).ok()
-            })
-            .unwrap_or_default()
-    } else {
-        println!("shell.err2: {:?}", child);
-        String::default()
+    crate::scheduler::start(config).map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))
+}
+
+/// 本地命令只能在系统对话框中确认后设置，不接受来自界面的命令
+fn reject_command(config: &crate::notify::NotifyConfig) -> Result<()> {
+    if config.command.is_some() {
+        return Err(tauri::Error::ApiNotAllowlisted(
+            "本地命令需要通过set_notify_command设置".to_string(),
+        ));
     }
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn download(url: String, path: String) -> bool {
-    if let Ok(resp) = reqwest::get(url).await {
-        if let Ok(buff) = resp.bytes().await {
-            return std::fs::write(path, buff).is_ok();
+pub async fn schedule_status() -> crate::scheduler::ScheduleStatus {
+    crate::scheduler::status()
+}
+
+/// 立即刷新一次订阅的内容
+#[tauri::command]
+pub async fn run_schedule() -> Result<crate::scheduler::RunReport> {
+    crate::scheduler::run_now()
+        .await
+        .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))
+}
+
+/// 发送一条测试通知，返回失败的原因
+#[tauri::command]
+pub async fn test_notify(config: crate::notify::NotifyConfig) -> Result<Vec<String>> {
+    reject_command(&config)?;
+    let summary = crate::notify::Summary::new(vec![crate::notify::Event::ChannelDown {
+        name: "测试频道".to_string(),
+    }]);
+    Ok(crate::notify::send(&config, &summary).await)
+}
+
+/// 设置通知时执行的本地命令，需要用户在系统对话框中确认，返回是否已保存
+/// `command`第一项为程序，其余为参数，为空时移除
+#[tauri::command]
+pub async fn set_notify_command<R: Runtime>(
+    window: Window<R>,
+    command: Option<Vec<String>>,
+) -> Result<bool> {
+    if let Some(c) = command.as_ref() {
+        let message = format!(
+            "检测结果变化时将执行以下命令:\n\n{}\n\n是否允许?",
+            c.join(" ")
+        );
+        let approved = tokio::task::spawn_blocking(move || {
+            tauri::api::dialog::blocking::ask(Some(&window), "通知命令", message)
+        })
+        .await
+        .unwrap_or_default();
+        if !approved {
+            return Ok(false);
         }
     }
-    false
+    crate::notify::set_command(command)
+        .map_err(|e| tauri::Error::ApiNotAllowlisted(e.to_string()))?;
+    Ok(true)
 }
 
+/// 当前通知时执行的本地命令
 #[tauri::command]
-pub async fn hash(content: String) -> String {
-    let value = xxhash_rust::xxh3::xxh3_64_with_seed(content.as_bytes(), 42);
-    format!("{:0>16X}", value)
+pub async fn notify_command() -> Option<Vec<String>> {
+    crate::notify::command()
 }
 
+/// 重新加载节目单，返回节目单中的频道数量
+#[tauri::command]
+pub async fn load_epg(urls: Option<Vec<String>>) -> usize {
+    crate::server::refresh_epg(urls.unwrap_or_default(), true).await
+}
 
-#[tokio::test]
-async fn test_exec() {
-    let args = r#"start  /d D:\"Program Files"\mpv mpv http://39.134.24.162/dbiptv.sn.chinamobile.com/PLTV/88888888/224/3221226395/1.m3u8"#;
-    inline_exec(args.to_string()).await;
-    assert_eq!(1, 1)
+#[tauri::command]
+pub async fn lan_ip() -> Option<Vec<String>> {
+    crate::utils::lan_ip()
+}
+
+#[tauri::command]
+pub async fn is_install(application: String) -> bool {
+    crate::utils::is_installed(&application)
+}
+
+/// 下载文件，仅允许写入应用数据目录或通过对话框选择的路径
+/// 进度通过 download://progress 事件通知
+#[tauri::command]
+pub async fn download<R: Runtime>(
+    window: Window<R>,
+    url: String,
+    path: String,
+    max_size: Option<u64>,
+    hash: Option<String>,
+    timeout: Option<u64>,
+) -> bool {
+    let options = crate::storage::DownloadOptions {
+        max_size,
+        hash,
+        timeout,
+    };
+    let res = match crate::storage::allowed_path(&window, &path) {
+        Ok(path) => {
+            crate::storage::download(&url, &path, &options, |progress, total| {
+                window
+                    .emit(
+                        "download://progress",
+                        tvbox::check::ProgressPayload {
+                            progress,
+                            total,
+                            job: None,
+                        },
+                    )
+                    .ok();
+            })
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| println!("download.error: {:?}", e)).is_ok()
+}
+
+#[tauri::command]
+pub async fn hash(content: String) -> String {
+    utils::hash(content.as_bytes())
 }
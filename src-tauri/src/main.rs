@@ -5,14 +5,24 @@
 extern crate anyhow;
 #[macro_use]
 extern crate serde;
-#[macro_use]
 extern crate log;
 
+use tauri::Manager;
+
+mod cli;
 mod desktop;
+mod mirror;
+mod notify;
+mod player;
+mod scheduler;
 mod server;
+mod storage;
 mod tvbox;
 mod utils;
 fn main() {
+    if let Some(code) = cli::run() {
+        std::process::exit(code);
+    }
     std::env::set_var("RUST_LOG", "info");
     std::thread::spawn(|| {
         let rt = tokio::runtime::Runtime::new().unwrap();
@@ -23,17 +33,67 @@ fn main() {
     });
     tauri::Builder::default()
         .plugin(tauri_plugin_store::Builder::default().build())
+        .setup(|app| {
+            let identifier = app.config().tauri.bundle.identifier.to_string();
+            notify::set_desktop(move |title, body| {
+                tauri::api::notification::Notification::new(&identifier)
+                    .title(title)
+                    .body(body)
+                    .show()
+                    .ok();
+            });
+            if let Some(dir) = app.path_resolver().app_data_dir() {
+                if let Err(e) = tvbox::uptime::open(&dir.join("uptime.db")) {
+                    println!("uptime.error: {:?}", e);
+                }
+                if let Err(e) = player::open(&dir.join("player.json")) {
+                    println!("player.error: {:?}", e);
+                }
+                if let Err(e) = notify::open(&dir.join("notify.json")) {
+                    println!("notify.error: {:?}", e);
+                }
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             desktop::parse_playlist,
             desktop::parse_tvbox,
+            desktop::merge_tvbox,
+            desktop::filter_tvbox,
+            desktop::lint_tvbox,
+            desktop::format_tvbox,
+            desktop::tvbox_providers,
+            desktop::remove_provider,
+            desktop::dedup_playlist,
+            desktop::group_playlist,
+            desktop::filter_playlist,
             desktop::get_content,
+            desktop::diff,
+            desktop::import_links,
             desktop::urls_accessibility,
-            desktop::exec,
+            desktop::play,
+            desktop::choose_player,
+            desktop::custom_player,
+            desktop::set_player_args,
             desktop::vods_connectivity,
             desktop::live_connectivity,
             desktop::parses_connectivity,
+            desktop::cancel_job,
+            desktop::job_status,
+            desktop::list_jobs,
+            desktop::uptime,
+            desktop::host_uptime,
             desktop::save,
             desktop::cache,
+            desktop::relay_headers,
+            desktop::load_epg,
+            desktop::schedule,
+            desktop::schedule_status,
+            desktop::run_schedule,
+            desktop::test_notify,
+            desktop::set_notify_command,
+            desktop::notify_command,
+            desktop::github_mirrors,
             desktop::lan_ip,
             desktop::is_install,
             desktop::download,
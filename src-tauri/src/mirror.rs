@@ -0,0 +1,233 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use reqwest::StatusCode;
+use std::{
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+/// 默认的GitHub镜像前缀，镜像地址为 前缀 + 原始地址
+const DEFAULT_MIRRORS: &[&str] = &[
+    "https://mirror.ghproxy.com/",
+    "https://ghproxy.net/",
+    "https://gh.ddlc.top/",
+];
+/// 读取内容时每个地址的尝试次数
+pub const RETRIES: u32 = 3;
+/// 检测时每个地址的尝试次数
+pub const CHECK_RETRIES: u32 = 2;
+/// 首次重试前的等待时间(毫秒)，之后每次翻倍
+const BACKOFF: u64 = 500;
+/// 尝试所有地址与重试的总时间上限
+const TOTAL_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// GitHub上的文件地址，可能带有镜像前缀, 如: https://ghproxy.com/raw.githubusercontent.com/...
+static GITHUB: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(
+        r"^(?:https?://[^/]+/)?((?:https?://)?(?:raw\.githubusercontent\.com|gist\.githubusercontent\.com|github\.com/[^/]+/[^/]+/(?:raw|releases/download))/\S*)$",
+    )
+    .unwrap()
+});
+
+/// 替换了域名的raw镜像
+const RAW_HOSTS: &[&str] = &["https://raw.fastgit.org/", "https://raw.kgithub.com/"];
+
+struct Mirrors {
+    prefixes: Vec<String>,
+    /// 最近一次可用的镜像前缀，空字符串表示直连
+    preferred: Option<String>,
+}
+
+static MIRRORS: Lazy<RwLock<Mirrors>> = Lazy::new(|| {
+    RwLock::new(Mirrors {
+        prefixes: DEFAULT_MIRRORS.iter().map(|m| m.to_string()).collect(),
+        preferred: None,
+    })
+});
+
+/// 当前的镜像前缀
+pub fn mirrors() -> Vec<String> {
+    MIRRORS.read().unwrap().prefixes.clone()
+}
+
+/// 设置镜像前缀，按顺序尝试
+pub fn set_mirrors(prefixes: Vec<String>) {
+    let prefixes = prefixes
+        .into_iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            if p.ends_with('/') {
+                p
+            } else {
+                format!("{}/", p)
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut m = MIRRORS.write().unwrap();
+    if let Some(preferred) = m.preferred.as_ref() {
+        if !preferred.is_empty() && !prefixes.contains(preferred) {
+            m.preferred = None;
+        }
+    }
+    m.prefixes = prefixes;
+}
+
+/// 去除镜像前缀后的GitHub地址，非GitHub地址为空
+pub fn github_origin(url: &str) -> Option<String> {
+    for host in RAW_HOSTS {
+        if let Some(path) = url.strip_prefix(host) {
+            return Some(format!("https://raw.githubusercontent.com/{}", path));
+        }
+    }
+    let origin = GITHUB.captures(url.trim())?.get(1)?.as_str();
+    if origin.starts_with("http://") || origin.starts_with("https://") {
+        Some(origin.to_string())
+    } else {
+        Some(format!("https://{}", origin))
+    }
+}
+
+/// 需要依次尝试的地址：用户填写的地址、上次可用的镜像、GitHub原始地址、其他镜像
+pub fn candidates(url: &str) -> Vec<String> {
+    let Some(origin) = github_origin(url) else {
+        return vec![url.to_string()];
+    };
+    let m = MIRRORS.read().unwrap();
+    let mut prefixes = vec![];
+    if let Some(preferred) = m.preferred.as_ref() {
+        prefixes.push(preferred.to_string());
+    }
+    prefixes.push(String::new());
+    prefixes.extend(m.prefixes.iter().cloned());
+    let mut items: Vec<String> = vec![url.trim().to_string()];
+    for p in prefixes {
+        let candidate = format!("{}{}", p, origin);
+        if !items.contains(&candidate) {
+            items.push(candidate);
+        }
+    }
+    items
+}
+
+fn remember(candidate: &str, origin: &str) {
+    // 替换了域名的镜像无法作为前缀使用
+    let Some(prefix) = candidate.strip_suffix(origin) else {
+        return;
+    };
+    let mut m = MIRRORS.write().unwrap();
+    if m.preferred.as_deref() != Some(prefix) {
+        m.preferred = Some(prefix.to_string());
+    }
+}
+
+/// 超时、连接失败等可以重试的错误
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect() || e.is_request()
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+/// 发送GET请求，临时错误时按指数退避重试，GitHub地址失败时依次尝试镜像
+/// 所有地址都失败或超过总时间上限时返回最后一次的响应或错误
+pub async fn send(client: &reqwest::Client, url: &str, retries: u32) -> Result<reqwest::Response> {
+    let origin = github_origin(url);
+    let deadline = Instant::now() + TOTAL_TIMEOUT;
+    let mut last = Err(anyhow!("无效资源!"));
+    'candidates: for candidate in candidates(url) {
+        for attempt in 0..retries.max(1) {
+            if attempt > 0 {
+                let delay = Duration::from_millis(BACKOFF * 2u64.pow(attempt - 1));
+                if Instant::now() + delay >= deadline {
+                    break 'candidates;
+                }
+                tokio::time::sleep(delay).await;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Ok(res) = tokio::time::timeout(remaining, client.get(&candidate).send()).await
+            else {
+                if last.is_err() {
+                    last = Err(anyhow!("请求超时: {}", url));
+                }
+                break 'candidates;
+            };
+            match res {
+                Ok(resp) if resp.status().is_success() => {
+                    if let Some(origin) = origin.as_ref() {
+                        remember(&candidate, origin);
+                    }
+                    return Ok(resp);
+                }
+                Ok(resp) => {
+                    let retryable = is_retryable(resp.status());
+                    last = Ok(resp);
+                    if !retryable {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let transient = is_transient(&e);
+                    last = Err(e.into());
+                    if !transient {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    last
+}
+
+#[test]
+fn test_github_mirror() {
+    let origin = "https://raw.githubusercontent.com/FongMi/CatVodSpider/main/json/config.json";
+    assert_eq!(github_origin(origin).as_deref(), Some(origin));
+    assert_eq!(
+        github_origin("https://raw.fastgit.org/FongMi/CatVodSpider/main/json/config.json")
+            .as_deref(),
+        Some(origin)
+    );
+    assert_eq!(
+        github_origin("https://ghproxy.com/raw.githubusercontent.com/a/b/main/py.json").as_deref(),
+        Some("https://raw.githubusercontent.com/a/b/main/py.json")
+    );
+    assert_eq!(
+        github_origin("https://ghproxy.com/https://github.com/a/b/raw/main/1.txt").as_deref(),
+        Some("https://github.com/a/b/raw/main/1.txt")
+    );
+    assert_eq!(github_origin("https://github.com/a/b"), None);
+    assert_eq!(github_origin("http://example.com/a.json"), None);
+    assert_eq!(
+        candidates("http://example.com/a.json"),
+        vec!["http://example.com/a.json"]
+    );
+
+    // 用户填写的镜像地址最先尝试
+    set_mirrors(vec!["https://m1.example.com".to_string()]);
+    let items = candidates("https://ghproxy.com/https://raw.githubusercontent.com/a/b/main/1.txt");
+    assert_eq!(
+        items,
+        vec![
+            "https://ghproxy.com/https://raw.githubusercontent.com/a/b/main/1.txt",
+            "https://raw.githubusercontent.com/a/b/main/1.txt",
+            "https://m1.example.com/https://raw.githubusercontent.com/a/b/main/1.txt",
+        ]
+    );
+    remember(&items[2], &items[1]);
+    assert_eq!(
+        candidates(&items[1])[..2],
+        [
+            "https://raw.githubusercontent.com/a/b/main/1.txt",
+            "https://m1.example.com/https://raw.githubusercontent.com/a/b/main/1.txt"
+        ]
+    );
+    set_mirrors(DEFAULT_MIRRORS.iter().map(|m| m.to_string()).collect());
+    assert_eq!(
+        candidates(&items[1])[1],
+        DEFAULT_MIRRORS[0].to_string() + &items[1]
+    );
+}
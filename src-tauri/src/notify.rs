@@ -0,0 +1,416 @@
+use crate::{
+    scheduler::SubscriptionKind,
+    tvbox::{channel::Normalizer, playlist::LivePlaylist, source::Source},
+};
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::io::AsyncWriteExt;
+
+/// 本地命令的最长执行时间
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// 系统通知中最多列出的事件数量
+const DESKTOP_LINES: usize = 5;
+
+type Desktop = Box<dyn Fn(&str, &str) + Send + Sync>;
+
+/// 显示系统通知的方法，由桌面应用设置
+static DESKTOP: Lazy<Mutex<Option<Desktop>>> = Lazy::new(|| Mutex::new(None));
+
+/// 桌面应用中用户确认过的本地命令
+struct Approved {
+    /// 保存的位置
+    path: Option<PathBuf>,
+    command: Option<Vec<String>>,
+}
+
+static COMMAND: Lazy<Mutex<Approved>> = Lazy::new(|| {
+    Mutex::new(Approved {
+        path: None,
+        command: None,
+    })
+});
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::ClientBuilder::new()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap()
+});
+
+fn default_shrink_percent() -> f64 {
+    20.0
+}
+
+/// 通知配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    /// 以POST方式发送json摘要的地址
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+    /// 本地命令，第一项为程序，其余为参数，不经过shell执行，json摘要通过标准输入传入
+    /// 只能在配置文件中设置，桌面应用中使用用户确认过的命令
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+    /// 显示系统通知，仅在桌面应用中有效
+    #[serde(default)]
+    pub desktop: bool,
+    /// 条目数减少超过该百分比时通知
+    #[serde(default = "default_shrink_percent")]
+    pub shrink_percent: f64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: vec![],
+            command: None,
+            desktop: false,
+            shrink_percent: default_shrink_percent(),
+        }
+    }
+}
+
+impl NotifyConfig {
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.is_empty() && self.command().is_none() && !self.desktop
+    }
+
+    /// 配置中的命令，没有时使用用户确认过的命令
+    fn command(&self) -> Option<Vec<String>> {
+        self.command.clone().or_else(command)
+    }
+}
+
+/// 读取用户确认过的本地命令，之后的修改都会保存到该文件
+pub fn open(path: &Path) -> Result<()> {
+    let command = match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    *COMMAND.lock().unwrap() = Approved {
+        path: Some(path.to_path_buf()),
+        command,
+    };
+    Ok(())
+}
+
+/// 用户确认过的本地命令
+pub fn command() -> Option<Vec<String>> {
+    COMMAND.lock().unwrap().command.clone()
+}
+
+/// 设置并保存本地命令，需要先由用户确认，为空时移除
+pub fn set_command(command: Option<Vec<String>>) -> Result<()> {
+    if let Some(c) = command.as_ref() {
+        if c.first().map(|p| p.trim().is_empty()).unwrap_or(true) {
+            return Err(anyhow!("命令不能为空"));
+        }
+    }
+    let mut m = COMMAND.lock().unwrap();
+    if let Some(path) = m.path.as_ref() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&command)?)?;
+    }
+    m.command = command;
+    Ok(())
+}
+
+/// 检测结果的变化
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// 站点检测失败或已被移除
+    SiteDown { key: String, name: String },
+    /// 频道的所有地址都不可用
+    ChannelDown { name: String },
+    /// 条目数减少超过阈值
+    Shrunk {
+        kind: SubscriptionKind,
+        previous: usize,
+        current: usize,
+    },
+    /// 检测结果过少，保留了上一版本
+    KeptPrevious { kind: SubscriptionKind },
+}
+
+impl Event {
+    pub fn describe(&self) -> String {
+        let kind_name = |kind: &SubscriptionKind| match kind {
+            SubscriptionKind::Tvbox => "tvbox配置",
+            SubscriptionKind::Playlist => "直播源",
+        };
+        match self {
+            Event::SiteDown { key, name } => format!("站点失效: {}({})", name, key),
+            Event::ChannelDown { name } => format!("频道失效: {}", name),
+            Event::Shrunk {
+                kind,
+                previous,
+                current,
+            } => format!("{}条目减少: {} -> {}", kind_name(kind), previous, current),
+            Event::KeptPrevious { kind } => {
+                format!("{}的检测结果过少，保留上一版本", kind_name(kind))
+            }
+        }
+    }
+}
+
+/// 发送的json摘要
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Summary {
+    /// 生成时间(毫秒时间戳)
+    pub time: i64,
+    pub events: Vec<Event>,
+}
+
+impl Summary {
+    pub fn new(events: Vec<Event>) -> Self {
+        Self {
+            time: chrono::Local::now().timestamp_millis(),
+            events,
+        }
+    }
+
+    /// 系统通知的正文
+    fn text(&self) -> String {
+        let mut lines = self
+            .events
+            .iter()
+            .take(DESKTOP_LINES)
+            .map(|e| e.describe())
+            .collect::<Vec<_>>();
+        if self.events.len() > DESKTOP_LINES {
+            lines.push(format!("等{}项变化", self.events.len()));
+        }
+        lines.join("\n")
+    }
+}
+
+/// 设置显示系统通知的方法，参数为标题与正文
+pub fn set_desktop<F>(show: F)
+where
+    F: Fn(&str, &str) + Send + Sync + 'static,
+{
+    *DESKTOP.lock().unwrap() = Some(Box::new(show));
+}
+
+/// 比较前后两个版本的tvbox配置
+pub fn diff_tvbox(previous: &Source, current: &Source, config: &NotifyConfig) -> Vec<Event> {
+    let keys = current
+        .sites
+        .iter()
+        .map(|s| s.key.as_str())
+        .collect::<HashSet<_>>();
+    let mut events = previous
+        .sites
+        .iter()
+        .filter(|s| !keys.contains(s.key.as_str()))
+        .map(|s| Event::SiteDown {
+            key: s.key.to_string(),
+            name: s.name.to_string(),
+        })
+        .collect::<Vec<_>>();
+    let count =
+        |s: &Source| s.sites.len() + s.lives.len() + s.parses.as_ref().map_or(0, |p| p.len());
+    events.extend(shrunk(
+        SubscriptionKind::Tvbox,
+        count(previous),
+        count(current),
+        config.shrink_percent,
+    ));
+    events
+}
+
+/// 比较前后两个版本的直播源，频道按归一化后的名称比较
+pub fn diff_playlist(
+    previous: &LivePlaylist,
+    current: &LivePlaylist,
+    config: &NotifyConfig,
+) -> Vec<Event> {
+    let normalizer = Normalizer::default();
+    let alive = current
+        .channels
+        .iter()
+        .filter(|c| !c.urls.is_empty())
+        .map(|c| normalizer.key(&c.name))
+        .collect::<HashSet<_>>();
+    let mut reported = HashSet::new();
+    let mut events = vec![];
+    for c in &previous.channels {
+        let key = normalizer.key(&c.name);
+        if !alive.contains(&key) && reported.insert(key) {
+            events.push(Event::ChannelDown {
+                name: c.name.to_string(),
+            });
+        }
+    }
+    events.extend(shrunk(
+        SubscriptionKind::Playlist,
+        previous.channels.len(),
+        current.channels.len(),
+        config.shrink_percent,
+    ));
+    events
+}
+
+fn shrunk(kind: SubscriptionKind, previous: usize, current: usize, percent: f64) -> Option<Event> {
+    if previous == 0 || current >= previous {
+        return None;
+    }
+    let lost = (previous - current) as f64 * 100.0 / previous as f64;
+    if lost > percent {
+        Some(Event::Shrunk {
+            kind,
+            previous,
+            current,
+        })
+    } else {
+        None
+    }
+}
+
+/// 发送通知，返回失败的原因
+pub async fn send(config: &NotifyConfig, summary: &Summary) -> Vec<String> {
+    let mut errors = vec![];
+    let body = match serde_json::to_vec(summary) {
+        Ok(body) => body,
+        Err(e) => return vec![e.to_string()],
+    };
+    for url in &config.webhooks {
+        let res = CLIENT
+            .post(url)
+            .header("content-type", "application/json")
+            .body(body.clone())
+            .send()
+            .await;
+        match res {
+            Ok(resp) if resp.status().is_success() => {}
+            Ok(resp) => errors.push(format!("{}: {}", url, resp.status())),
+            Err(e) => errors.push(format!("{}: {}", url, e)),
+        }
+    }
+    if let Some(command) = config.command() {
+        if let Err(e) = run_command(&command, &body).await {
+            errors.push(format!("{}: {}", command.join(" "), e));
+        }
+    }
+    if config.desktop {
+        if let Some(show) = DESKTOP.lock().unwrap().as_ref() {
+            show("tvbox-provider", &summary.text());
+        }
+    }
+    errors
+}
+
+/// 直接执行命令(不经过shell)，摘要写入标准输入
+async fn run_command(command: &[String], input: &[u8]) -> Result<()> {
+    let (program, args) = command.split_first().ok_or(anyhow!("命令不能为空"))?;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).await?;
+    }
+    let status = tokio::time::timeout(COMMAND_TIMEOUT, child.wait())
+        .await
+        .map_err(|_| anyhow!("执行超时"))??;
+    if !status.success() {
+        return Err(anyhow!("退出码: {:?}", status.code()));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_notify() {
+    use tokio::io::AsyncReadExt;
+    let previous = LivePlaylist::parse("CCTV1,http://a/1\nCCTV-2,http://a/2\nCCTV3,http://a/3\n");
+    let current = LivePlaylist::parse("CCTV1,http://a/1\n");
+    let events = diff_playlist(&previous, &current, &NotifyConfig::default());
+    assert_eq!(
+        events,
+        vec![
+            Event::ChannelDown {
+                name: "CCTV-2".to_string()
+            },
+            Event::ChannelDown {
+                name: "CCTV3".to_string()
+            },
+            Event::Shrunk {
+                kind: SubscriptionKind::Playlist,
+                previous: 3,
+                current: 1
+            },
+        ]
+    );
+    assert!(shrunk(SubscriptionKind::Tvbox, 10, 9, 20.0).is_none());
+
+    // 本地的http服务代替webhook
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = vec![];
+        let mut chunk = [0u8; 1024];
+        loop {
+            let n = socket.read(&mut chunk).await.unwrap();
+            buf.extend_from_slice(&chunk[..n]);
+            let text = String::from_utf8_lossy(&buf).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let len = head
+                    .lines()
+                    .find_map(|l| {
+                        l.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|v| v.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or_default();
+                if body.len() >= len {
+                    socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                        .await
+                        .unwrap();
+                    return body.to_string();
+                }
+            }
+        }
+    });
+    let out = std::env::temp_dir().join(format!("tvbox-notify-{}.json", std::process::id()));
+    let config = NotifyConfig {
+        webhooks: vec![url],
+        command: Some(vec!["tee".to_string(), out.display().to_string()]).filter(|_| cfg!(unix)),
+        ..Default::default()
+    };
+    let summary = Summary::new(events);
+    let errors = send(&config, &summary).await;
+    assert!(errors.is_empty(), "{:?}", errors);
+    let body: Summary = serde_json::from_str(&server.await.unwrap()).unwrap();
+    assert_eq!(body.events.len(), 3);
+    if cfg!(unix) {
+        let written: Summary = serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+        assert_eq!(written.events, summary.events);
+        std::fs::remove_file(&out).ok();
+    }
+    // 命令不经过shell执行
+    assert!(!send(
+        &NotifyConfig {
+            command: Some(vec!["exit 3".to_string()]),
+            ..Default::default()
+        },
+        &summary
+    )
+    .await
+    .is_empty());
+    assert!(serde_json::from_str::<NotifyConfig>(r#"{"command": "cat > /tmp/a"}"#).is_err());
+}
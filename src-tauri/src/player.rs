@@ -0,0 +1,278 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Mutex,
+};
+use tokio::io::AsyncReadExt;
+
+/// 允许交给播放器的地址协议
+const SCHEMES: &[&str] = &[
+    "http", "https", "rtmp", "rtmps", "rtsp", "rtp", "udp", "mms", "srt",
+];
+/// 保留的错误输出长度
+const STDERR_LIMIT: usize = 4096;
+/// 参数模板中的占位符
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{[^{}]*\}").unwrap());
+
+/// 播放器类型
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerKind {
+    Mpv,
+    Vlc,
+    Ffplay,
+    Custom,
+}
+
+/// 播放器配置，来自前端时只能选择播放器类型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerProfile {
+    pub player: PlayerKind,
+}
+
+impl Default for PlayerProfile {
+    fn default() -> Self {
+        Self {
+            player: PlayerKind::Mpv,
+        }
+    }
+}
+
+/// 自定义播放器，只保存在本地，由用户在系统对话框中选择
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomPlayer {
+    /// 播放器可执行文件路径
+    pub path: String,
+    /// 参数模板，其中的{url}会被替换为播放地址，为空时只传入播放地址
+    pub args: Option<Vec<String>>,
+}
+
+/// 自定义播放器以及保存的位置
+static CUSTOM: Lazy<Mutex<(Option<PathBuf>, Option<CustomPlayer>)>> =
+    Lazy::new(|| Mutex::new((None, None)));
+
+/// 读取保存的自定义播放器，之后的修改都会保存到该文件
+pub fn open(path: &Path) -> Result<()> {
+    let player = match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    *CUSTOM.lock().unwrap() = (Some(path.to_path_buf()), player);
+    Ok(())
+}
+
+/// 当前的自定义播放器
+pub fn custom() -> Option<CustomPlayer> {
+    CUSTOM.lock().unwrap().1.clone()
+}
+
+/// 设置并保存自定义播放器，为空时移除
+pub fn set_custom(player: Option<CustomPlayer>) -> Result<()> {
+    if let Some(p) = player.as_ref() {
+        if p.path.trim().is_empty() {
+            return Err(anyhow!("自定义播放器需要指定路径"));
+        }
+        if let Some(args) = p.args.as_ref() {
+            validate_args(args)?;
+        }
+    }
+    let mut m = CUSTOM.lock().unwrap();
+    if let Some(path) = m.0.as_ref() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&player)?)?;
+    }
+    m.1 = player;
+    Ok(())
+}
+
+/// 设置自定义播放器的参数模板，为空时只传入播放地址
+pub fn set_custom_args(args: Option<Vec<String>>) -> Result<()> {
+    let player = custom().ok_or_else(|| anyhow!("请先选择自定义播放器"))?;
+    set_custom(Some(CustomPlayer { args, ..player }))
+}
+
+/// 校验参数模板，只允许{url}占位符且至少包含一个
+pub fn validate_args(args: &[String]) -> Result<()> {
+    for arg in args {
+        if arg.trim().is_empty() {
+            return Err(anyhow!("播放器参数不能为空"));
+        }
+        if let Some(m) = PLACEHOLDER.find_iter(arg).find(|m| m.as_str() != "{url}") {
+            return Err(anyhow!("不支持的占位符: {}", m.as_str()));
+        }
+    }
+    if !args.iter().any(|a| a.contains("{url}")) {
+        return Err(anyhow!("播放器参数中缺少{{url}}"));
+    }
+    Ok(())
+}
+
+impl PlayerProfile {
+    fn program(&self) -> Result<String> {
+        let name = match self.player {
+            PlayerKind::Mpv => "mpv",
+            PlayerKind::Vlc => "vlc",
+            PlayerKind::Ffplay => "ffplay",
+            PlayerKind::Custom => {
+                return custom()
+                    .map(|p| p.path)
+                    .ok_or_else(|| anyhow!("请先选择自定义播放器"))
+            }
+        };
+        Ok(name.to_string())
+    }
+
+    fn default_args(&self) -> Vec<String> {
+        let args: &[&str] = match self.player {
+            PlayerKind::Mpv => &["--force-window=immediate", "{url}"],
+            PlayerKind::Vlc => &["--no-one-instance", "{url}"],
+            PlayerKind::Ffplay => &["-hide_banner", "{url}"],
+            PlayerKind::Custom => &["{url}"],
+        };
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    /// 生成播放器的参数列表，只有自定义播放器使用保存的参数模板
+    pub fn argv(&self, url: &str) -> Result<Vec<String>> {
+        let args = match self.player {
+            PlayerKind::Custom => custom().and_then(|p| p.args),
+            _ => None,
+        }
+        .unwrap_or_else(|| self.default_args());
+        if !args.iter().any(|a| a.contains("{url}")) {
+            return Err(anyhow!("播放器参数中缺少{{url}}"));
+        }
+        Ok(args.iter().map(|a| a.replace("{url}", url)).collect())
+    }
+}
+
+/// 播放结果
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayStatus {
+    /// 播放器进程id
+    pub pid: Option<u32>,
+    pub success: bool,
+    /// 退出码，被信号终止时为空
+    pub code: Option<i32>,
+    pub stderr: String,
+}
+
+/// 校验播放地址，只允许常见的流媒体协议
+pub fn validate_url(url: &str) -> Result<url::Url> {
+    let uri = url::Url::parse(url.trim()).map_err(|_| anyhow!("无效的播放地址"))?;
+    if !SCHEMES.contains(&uri.scheme()) {
+        return Err(anyhow!("不支持的协议: {}", uri.scheme()));
+    }
+    Ok(uri)
+}
+
+/// 直接启动播放器进程(不经过shell)，启动后返回进程id，播放器退出后通过`on_exit`返回结果
+pub fn play<F>(url: &str, profile: &PlayerProfile, on_exit: F) -> Result<Option<u32>>
+where
+    F: FnOnce(PlayStatus) + Send + 'static,
+{
+    let uri = validate_url(url)?;
+    let program = profile.program()?;
+    let argv = profile.argv(uri.as_str())?;
+    let child = tokio::process::Command::new(&program)
+        .args(&argv)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("无法启动播放器 {}: {}", program, e))?;
+    let pid = child.id();
+    tokio::spawn(async move { on_exit(wait(child).await) });
+    Ok(pid)
+}
+
+/// 等待播放器退出
+async fn wait(mut child: tokio::process::Child) -> PlayStatus {
+    let pid = child.id();
+    // 只保留最后的错误输出，避免播放器持续输出时占用过多内存
+    let mut stderr = Vec::with_capacity(STDERR_LIMIT);
+    if let Some(mut err) = child.stderr.take() {
+        let mut buf = [0u8; 1024];
+        while let Ok(n) = err.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+            stderr.extend_from_slice(&buf[..n]);
+            if stderr.len() > STDERR_LIMIT {
+                stderr.drain(..stderr.len() - STDERR_LIMIT);
+            }
+        }
+    }
+    let stderr = String::from_utf8_lossy(&stderr)
+        .trim_start_matches('\u{fffd}')
+        .to_string();
+    match child.wait().await {
+        Ok(status) => PlayStatus {
+            pid,
+            success: status.success(),
+            code: status.code(),
+            stderr,
+        },
+        Err(e) => PlayStatus {
+            pid,
+            success: false,
+            code: None,
+            stderr: e.to_string(),
+        },
+    }
+}
+
+#[test]
+fn test_player_argv() {
+    assert!(validate_url("http://example.com/live.m3u8").is_ok());
+    assert!(validate_url("file:///etc/passwd").is_err());
+    assert!(validate_url("javascript:alert(1)").is_err());
+    assert!(validate_url("--help").is_err());
+
+    // 前端只能选择播放器类型，不能指定路径与参数
+    let profile = r#"{"player": "mpv", "path": "/bin/sh", "args": ["-c", "id"]}"#;
+    assert!(serde_json::from_str::<PlayerProfile>(profile).is_err());
+    let profile: PlayerProfile = serde_json::from_str(r#"{"player": "mpv"}"#).unwrap();
+    assert_eq!(profile.program().unwrap(), "mpv");
+    let argv = profile.argv("http://example.com/1.m3u8").unwrap();
+    assert_eq!(
+        argv,
+        vec!["--force-window=immediate", "http://example.com/1.m3u8"]
+    );
+
+    let profile = PlayerProfile {
+        player: PlayerKind::Custom,
+    };
+    assert!(profile.program().is_err());
+    let player = CustomPlayer {
+        path: "/usr/bin/player".to_string(),
+        args: Some(vec!["--fs".to_string()]),
+    };
+    assert!(set_custom(Some(player.clone())).is_err());
+    assert!(validate_args(&["{url}".to_string(), "{cmd}".to_string()]).is_err());
+    assert!(validate_args(&["".to_string(), "{url}".to_string()]).is_err());
+    let player = CustomPlayer {
+        args: Some(vec!["--title=tv".to_string(), "--url={url}".to_string()]),
+        ..player
+    };
+    set_custom(Some(player)).unwrap();
+    assert_eq!(profile.program().unwrap(), "/usr/bin/player");
+    let argv = profile.argv("http://example.com/a b.m3u8").unwrap();
+    assert_eq!(
+        argv,
+        vec!["--title=tv", "--url=http://example.com/a b.m3u8"]
+    );
+    set_custom_args(None).unwrap();
+    assert_eq!(custom().unwrap().path, "/usr/bin/player");
+    assert_eq!(
+        profile.argv("http://example.com/1.m3u8").unwrap(),
+        vec!["http://example.com/1.m3u8"]
+    );
+}
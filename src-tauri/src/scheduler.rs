@@ -0,0 +1,541 @@
+use crate::{
+    notify::{self, Event, NotifyConfig},
+    server,
+    tvbox::{
+        channel::Normalizer,
+        check,
+        filter::{Filter, FilterRule},
+        job::Job,
+        playlist::LivePlaylist,
+        source::{origin::Origin, writer::WriteOptions, Source},
+        spawn, uptime, urls_stream, Connection, Wrapper,
+    },
+    utils,
+};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration as Span, Local, Timelike};
+use once_cell::sync::Lazy;
+use std::{collections::HashSet, sync::Mutex};
+
+fn default_min_ratio() -> f64 {
+    0.5
+}
+
+fn default_min_items() -> usize {
+    1
+}
+
+static STATE: Lazy<Mutex<State>> = Lazy::new(|| Mutex::new(State::default()));
+/// 同一时间只执行一次刷新
+static RUN: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionKind {
+    Tvbox,
+    Playlist,
+}
+
+/// 订阅的配置或直播源
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub kind: SubscriptionKind,
+    pub url: String,
+    /// 指定内容编码，默认自动检测
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charset: Option<String>,
+}
+
+/// 定时刷新配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    /// cron表达式(分 时 日 月 周), 如: `0 */6 * * *`、`@daily`
+    pub cron: String,
+    pub subscriptions: Vec<Subscription>,
+    #[serde(default)]
+    pub quick: bool,
+    #[serde(default)]
+    pub skip_ipv6: bool,
+    /// 检测直播源时检测m3u8内容是否有效
+    #[serde(default)]
+    pub check_m3u8: bool,
+    /// 合并频道时历史上更稳定的地址排在前面，合并配置时保留更稳定的站点与直播
+    #[serde(default)]
+    pub prefer_stable: bool,
+    /// 直播源输出为m3u格式，默认为txt格式
+    #[serde(default)]
+    pub m3u: bool,
+    /// 新结果的条目数低于上一版本的该比例时保留上一版本
+    #[serde(default = "default_min_ratio")]
+    pub min_ratio: f64,
+    /// 新结果至少包含的条目数
+    #[serde(default = "default_min_items")]
+    pub min_items: usize,
+    /// 内容变化时的通知
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    /// 提供的tvbox配置的输出格式
+    #[serde(default)]
+    pub format: WriteOptions,
+    /// 检测前对合并后的配置与直播源执行的过滤与转换规则
+    #[serde(default)]
+    pub filters: Vec<FilterRule>,
+}
+
+/// 一次刷新的结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunReport {
+    /// 开始时间(毫秒时间戳)
+    pub started: i64,
+    pub finished: i64,
+    /// 检测后的条目数，没有对应的订阅或全部获取失败时为空
+    pub tvbox: Option<usize>,
+    pub playlist: Option<usize>,
+    /// 是否已替换提供的内容，未通过检查时保留上一版本
+    pub tvbox_updated: bool,
+    pub playlist_updated: bool,
+    /// 与上一版本相比的变化
+    pub events: Vec<Event>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduleStatus {
+    pub config: Option<ScheduleConfig>,
+    /// 下一次刷新时间(毫秒时间戳)
+    pub next_run: Option<i64>,
+    pub running: bool,
+    pub last: Option<RunReport>,
+}
+
+#[derive(Default)]
+struct State {
+    status: ScheduleStatus,
+    task: Option<Wrapper<()>>,
+}
+
+/// cron表达式，支持`*`、`*/n`、`a-b`、`a-b/n`以及逗号分隔的列表
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// 日与周都有限制时满足其一即可
+    any_day: bool,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            e => e,
+        };
+        let fields = expr.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(anyhow!("无效的cron表达式: {}", expr));
+        }
+        // 周日可以写作0或7
+        let mut weekdays = field(fields[4], 0, 7)?;
+        weekdays[0] |= weekdays[7];
+        Ok(Self {
+            minutes: field(fields[0], 0, 59)?,
+            hours: field(fields[1], 0, 23)?,
+            days: field(fields[2], 1, 31)?,
+            months: field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] != "*" && fields[4] != "*",
+        })
+    }
+
+    fn matches(&self, t: &DateTime<Local>) -> bool {
+        let day = self.days[t.day() as usize];
+        let weekday = self.weekdays[t.weekday().num_days_from_sunday() as usize];
+        let day = if self.any_day {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        day && self.minutes[t.minute() as usize]
+            && self.hours[t.hour() as usize]
+            && self.months[t.month() as usize]
+    }
+
+    /// `after`之后的下一次执行时间，一年内没有匹配的时间时为空
+    pub fn next(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + Span::minutes(1);
+        let end = after + Span::days(366);
+        while t <= end {
+            if self.matches(&t) {
+                return Some(t);
+            }
+            t += Span::minutes(1);
+        }
+        None
+    }
+}
+
+fn field(expr: &str, min: u32, max: u32) -> Result<Vec<bool>> {
+    let invalid = || anyhow!("无效的cron字段: {}", expr);
+    let mut set = vec![false; max as usize + 1];
+    for part in expr.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (r, s.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((a, b)) => (
+                a.parse().map_err(|_| invalid())?,
+                b.parse().map_err(|_| invalid())?,
+            ),
+            None => {
+                let v = range.parse().map_err(|_| invalid())?;
+                // `5/15`表示从5开始每15
+                (v, if part.contains('/') { max } else { v })
+            }
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for v in (start..=end).step_by(step as usize) {
+            set[v as usize] = true;
+        }
+    }
+    Ok(set)
+}
+
+/// 设置定时刷新，`config`为空时停止
+pub fn start(config: Option<ScheduleConfig>) -> Result<ScheduleStatus> {
+    let cron = config.as_ref().map(|c| Cron::parse(&c.cron)).transpose()?;
+    if let Some(config) = config.as_ref() {
+        Filter::new(config.filters.clone())?;
+    }
+    let mut state = STATE.lock().unwrap();
+    state.task = None;
+    state.status.next_run = None;
+    state.status.config = config.clone();
+    if let (Some(config), Some(cron)) = (config, cron) {
+        state.task = Some(spawn(schedule_loop(config, cron)));
+    }
+    Ok(state.status.clone())
+}
+
+pub fn status() -> ScheduleStatus {
+    STATE.lock().unwrap().status.clone()
+}
+
+/// 使用当前配置立即刷新一次
+pub async fn run_now() -> Result<RunReport> {
+    let config = status().config.ok_or(anyhow!("未设置定时刷新"))?;
+    Ok(run(&config).await)
+}
+
+async fn schedule_loop(config: ScheduleConfig, cron: Cron) {
+    while let Some(next) = cron.next(Local::now()) {
+        STATE.lock().unwrap().status.next_run = Some(next.timestamp_millis());
+        let wait = (next - Local::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+        run(&config).await;
+    }
+    STATE.lock().unwrap().status.next_run = None;
+}
+
+/// 执行一次刷新，正在刷新时等待其完成，可以通过任务id取消
+pub async fn run(config: &ScheduleConfig) -> RunReport {
+    let _guard = RUN.lock().await;
+    STATE.lock().unwrap().status.running = true;
+    let started = Local::now().timestamp_millis();
+    let job = Job::new("schedule", None);
+    let mut report = job.run(refresh(config)).await.unwrap_or_else(|| RunReport {
+        errors: vec!["刷新已取消".to_string()],
+        ..Default::default()
+    });
+    report.started = started;
+    report.finished = Local::now().timestamp_millis();
+    println!(
+        "schedule: {}",
+        serde_json::to_string(&report).unwrap_or_default()
+    );
+    let mut state = STATE.lock().unwrap();
+    state.status.running = false;
+    state.status.last = Some(report.clone());
+    report
+}
+
+/// 重新获取、检测并合并订阅的内容，通过检查后同时替换提供的配置与直播源
+async fn refresh(config: &ScheduleConfig) -> RunReport {
+    let mut report = RunReport::default();
+    let subs = |kind| {
+        config
+            .subscriptions
+            .iter()
+            .filter(move |s| s.kind == kind)
+            .collect::<Vec<_>>()
+    };
+    let filter = Filter::new(config.filters.clone()).unwrap_or_else(|e| {
+        report.errors.push(e.to_string());
+        Filter::default()
+    });
+    let tvbox = refresh_tvbox(
+        &subs(SubscriptionKind::Tvbox),
+        config,
+        &filter,
+        &mut report.errors,
+    )
+    .await;
+    let playlist = refresh_playlist(
+        &subs(SubscriptionKind::Playlist),
+        config,
+        &filter,
+        &mut report.errors,
+    )
+    .await;
+    report.tvbox = tvbox.as_ref().map(source_count);
+    report.playlist = playlist.as_ref().map(|p| p.channels.len());
+
+    let previous = Source::parse(&server::cached("tvbox").await, '#').unwrap_or_default();
+    if let Some(source) = tvbox.as_ref() {
+        report
+            .events
+            .extend(notify::diff_tvbox(&previous, source, &config.notify));
+    }
+    let tvbox = tvbox.filter(|s| sane(source_count(s), source_count(&previous), config));
+    let previous = LivePlaylist::parse(&server::cached("playlist").await);
+    if let Some(playlist) = playlist.as_ref() {
+        report
+            .events
+            .extend(notify::diff_playlist(&previous, playlist, &config.notify));
+    }
+    let playlist = playlist.filter(|p| sane(p.channels.len(), previous.channels.len(), config));
+    report.tvbox_updated = tvbox.is_some();
+    report.playlist_updated = playlist.is_some();
+    if report.tvbox.is_some() && !report.tvbox_updated {
+        report.events.push(Event::KeptPrevious {
+            kind: SubscriptionKind::Tvbox,
+        });
+    }
+    if report.playlist.is_some() && !report.playlist_updated {
+        report.events.push(Event::KeptPrevious {
+            kind: SubscriptionKind::Playlist,
+        });
+    }
+    let tvbox = tvbox.and_then(|s| {
+        s.to_string_with(&config.format)
+            .map_err(|e| report.errors.push(e.to_string()))
+            .ok()
+    });
+    let playlist = playlist.map(|p| if config.m3u { p.to_m3u() } else { p.to_txt() });
+    server::swap(tvbox, playlist).await;
+    if !report.events.is_empty() && !config.notify.is_empty() {
+        let summary = notify::Summary::new(report.events.clone());
+        report
+            .errors
+            .extend(notify::send(&config.notify, &summary).await);
+    }
+    report
+}
+
+/// 新结果是否可以替换上一版本
+fn sane(count: usize, previous: usize, config: &ScheduleConfig) -> bool {
+    count >= config.min_items.max(1) && count as f64 >= previous as f64 * config.min_ratio
+}
+
+fn source_count(source: &Source) -> usize {
+    source.sites.len() + source.lives.len() + source.parses.as_ref().map_or(0, |p| p.len())
+}
+
+async fn refresh_tvbox(
+    subs: &[&Subscription],
+    config: &ScheduleConfig,
+    filter: &Filter,
+    errors: &mut Vec<String>,
+) -> Option<Source> {
+    let mut sources = vec![];
+    for sub in subs {
+        match load_source(sub).await {
+            Ok(s) => sources.push(s),
+            Err(e) => errors.push(format!("{}: {}", sub.url, e)),
+        }
+    }
+    if sources.is_empty() {
+        return None;
+    }
+    let mut source = Source::merge(sources);
+    if config.prefer_stable {
+        source.sort_by_stability().await;
+    }
+    filter.apply_source(&mut source);
+    source.sites = check_all(source.sites, config).await;
+    source.lives = check_all(source.lives, config).await;
+    if let Some(parses) = source.parses.take() {
+        source.parses = Some(check_all(parses, config).await);
+    }
+    Some(source)
+}
+
+async fn load_source(sub: &Subscription) -> Result<Source> {
+    let content = utils::read_content_with(&sub.url, sub.charset.as_deref()).await?;
+    let mut source = Source::parse(&content, '#')?;
+    source.tag(&Origin::new(&sub.url, &content));
+    let base = if utils::is_http_url(&sub.url) {
+        Some(sub.url.to_string())
+    } else {
+        utils::base_uri(&sub.url)
+    };
+    if let Some(base) = base {
+        source.base(&base)?;
+    }
+    server::allow_local_files(&sub.url, &serde_json::to_string(&source)?);
+    Ok(source)
+}
+
+/// 检测并保留可用的条目，顺序不变
+async fn check_all<T>(items: Vec<T>, config: &ScheduleConfig) -> Vec<T>
+where
+    T: Connection + Clone + Send + Sync + 'static,
+{
+    let threads = check::threads(items.len(), num_cpus::get() / 2);
+    let mut stream = check::check_stream(items, threads, config.quick, config.skip_ipv6);
+    let mut alive = vec![];
+    while let Some((index, status)) = stream.next().await {
+        if status.connectable {
+            alive.push((index, status.extra));
+        }
+    }
+    uptime::save().await;
+    alive.sort_by_key(|(index, _)| *index);
+    alive.into_iter().map(|(_, item)| item).collect()
+}
+
+async fn refresh_playlist(
+    subs: &[&Subscription],
+    config: &ScheduleConfig,
+    filter: &Filter,
+    errors: &mut Vec<String>,
+) -> Option<LivePlaylist> {
+    let mut playlist = LivePlaylist::default();
+    let mut loaded = false;
+    for sub in subs {
+        match utils::read_content_with(&sub.url, sub.charset.as_deref()).await {
+            Ok(content) => {
+                server::allow_local_files(&sub.url, &content);
+                let p = LivePlaylist::parse(&content);
+                for epg in p.epg {
+                    if !playlist.epg.contains(&epg) {
+                        playlist.epg.push(epg);
+                    }
+                }
+                playlist.channels.extend(p.channels);
+                loaded = true;
+            }
+            Err(e) => errors.push(format!("{}: {}", sub.url, e)),
+        }
+    }
+    if !loaded {
+        return None;
+    }
+    filter.apply_playlist(&mut playlist);
+    let mut urls = vec![];
+    let mut seen = HashSet::new();
+    for u in playlist.channels.iter().flat_map(|c| c.urls.iter()) {
+        if seen.insert(u) {
+            urls.push(u.to_string());
+        }
+    }
+    let mut stream = urls_stream(
+        urls,
+        config.quick,
+        config.skip_ipv6,
+        config.check_m3u8,
+        None,
+    );
+    let mut alive = HashSet::new();
+    while let Some((_, status)) = stream.next().await {
+        if status.connectable {
+            alive.insert(status.extra.url);
+        }
+    }
+    uptime::save().await;
+    for c in playlist.channels.iter_mut() {
+        c.urls.retain(|u| alive.contains(u));
+    }
+    playlist.channels.retain(|c| !c.urls.is_empty());
+    let normalizer = Normalizer::default();
+    if config.prefer_stable {
+        playlist.dedup_stable(&normalizer).await;
+    } else {
+        playlist.dedup(&normalizer);
+    }
+    Some(playlist)
+}
+
+#[test]
+fn test_cron() {
+    use chrono::TimeZone;
+    let at = |y, m, d, h, min| Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap();
+    let cron = Cron::parse("0 */6 * * *").unwrap();
+    assert_eq!(cron.next(at(2024, 1, 1, 0, 0)), Some(at(2024, 1, 1, 6, 0)));
+    assert_eq!(
+        cron.next(at(2024, 1, 1, 23, 30)),
+        Some(at(2024, 1, 2, 0, 0))
+    );
+    let cron = Cron::parse("30 3 * * 1-5").unwrap();
+    // 2024-01-06 为周六
+    assert_eq!(cron.next(at(2024, 1, 6, 0, 0)), Some(at(2024, 1, 8, 3, 30)));
+    let cron = Cron::parse("0 0 1 * 7").unwrap();
+    // 日与周满足其一即可: 2024-01-07为周日
+    assert_eq!(cron.next(at(2024, 1, 2, 0, 0)), Some(at(2024, 1, 7, 0, 0)));
+    let cron = Cron::parse("5,10/20 * * * *").unwrap();
+    assert_eq!(cron.next(at(2024, 1, 1, 0, 6)), Some(at(2024, 1, 1, 0, 10)));
+    assert_eq!(
+        cron.next(at(2024, 1, 1, 0, 10)),
+        Some(at(2024, 1, 1, 0, 30))
+    );
+    assert_eq!(
+        Cron::parse("@daily").unwrap(),
+        Cron::parse("0 0 * * *").unwrap()
+    );
+    assert!(Cron::parse("60 * * * *").is_err());
+    assert!(Cron::parse("* * *").is_err());
+    assert!(Cron::parse("*/0 * * * *").is_err());
+    assert!(Cron::parse("0 0 31 2 *")
+        .unwrap()
+        .next(at(2024, 1, 1, 0, 0))
+        .is_none());
+}
+
+#[test]
+fn test_merge_sources() {
+    let a = Source::parse(
+        r#"{"spider":"a.jar","sites":[{"key":"a","name":"a","type":3,"api":"csp_A"}],
+        "lives":[],"parses":[{"name":"p","type":1,"url":"http://p/"}],"ads":["ad.a"]}"#,
+        '#',
+    )
+    .unwrap();
+    let b = Source::parse(
+        r#"{"spider":"b.jar","sites":[{"key":"a","name":"a","type":3,"api":"csp_A"},
+        {"key":"b","name":"b","type":3,"api":"csp_B"}],"lives":[],
+        "parses":[{"name":"p","type":1,"url":"http://p/"}],"ads":["ad.a","ad.b"]}"#,
+        '#',
+    )
+    .unwrap();
+    let merged = Source::merge(vec![a, b]);
+    assert_eq!(merged.sites.len(), 2);
+    assert_eq!(merged.sites[0].jar, None);
+    assert_eq!(merged.sites[1].jar.as_deref(), Some("b.jar"));
+    assert_eq!(merged.parses.unwrap().len(), 1);
+    assert_eq!(merged.ads.unwrap(), vec!["ad.a", "ad.b"]);
+    assert_eq!(source_count(&Source::default()), 0);
+
+    let config: ScheduleConfig =
+        serde_json::from_str(r#"{"cron":"@hourly","subscriptions":[]}"#).unwrap();
+    assert!(sane(10, 0, &config));
+    assert!(sane(5, 10, &config));
+    assert!(!sane(4, 10, &config));
+    assert!(!sane(0, 0, &config));
+}
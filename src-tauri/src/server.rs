@@ -1,42 +1,267 @@
-use axum::{response::IntoResponse, routing::get, Router};
+use crate::tvbox::{
+    epg::{self, Guide},
+    hls,
+    playlist::LivePlaylist,
+    source::rule::Rule,
+};
+use axum::{
+    body::Body,
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
 use once_cell::sync::Lazy;
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    path::PathBuf,
+    time::Duration,
+};
 use tokio::sync::Mutex;
+use url::Url;
 
 pub struct Cache {
     pub tvbox: String,
     pub playlist: String,
+    /// tvbox配置中的广告域名，用于中转时过滤广告
+    pub ads: Vec<String>,
+    /// tvbox配置中的广告规则
+    pub rules: Vec<Rule>,
+    /// tvbox配置中直播的节目单(XMLTV)地址
+    pub epg: Vec<String>,
+    /// 配置与直播源中引用的、已登记的本地文件，键为file://地址的hash
+    pub files: HashMap<String, PathBuf>,
 }
 
 impl Cache {
     pub fn update(&mut self, key: &str, value: String) {
         let key = key.to_lowercase();
         if key == "tvbox" {
+            let source = crate::tvbox::source::Source::parse(&value, '#').ok();
+            self.ads = source
+                .as_ref()
+                .and_then(|s| s.ads.clone())
+                .unwrap_or_default();
+            self.epg = source
+                .as_ref()
+                .map(|s| {
+                    s.lives
+                        .iter()
+                        .filter_map(|l| l.epg.clone())
+                        .filter(|u| epg::is_xmltv_url(u))
+                        .collect()
+                })
+                .unwrap_or_default();
+            self.rules = source.and_then(|s| s.rules).unwrap_or_default();
             self.tvbox = value
         } else if key == "playlist" {
             self.playlist = value;
         }
+        let allowed = LOCAL_FILES.lock().unwrap();
+        self.files = FILE_URL
+            .find_iter(&self.tvbox)
+            .chain(FILE_URL.find_iter(&self.playlist))
+            .filter(|m| allowed.contains(m.as_str()))
+            .filter_map(|m| {
+                let path = crate::utils::local_path(m.as_str())?;
+                Some((file_id(m.as_str()), path))
+            })
+            .collect();
+    }
+
+    /// 节目单地址，来自直播源的x-tvg-url与tvbox直播的epg
+    pub fn epg_urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = vec![];
+        for u in LivePlaylist::parse(&self.playlist)
+            .epg
+            .into_iter()
+            .chain(self.epg.iter().cloned())
+        {
+            if !urls.contains(&u) {
+                urls.push(u);
+            }
+        }
+        urls
+    }
+
+    /// 将内容中的本地文件地址替换为局域网可访问的地址
+    pub fn localize(&self, content: &str, origin: &str) -> String {
+        FILE_URL
+            .replace_all(content, |c: &regex::Captures| {
+                let uri = &c[0];
+                let id = file_id(uri);
+                if !self.files.contains_key(&id) {
+                    return uri.to_string();
+                }
+                let name = uri.rsplit('/').next().unwrap_or_default();
+                format!("{}/file/{}/{}", origin, id, name)
+            })
+            .to_string()
+    }
+}
+
+/// 本地文件地址，不包含tvbox配置中spider的;md5;等附加信息
+static FILE_URL: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r#"file:///[^\s"'<>;,]+"#).unwrap());
+
+fn file_id(uri: &str) -> String {
+    crate::utils::hash(uri.as_bytes())
+}
+
+/// 本地配置中引用的本地文件(file://地址)，只有这些文件会通过局域网提供
+static LOCAL_FILES: Lazy<std::sync::Mutex<HashSet<String>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashSet::new()));
+
+/// 登记从本地加载的配置`uri`中引用的本地文件，只登记配置所在目录内的文件
+/// 远程配置中的本地文件地址不会被登记，也就不会被提供
+pub fn allow_local_files(uri: &str, content: &str) {
+    let canonical = |p: PathBuf| p.canonicalize().ok();
+    let dir = crate::utils::local_path(uri)
+        .and_then(canonical)
+        .and_then(|p| p.parent().map(|d| d.to_path_buf()));
+    let Some(dir) = dir else {
+        return;
+    };
+    let mut files = LOCAL_FILES.lock().unwrap();
+    for m in FILE_URL.find_iter(content) {
+        let inside = crate::utils::local_path(m.as_str())
+            .and_then(canonical)
+            .map(|p| p.starts_with(&dir))
+            .unwrap_or_default();
+        if inside {
+            files.insert(m.as_str().to_string());
+        }
+    }
+}
+
+/// 请求中的服务地址, 如: http://192.168.1.2:8090
+fn origin(headers: &HeaderMap) -> String {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("127.0.0.1:8090");
+    format!("http://{}", host)
+}
+
+/// 当前提供的内容
+pub async fn cached(key: &str) -> String {
+    let cache = CACHE.lock().await;
+    match key.to_lowercase().as_str() {
+        "tvbox" => cache.tvbox.to_string(),
+        "playlist" => cache.playlist.to_string(),
+        _ => String::default(),
+    }
+}
+
+/// 同时替换tvbox配置与直播源，请求不会读到只更新了一部分的内容，为空时保持不变
+pub async fn swap(tvbox: Option<String>, playlist: Option<String>) {
+    if tvbox.is_none() && playlist.is_none() {
+        return;
+    }
+    let mut m = CACHE.lock().await;
+    let before = m.epg_urls();
+    if let Some(tvbox) = tvbox {
+        m.update("tvbox", tvbox);
+    }
+    if let Some(playlist) = playlist {
+        m.update("playlist", playlist);
+    }
+    let changed = m.epg_urls() != before;
+    drop(m);
+    if changed {
+        tokio::spawn(refresh_epg(vec![], false));
     }
 }
 
 pub async fn updata_cache(key: &str, value: String) {
     let mut m = CACHE.lock().await;
+    let before = m.epg_urls();
     m.update(key, value);
+    let changed = m.epg_urls() != before;
+    drop(m);
+    // 节目单地址变化时才重新加载
+    if changed {
+        tokio::spawn(refresh_epg(vec![], false));
+    }
+}
+
+/// 重新加载节目单，地址来自直播源的x-tvg-url、tvbox直播的epg以及`urls`
+/// 地址未变化且`force`为false时不会重新下载
+pub async fn refresh_epg(urls: Vec<String>, force: bool) -> usize {
+    let mut all = urls;
+    all.extend(CACHE.lock().await.epg_urls());
+    let mut urls: Vec<String> = vec![];
+    for u in all {
+        if !urls.contains(&u) {
+            urls.push(u);
+        }
+    }
+    if !force && EPG.lock().await.sources == urls {
+        return EPG.lock().await.channels.len();
+    }
+    let guide = Guide::load(&urls).await;
+    let count = guide.channels.len();
+    let mut m = EPG.lock().await;
+    *m = guide;
+    m.sources = urls;
+    count
 }
 
 static CACHE: Lazy<Mutex<Cache>> = Lazy::new(|| {
     Mutex::new(Cache {
         tvbox: String::default(),
         playlist: String::default(),
+        ads: vec![],
+        rules: vec![],
+        epg: vec![],
+        files: HashMap::new(),
     })
 });
 
+static EPG: Lazy<Mutex<Guide>> = Lazy::new(|| Mutex::new(Guide::default()));
+
+/// 中转请求时按来源注入的请求头, 如: Referer, User-Agent
+static RELAY_HEADERS: Lazy<Mutex<HashMap<String, HashMap<String, String>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static RELAY_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::ClientBuilder::new()
+        .user_agent(
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:109.0) Gecko/20100101 Firefox/112.0",
+        )
+        .connect_timeout(Duration::from_secs_f32(6.0))
+        // 重定向在中转时逐个检查目标地址
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+});
+
+/// 中转允许的最大重定向次数
+const RELAY_REDIRECT_LIMIT: usize = 10;
+
+/// 设置来源的中转请求头，headers为空时移除该来源
+pub async fn set_relay_headers(src: &str, headers: HashMap<String, String>) {
+    let mut m = RELAY_HEADERS.lock().await;
+    if headers.is_empty() {
+        m.remove(src);
+    } else {
+        m.insert(src.to_string(), headers);
+    }
+}
+
 pub async fn run() {
     let app = Router::new()
         .route("/", get(root))
         .route("/playlist.txt", get(playlist_txt))
         .route("/playlist.m3u", get(playlist_m3u))
         .route("/playlist.m3u8", get(playlist_m3u))
-        .route("/tvbox.json", get(tvbox_json));
+        .route("/tvbox.json", get(tvbox_json))
+        .route("/relay", get(relay))
+        .route("/epg.xml", get(epg_xml))
+        .route("/epg", get(epg_json))
+        .route("/file/:id/:name", get(local_file));
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8090").await.unwrap();
@@ -48,7 +273,10 @@ async fn root() -> impl IntoResponse {
     <a target=_blank href=/playlist.txt>playlist.txt</a><br>
     <a target=_blank href=/playlist.m3u>playlist.m3u</a><br>
     <a target=_blank href=/playlist.m3u8>playlist.m3u8</a><br>
-    <a target=_blank href=/tvbox.json>tvbox.json</a>
+    <a target=_blank href=/tvbox.json>tvbox.json</a><br>
+    <a target=_blank href=/epg.xml>epg.xml</a><br>
+    <span>/epg?ch=[频道名称]&date=[yyyy-MM-dd]</span><br>
+    <span>/relay?url=[m3u8地址]&src=[来源]</span>
     </ul>
     "#
     .to_string();
@@ -59,22 +287,310 @@ async fn root() -> impl IntoResponse {
 }
 
 /// 文本格式的直播源
-async fn playlist_txt() -> impl IntoResponse {
-    CACHE.lock().await.playlist.clone()
+async fn playlist_txt(headers: HeaderMap) -> impl IntoResponse {
+    let cache = CACHE.lock().await;
+    cache.localize(&cache.playlist, &origin(&headers))
 }
 /// m3u格式的直播源
-async fn playlist_m3u() -> impl IntoResponse {
-    let content = CACHE.lock().await.playlist.clone();
+async fn playlist_m3u(headers: HeaderMap) -> impl IntoResponse {
+    let content = {
+        let cache = CACHE.lock().await;
+        cache.localize(&cache.playlist, &origin(&headers))
+    };
     let mut resp = axum::response::Response::new(content);
     resp.headers_mut()
         .insert("content-type", "application/x-mpegURL".parse().unwrap());
     resp
 }
 /// tvbox 配置信息
-async fn tvbox_json() -> impl IntoResponse {
-    let content = CACHE.lock().await.tvbox.clone();
+async fn tvbox_json(headers: HeaderMap) -> impl IntoResponse {
+    let content = {
+        let cache = CACHE.lock().await;
+        cache.localize(&cache.tvbox, &origin(&headers))
+    };
     let mut resp = axum::response::Response::new(content);
     resp.headers_mut()
         .insert("content-type", "application/json".parse().unwrap());
     resp
 }
+
+/// 只包含当前直播源频道的XMLTV节目单
+async fn epg_xml() -> impl IntoResponse {
+    let channels = LivePlaylist::parse(&CACHE.lock().await.playlist).channels;
+    let content = EPG.lock().await.to_xmltv(&channels);
+    let mut resp = axum::response::Response::new(content);
+    resp.headers_mut()
+        .insert("content-type", "application/xml".parse().unwrap());
+    resp
+}
+
+/// 配置中引用的本地文件，只允许访问已登记的文件
+async fn local_file(Path((id, _name)): Path<(String, String)>) -> Response {
+    let Some(path) = CACHE.lock().await.files.get(&id).cloned() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let content_type = match path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("m3u") | Some("m3u8") => "application/x-mpegURL",
+        Some("js") => "application/javascript",
+        Some("xml") => "application/xml",
+        _ => "application/octet-stream",
+    };
+    match tokio::fs::read(&path).await {
+        Ok(content) => ([(header::CONTENT_TYPE, content_type)], content).into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EpgQuery {
+    /// 频道名称或id
+    pub ch: String,
+    /// yyyy-MM-dd，默认为当天
+    pub date: Option<String>,
+}
+
+/// DIYP格式的节目单
+async fn epg_json(Query(query): Query<EpgQuery>) -> impl IntoResponse {
+    let date = query
+        .date
+        .unwrap_or_else(|| chrono::Local::now().format("%Y-%m-%d").to_string());
+    let epg = EPG.lock().await.diyp(&query.ch, &date);
+    axum::Json(epg)
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct RelayQuery {
+    /// 上游地址
+    pub url: String,
+    /// 来源名称，用于查找需要注入的请求头
+    pub src: Option<String>,
+    pub referer: Option<String>,
+    pub ua: Option<String>,
+}
+
+impl RelayQuery {
+    /// 生成经过中转的地址，非http(s)地址保持不变
+    pub fn relay_uri(&self, uri: &str) -> String {
+        if !crate::utils::is_http_url(uri) {
+            return uri.to_string();
+        }
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("url", uri);
+        if let Some(src) = self.src.as_ref() {
+            query.append_pair("src", src);
+        }
+        if let Some(referer) = self.referer.as_ref() {
+            query.append_pair("referer", referer);
+        }
+        if let Some(ua) = self.ua.as_ref() {
+            query.append_pair("ua", ua);
+        }
+        format!("/relay?{}", query.finish())
+    }
+}
+
+/// HLS中转：m3u8中的地址会被重写为经过本服务的地址并按配置过滤广告分片，其他内容直接以流的方式转发
+async fn relay(Query(query): Query<RelayQuery>, headers: HeaderMap) -> Response {
+    match relay_upstream(query, headers).await {
+        Ok(resp) => resp,
+        Err(e) => {
+            println!("relay.error: {:?}", e);
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+    }
+}
+
+async fn relay_upstream(query: RelayQuery, headers: HeaderMap) -> anyhow::Result<Response> {
+    let mut upstream = Url::parse(&query.url)?;
+    let relay_headers = match query.src.as_ref() {
+        Some(src) => RELAY_HEADERS.lock().await.get(src).cloned(),
+        None => None,
+    };
+    let mut redirects = 0;
+    let resp = loop {
+        ensure_public(&upstream).await?;
+        let mut req = RELAY_CLIENT.get(upstream.clone());
+        if let Some(h) = relay_headers.as_ref() {
+            for (k, v) in h {
+                req = req.header(k.as_str(), v.as_str());
+            }
+        }
+        if let Some(referer) = query.referer.as_ref() {
+            req = req.header("referer", referer.as_str());
+        }
+        if let Some(ua) = query.ua.as_ref() {
+            req = req.header("user-agent", ua.as_str());
+        }
+        if let Some(range) = headers.get(header::RANGE) {
+            req = req.header("range", range.as_bytes());
+        }
+        let resp = req.send().await?;
+        let location = resp.headers().get("location").and_then(|v| v.to_str().ok());
+        match location {
+            Some(location) if resp.status().is_redirection() => {
+                redirects += 1;
+                if redirects > RELAY_REDIRECT_LIMIT {
+                    return Err(anyhow!("重定向次数过多"));
+                }
+                upstream = upstream.join(location)?;
+            }
+            _ => break resp,
+        }
+    };
+    let status = StatusCode::from_u16(resp.status().as_u16())?;
+    let upstream_type = resp.headers().get("content-type").cloned();
+    let content_type = upstream_type
+        .as_ref()
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+    let path = resp.url().path().to_lowercase();
+    let maybe_playlist = content_type.contains("mpegurl")
+        || path.ends_with(".m3u8")
+        || path.ends_with(".m3u");
+    if status.is_success() && maybe_playlist {
+        let base = resp.url().clone();
+        let content = resp.text().await?;
+        if hls::is_playlist(&content) {
+            let filter = {
+                let cache = CACHE.lock().await;
+                hls::AdFilter::new(&cache.ads, &cache.rules, base.as_str())
+            };
+            let content = hls::strip_ads(&content, &base, &filter);
+            let content = hls::rewrite_uris(&content, &base, |uri| query.relay_uri(uri));
+            return Ok((
+                [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+                content,
+            )
+                .into_response());
+        }
+        // 不是播放列表时保留上游的内容类型
+        let mut builder = Response::builder().status(status);
+        if let Some(value) = upstream_type {
+            builder = builder.header(header::CONTENT_TYPE, value.as_bytes());
+        }
+        return Ok(builder.body(Body::from(content))?);
+    }
+    let mut builder = Response::builder().status(status);
+    for name in ["content-type", "content-length", "content-range", "accept-ranges"] {
+        if let Some(value) = resp.headers().get(name) {
+            builder = builder.header(name, value.as_bytes());
+        }
+    }
+    let resp = builder.body(Body::from_stream(resp.bytes_stream()))?;
+    Ok(resp)
+}
+
+/// 中转服务监听所有网卡且不需要认证，只允许访问公网地址，避免被用来访问本机或局域网服务
+async fn ensure_public(url: &Url) -> anyhow::Result<()> {
+    if !crate::utils::is_http_url(url.as_str()) {
+        return Err(anyhow!("仅支持http(s)地址"));
+    }
+    let ips = match url.host() {
+        Some(url::Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+        Some(url::Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+        Some(url::Host::Domain(domain)) => {
+            let port = url.port_or_known_default().unwrap_or(80);
+            tokio::net::lookup_host((domain, port))
+                .await?
+                .map(|addr| addr.ip())
+                .collect()
+        }
+        None => vec![],
+    };
+    if ips.is_empty() || !ips.iter().all(is_public_ip) {
+        return Err(anyhow!("不允许中转到本机或内网地址"));
+    }
+    Ok(())
+}
+
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                // 100.64.0.0/10 运营商级NAT
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // fc00::/7 唯一本地地址
+                || (first & 0xfe00) == 0xfc00
+                // fe80::/10 链路本地地址
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_relay_target() {
+    for url in [
+        "http://127.0.0.1:8090/relay",
+        "http://localhost/",
+        "http://10.0.0.1/a.m3u8",
+        "http://192.168.1.1/",
+        "http://169.254.169.254/latest/meta-data",
+        "http://[::1]/",
+        "http://[::ffff:127.0.0.1]/",
+        "http://[fe80::1]/",
+        "file:///etc/passwd",
+    ] {
+        let url = Url::parse(url).unwrap();
+        assert!(ensure_public(&url).await.is_err(), "{}", url);
+    }
+    assert!(ensure_public(&Url::parse("http://1.1.1.1/a.m3u8").unwrap())
+        .await
+        .is_ok());
+}
+
+#[test]
+fn test_local_files() {
+    let dir = std::env::temp_dir().join("tvbox-provider-serve");
+    std::fs::create_dir_all(dir.join("jar")).unwrap();
+    let config = dir.join("tv.json");
+    let jar = dir.join("jar").join("a.jar");
+    std::fs::write(&config, "{}").unwrap();
+    std::fs::write(&jar, "jar").unwrap();
+    let jar = Url::from_file_path(jar.canonicalize().unwrap()).unwrap();
+    let outside = std::env::temp_dir().join("tvbox-provider-outside.txt");
+    std::fs::write(&outside, "secret").unwrap();
+    let outside = Url::from_file_path(outside.canonicalize().unwrap()).unwrap();
+    let content = format!(r#"{{"spider": "{}", "wallpaper": "{}"}}"#, jar, outside);
+    let mut cache = Cache {
+        tvbox: String::default(),
+        playlist: String::default(),
+        ads: vec![],
+        rules: vec![],
+        epg: vec![],
+        files: HashMap::new(),
+    };
+    // 远程配置中的本地文件不会被提供
+    allow_local_files("http://a.com/tv.json", &content);
+    cache.update("tvbox", content.to_string());
+    assert!(cache.files.is_empty());
+
+    // 本地配置只能引用配置所在目录内的文件
+    allow_local_files(config.to_str().unwrap(), &content);
+    cache.update("tvbox", content.to_string());
+    assert_eq!(cache.files.len(), 1);
+    assert!(cache.files.contains_key(&file_id(jar.as_str())));
+
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use std::{
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+use tauri::{Manager, Runtime};
+use tokio::io::AsyncWriteExt;
+
+/// 默认的下载大小上限：256MB
+const MAX_DOWNLOAD_SIZE: u64 = 256 * 1024 * 1024;
+/// 默认的下载超时时间(秒)
+const DOWNLOAD_TIMEOUT: u64 = 300;
+
+/// 检查写入路径是否被允许：应用数据目录，或通过对话框选择的路径
+pub fn allowed_path<R: Runtime, M: Manager<R>>(manager: &M, path: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(path);
+    if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+        return Err(anyhow!("无效路径: {}", path.display()));
+    }
+    if manager.fs_scope().is_allowed(&path) {
+        return Ok(path);
+    }
+    let data = manager.app_handle().path_resolver().app_data_dir();
+    if let Some(data) = data {
+        if path.starts_with(&data) {
+            std::fs::create_dir_all(path.parent().unwrap_or(&data))?;
+            return Ok(path);
+        }
+    }
+    Err(anyhow!("不允许写入: {}", path.display()))
+}
+
+/// 写入文件，先写入临时文件再重命名
+pub async fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp = temp_path(path);
+    let res = async {
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        file.write_all(content).await?;
+        file.sync_all().await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    finish(&tmp, path, res).await
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.part", name))
+}
+
+async fn finish<T>(tmp: &Path, path: &Path, res: Result<T>) -> Result<T> {
+    match res {
+        Ok(v) => {
+            tokio::fs::rename(tmp, path).await?;
+            Ok(v)
+        }
+        Err(e) => {
+            tokio::fs::remove_file(tmp).await.ok();
+            Err(e)
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct DownloadOptions {
+    /// 文件大小上限(字节)
+    pub max_size: Option<u64>,
+    /// 期望的文件hash，格式为 算法:hash，支持xxh3与md5，不带算法时为xxh3
+    pub hash: Option<String>,
+    /// 超时时间(秒)
+    pub timeout: Option<u64>,
+}
+
+enum Hasher {
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+    Md5(md5::Md5),
+}
+
+impl Hasher {
+    fn new(algorithm: &str) -> Result<Self> {
+        use md5::Digest;
+        match algorithm.to_lowercase().as_str() {
+            "xxh3" => Ok(Self::Xxh3(Box::new(xxhash_rust::xxh3::Xxh3::with_seed(42)))),
+            "md5" => Ok(Self::Md5(md5::Md5::new())),
+            _ => Err(anyhow!("不支持的hash算法: {}", algorithm)),
+        }
+    }
+    fn update(&mut self, buff: &[u8]) {
+        use md5::Digest;
+        match self {
+            Self::Xxh3(h) => h.update(buff),
+            Self::Md5(h) => h.update(buff),
+        }
+    }
+    /// 与utils::hash的输出格式保持一致
+    fn finish(self) -> String {
+        use md5::Digest;
+        match self {
+            Self::Xxh3(h) => format!("{:0>16X}", h.digest()),
+            Self::Md5(h) => h.finalize().iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+/// 以流的方式下载到临时文件，校验通过后重命名为目标文件
+/// `progress` 参数为已下载的字节数与总字节数(未知时为0)
+pub async fn download<F>(
+    url: &str,
+    path: &Path,
+    options: &DownloadOptions,
+    progress: F,
+) -> Result<u64>
+where
+    F: Fn(u64, u64),
+{
+    let expected = options.hash.as_ref().map(|h| match h.split_once(':') {
+        Some((algorithm, hash)) => (algorithm.to_string(), hash.trim().to_string()),
+        None => ("xxh3".to_string(), h.trim().to_string()),
+    });
+    let mut hasher = match expected.as_ref() {
+        Some((algorithm, _)) => Some(Hasher::new(algorithm)?),
+        None => None,
+    };
+    let max_size = options.max_size.unwrap_or(MAX_DOWNLOAD_SIZE);
+    let client = reqwest::ClientBuilder::new()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(
+            options.timeout.unwrap_or(DOWNLOAD_TIMEOUT),
+        ))
+        .build()?;
+    let mut resp = crate::mirror::send(&client, url, crate::mirror::RETRIES)
+        .await?
+        .error_for_status()?;
+    let total = resp.content_length().unwrap_or_default();
+    if total > max_size {
+        return Err(anyhow!("文件过大: {}", total));
+    }
+    let tmp = temp_path(path);
+    let res = async {
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        let mut size = 0;
+        while let Some(chunk) = resp.chunk().await? {
+            size += chunk.len() as u64;
+            if size > max_size {
+                return Err(anyhow!("文件过大: 超过{}字节", max_size));
+            }
+            if let Some(h) = hasher.as_mut() {
+                h.update(&chunk);
+            }
+            file.write_all(&chunk).await?;
+            progress(size, total);
+        }
+        file.sync_all().await?;
+        if let (Some(h), Some((_, hash))) = (hasher, expected.as_ref()) {
+            let actual = h.finish();
+            if !actual.eq_ignore_ascii_case(hash) {
+                return Err(anyhow!("hash校验失败: {}", actual));
+            }
+        }
+        Ok(size)
+    }
+    .await;
+    finish(&tmp, path, res).await
+}
+
+#[tokio::test]
+async fn test_write_atomic() {
+    let dir = std::env::temp_dir().join("tvbox-provider-test");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("write.json");
+    write_atomic(&path, b"{}").await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+    assert!(!temp_path(&path).exists());
+
+    let mut h = Hasher::new("xxh3").unwrap();
+    h.update(b"hello");
+    let value = xxhash_rust::xxh3::xxh3_64_with_seed(b"hello", 42);
+    assert_eq!(h.finish(), format!("{:0>16X}", value));
+    let mut h = Hasher::new("MD5").unwrap();
+    h.update(b"hello");
+    assert_eq!(h.finish(), "5d41402abc4b2a76b9719d911017c592");
+}
@@ -0,0 +1,372 @@
+use super::playlist::PlaylistChannel;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+/// 括号中的附加信息, 如: [3840*2160]、(备用)
+static BRACKETS: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r"\[[^\]]*\]|\([^)]*\)|【[^】]*】|「[^」]*」").unwrap());
+/// 码率、分辨率等画质信息, 如: 25M2160、1080P、HDR
+static QUALITY: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(
+        r"(?:\d+M\d*(?:HDR)?|\d{3,4}[PI]|[48]K|UHD|FHD|HDR|HEVC|H265|HD|SD|超高清|超清|高清|标清|蓝光|杜比|备用\d*|测试)$",
+    )
+    .unwrap()
+});
+/// CCTV编号, 如: CCTV-1综合、CCTV 5+、CCTV4K
+static CCTV: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r"^(?:CCTV|中央)[\s\-_]*(\d+)(\+|PLUS|K)?").unwrap());
+/// 名称末尾以`.`分隔的备用标记与扩展名, 如: CCTV16-4K.备用、湖南卫视.m3u8
+static SUFFIX: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(r"\.\s*((?:备用|线路|源)\d*|测试|M3U8?|TS|FLV|MP4)\s*$").unwrap()
+});
+
+/// CCTV编号后的频道说明，不影响频道的区分, 如: CCTV-1综合、CCTV5+体育赛事
+/// 其他内容会保留在名称中, 如: CCTV4欧洲、CCTV4美洲
+const CCTV_TITLES: &[&str] = &[
+    "综合",
+    "财经",
+    "综艺",
+    "中文国际",
+    "体育赛事",
+    "体育",
+    "电影",
+    "国防军事",
+    "军事",
+    "电视剧",
+    "纪录",
+    "记录",
+    "科教",
+    "戏曲",
+    "社会与法",
+    "新闻",
+    "少儿",
+    "音乐",
+    "奥林匹克",
+    "农业农村",
+];
+
+/// 常见繁体字
+const TRADITIONAL: &[(char, char)] = &[
+    ('衛', '卫'),
+    ('視', '视'),
+    ('臺', '台'),
+    ('東', '东'),
+    ('鳳', '凤'),
+    ('華', '华'),
+    ('亞', '亚'),
+    ('門', '门'),
+    ('電', '电'),
+    ('廣', '广'),
+    ('無', '无'),
+    ('綫', '线'),
+    ('線', '线'),
+    ('劇', '剧'),
+    ('藝', '艺'),
+    ('聞', '闻'),
+    ('體', '体'),
+    ('樂', '乐'),
+    ('兒', '儿'),
+    ('經', '经'),
+    ('濟', '济'),
+    ('國', '国'),
+    ('際', '际'),
+    ('財', '财'),
+    ('動', '动'),
+    ('畫', '画'),
+    ('紀', '纪'),
+    ('錄', '录'),
+    ('農', '农'),
+    ('業', '业'),
+    ('軍', '军'),
+    ('灣', '湾'),
+    ('龍', '龙'),
+    ('鐵', '铁'),
+    ('遊', '游'),
+    ('戲', '戏'),
+    ('歷', '历'),
+    ('語', '语'),
+    ('學', '学'),
+    ('購', '购'),
+];
+
+/// 默认的频道别名，键为归一化后的名称
+const ALIASES: &[(&str, &str)] = &[
+    ("上海东方卫视", "东方卫视"),
+    ("上海卫视", "东方卫视"),
+    ("SITV东方卫视", "东方卫视"),
+    ("北京卫视BTV", "北京卫视"),
+    ("BTV北京卫视", "北京卫视"),
+    ("内蒙卫视", "内蒙古卫视"),
+    ("凤凰中文", "凤凰卫视中文台"),
+    ("凤凰卫视中文", "凤凰卫视中文台"),
+    ("凤凰资讯", "凤凰卫视资讯台"),
+    ("凤凰卫视资讯", "凤凰卫视资讯台"),
+];
+
+/// 归一化后的频道名称
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelName {
+    /// 标准名称
+    pub name: String,
+    /// 名称中被移除的画质、备用等标记, 如: 4K、备用
+    pub tags: Vec<String>,
+}
+
+impl ChannelName {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.starts_with(tag))
+    }
+}
+
+/// 频道名称归一化
+#[derive(Debug, Clone)]
+pub struct Normalizer {
+    aliases: HashMap<String, String>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        let mut n = Self {
+            aliases: HashMap::new(),
+        };
+        n.extend(ALIASES.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        n
+    }
+}
+
+impl Normalizer {
+    /// 添加别名，别名与标准名称都会先进行归一化
+    pub fn extend<I>(&mut self, aliases: I)
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (alias, name) in aliases {
+            let alias = self.key(&alias);
+            let name = self.key(&name);
+            if !alias.is_empty() && !name.is_empty() && alias != name {
+                self.aliases.insert(alias, name);
+            }
+        }
+    }
+
+    /// 全角转半角、繁体转简体、统一大写并去除括号中的附加信息
+    fn clean(&self, name: &str) -> (String, Vec<String>) {
+        let name = name
+            .chars()
+            .map(|c| match c {
+                '\u{3000}' => ' ',
+                '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+                _ => TRADITIONAL
+                    .iter()
+                    .find(|(t, _)| *t == c)
+                    .map(|(_, s)| *s)
+                    .unwrap_or(c),
+            })
+            .collect::<String>()
+            .to_uppercase();
+        let name = BRACKETS.replace_all(&name, "").to_string();
+        // CCTV16-4K.备用 -> CCTV16-4K，其他的`.`属于名称
+        let suffix = SUFFIX
+            .captures(&name)
+            .and_then(|c| Some((c.get(0)?.start(), c[1].to_string())))
+            .filter(|(start, _)| *start > 0);
+        match suffix {
+            Some((start, tag)) => (name[..start].to_string(), vec![tag]),
+            None => (name, vec![]),
+        }
+    }
+
+    /// 归一化频道名称
+    pub fn parse(&self, name: &str) -> ChannelName {
+        let (mut name, mut tags) = self.clean(name);
+        if let Some(cap) = CCTV.captures(&name) {
+            // CCTV4K、CCTV8K 为独立频道，其他编号后的K属于画质信息
+            let suffix = match cap.get(2).map(|m| m.as_str()) {
+                Some("K") if matches!(&cap[1], "4" | "8") => "K",
+                Some("+") | Some("PLUS") => "+",
+                _ => "",
+            };
+            let end = match suffix {
+                "" => cap.get(1).map(|m| m.end()).unwrap_or_default(),
+                _ => cap[0].len(),
+            };
+            let mut rest = name[end..].to_string();
+            rest.retain(|c| !is_separator(c));
+            strip_quality(&mut rest, &mut tags, 0);
+            // 只去除频道说明，其他内容用于区分频道, 如: CCTV4欧洲
+            let title = CCTV_TITLES
+                .iter()
+                .filter(|t| rest.starts_with(*t))
+                .max_by_key(|t| t.len());
+            let rest = title.map(|t| &rest[t.len()..]).unwrap_or(&rest);
+            let rest = rest.strip_prefix("频道").unwrap_or(rest);
+            name = format!("CCTV{}{}{}", &cap[1], suffix, rest);
+        } else {
+            name.retain(|c| !is_separator(c));
+            strip_quality(&mut name, &mut tags, 1);
+            if let Some(n) = name.strip_suffix('台').filter(|n| n.ends_with("卫视")) {
+                name = n.to_string();
+            }
+        }
+        if let Some(alias) = self.aliases.get(&name) {
+            name = alias.to_string();
+        }
+        ChannelName { name, tags }
+    }
+
+    /// 归一化后的频道名称，用于比较
+    pub fn key(&self, name: &str) -> String {
+        self.parse(name).name
+    }
+
+    /// 合并同一频道的多个条目，地址按优先级排序：正式源、IPv4优先
+    pub fn merge(&self, channels: Vec<PlaylistChannel>) -> Vec<PlaylistChannel> {
+        self.merge_by(channels, |_| 0.0)
+    }
+
+    /// 合并同一频道的多个条目，优先级相同时按`score`从高到低排序, 如: 历史稳定性
+    pub fn merge_by<F>(&self, channels: Vec<PlaylistChannel>, score: F) -> Vec<PlaylistChannel>
+    where
+        F: Fn(&str) -> f64,
+    {
+        let mut merged: Vec<PlaylistChannel> = vec![];
+        let mut urls: Vec<Vec<(i32, f64, String)>> = vec![];
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for c in channels {
+            let parsed = self.parse(&c.name);
+            if parsed.name.is_empty() {
+                continue;
+            }
+            let i = *index.entry(parsed.name.to_string()).or_insert_with(|| {
+                merged.push(PlaylistChannel {
+                    name: parsed.name.to_string(),
+                    ..Default::default()
+                });
+                urls.push(vec![]);
+                merged.len() - 1
+            });
+            let item = &mut merged[i];
+            item.group = item.group.take().or(c.group);
+            item.tvg_id = item.tvg_id.take().or(c.tvg_id);
+            item.tvg_name = item.tvg_name.take().or(c.tvg_name);
+            item.logo = item.logo.take().or(c.logo);
+            for url in c.urls {
+                if urls[i].iter().any(|(_, _, u)| *u == url) {
+                    continue;
+                }
+                let mut rank = 0;
+                if parsed.has_tag("备用") || parsed.has_tag("测试") {
+                    rank += 1;
+                }
+                if url.contains("://[") {
+                    rank += 2;
+                }
+                let s = score(&url);
+                urls[i].push((rank, s, url));
+            }
+        }
+        for (item, mut u) in merged.iter_mut().zip(urls) {
+            u.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)));
+            item.urls = u.into_iter().map(|(_, _, u)| u).collect();
+        }
+        merged
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || "-_·|".contains(c)
+}
+
+/// 移除名称末尾的画质信息，`min`之前的内容不会被移除，如: 纯享4K
+fn strip_quality(name: &mut String, tags: &mut Vec<String>, min: usize) {
+    while let Some(m) = QUALITY.find(name) {
+        if m.start() < min {
+            break;
+        }
+        tags.push(m.as_str().to_string());
+        name.truncate(m.start());
+    }
+}
+
+static NORMALIZER: Lazy<Normalizer> = Lazy::new(Normalizer::default);
+
+/// 使用默认别名表归一化频道名称
+pub fn normalize(name: &str) -> String {
+    NORMALIZER.key(name)
+}
+
+#[test]
+fn test_normalize() {
+    let n = Normalizer::default();
+    assert_eq!(n.key("CCTV16-4K"), "CCTV16");
+    assert_eq!(n.key("CCTV16-4K.备用"), "CCTV16");
+    assert_eq!(n.key("CCTV-16 4K 25M2160"), "CCTV16");
+    assert_eq!(n.key("CCTV-1综合"), "CCTV1");
+    assert_eq!(n.key("CCTV5+体育赛事"), "CCTV5+");
+    assert_eq!(n.key("CCTV4K"), "CCTV4K");
+    assert_eq!(n.key("ＣＣＴＶ１３"), "CCTV13");
+    assert_eq!(n.key("湖南衛視 HD"), "湖南卫视");
+    assert_eq!(n.key("纯享4K.备用"), "纯享");
+    assert_eq!(n.key("欢笑剧场4K[3840*2160]"), "欢笑剧场");
+    assert_eq!(n.key("上海东方卫视"), "东方卫视");
+    assert!(n.parse("CCTV16-4K.备用").has_tag("备用"));
+    // 编号后的其他内容用于区分频道
+    assert_eq!(n.key("CCTV-4 欧洲 HD"), "CCTV4欧洲");
+    assert_eq!(n.key("CCTV4中文国际美洲"), "CCTV4美洲");
+    assert_eq!(n.key("CCTV1综合频道"), "CCTV1");
+    // 名称中的`.`不会被当作备用标记
+    assert_eq!(n.key("Dr.Who 高清"), "DR.WHO");
+    assert_eq!(n.key("湖南卫视.m3u8"), "湖南卫视");
+
+    let mut n = n;
+    n.extend([("芒果台".to_string(), "湖南卫视".to_string())]);
+    assert_eq!(n.key("芒果台"), "湖南卫视");
+
+    let channels = vec![
+        PlaylistChannel {
+            name: "CCTV16-4K.备用".to_string(),
+            urls: vec!["http://b/16.m3u8".to_string()],
+            ..Default::default()
+        },
+        PlaylistChannel {
+            name: "CCTV-16 4K 25M2160".to_string(),
+            tvg_id: Some("CCTV16".to_string()),
+            urls: vec![
+                "http://[::1]/16.m3u8".to_string(),
+                "http://a/16.m3u8".to_string(),
+            ],
+            ..Default::default()
+        },
+    ];
+    let merged = n.merge(channels);
+    assert_eq!(merged.len(), 1);
+    assert_eq!(merged[0].tvg_id.as_deref(), Some("CCTV16"));
+    assert_eq!(
+        merged[0].urls,
+        vec![
+            "http://a/16.m3u8",
+            "http://b/16.m3u8",
+            "http://[::1]/16.m3u8"
+        ]
+    );
+}
+
+#[test]
+fn test_merge_by() {
+    let n = Normalizer::default();
+    let channel = |url: &str| PlaylistChannel {
+        name: "CCTV1".to_string(),
+        urls: vec![url.to_string()],
+        ..Default::default()
+    };
+    let channels = vec![
+        channel("http://a/1.m3u8"),
+        channel("http://b/1.m3u8"),
+        channel("http://[::1]/1.m3u8"),
+    ];
+    let merged = n.merge_by(channels, |u| if u.contains("//b/") { 1.0 } else { 0.0 });
+    assert_eq!(
+        merged[0].urls,
+        vec!["http://b/1.m3u8", "http://a/1.m3u8", "http://[::1]/1.m3u8"]
+    );
+}
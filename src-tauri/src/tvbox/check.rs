@@ -1,94 +1,313 @@
-use std::sync::Arc;
-
-use super::{spawn, Connection};
+use super::{
+    checkpoint::{Checkpoint, Resume},
+    job::Job,
+    spawn, uptime, Connection, Wrapper,
+};
+use serde::de::DeserializeOwned;
 use tauri::{Runtime, Window};
-use tokio::sync::Mutex;
+use tokio::sync::mpsc;
 
 #[derive(Clone, Serialize)]
 pub struct ProgressPayload {
     pub progress: u64,
     pub total: u64,
+    /// 检测任务的id，用于区分同时进行的多个任务
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+}
+
+impl ProgressPayload {
+    pub fn new(job: &Job, progress: u64, total: u64) -> Self {
+        job.progress(progress, total);
+        Self {
+            progress,
+            total,
+            job: Some(job.id().to_string()),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionStatus<T> {
     pub connectable: bool,
+    /// 检测耗时(毫秒)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<u64>,
     pub extra: T,
 }
 
-pub async fn check_connections<T, R>(
-    window: Window<R>,
+/// 单个检测结果，`index`为在输入中的位置
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemPayload<'a, T> {
+    pub job: &'a str,
+    pub index: usize,
+    #[serde(flatten)]
+    pub status: &'a ConnectionStatus<T>,
+}
+
+/// 检测结果流，每个结果确定后立即返回，顺序与输入不一定相同
+/// 丢弃时会中止未完成的检测
+pub struct CheckStream<T> {
+    rx: mpsc::Receiver<(usize, ConnectionStatus<T>)>,
+    total: usize,
+    _tasks: Vec<Wrapper<()>>,
+}
+
+impl<T> CheckStream<T> {
+    /// 下一个检测结果，全部完成后返回空
+    /// 检测记录不会在这里写入数据库，需要时由调用者执行`uptime::save`
+    pub async fn next(&mut self) -> Option<(usize, ConnectionStatus<T>)> {
+        self.rx.recv().await
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+/// 生成一个合理的线程数，`cpus`为可用的线程数
+pub fn threads(tasks: usize, cpus: usize) -> usize {
+    let cpus = cpus.max(1);
+    if tasks > cpus * cpus {
+        cpus
+    } else {
+        (tasks / cpus).max(1)
+    }
+}
+
+/// 分块并发检测，返回结果流
+pub fn check_stream<T>(
     links: Vec<T>,
+    threads: usize,
     quick_mode: bool,
-    skip_ipv6: Option<bool>,
-) -> Vec<ConnectionStatus<T>>
+    skip_ipv6: bool,
+) -> CheckStream<T>
 where
-    T: for<'se> Connection + Clone + Send + Sync + 'static,
-    R: Runtime,
+    T: Connection + Clone + Send + Sync + 'static,
 {
-    if links.is_empty() {
-        return vec![];
-    }
-    let skip_ipv6 = skip_ipv6.unwrap_or_default();
-    let threads = {
-        let tasks = links.len().max(1);
-        let threads = (num_cpus::get() / 2).max(1);
-        if tasks > threads * threads {
-            threads
-        } else {
-            (tasks / threads).max(1)
-        }
-    };
-    let mut size = links.len() / threads;
-    if size == 0 {
-        size = links.len();
-    }
-
-    let total = links.len() as u64;
-    let count = Arc::new(Mutex::new(0));
-    let chunck = links.chunks(size).map(|ch| ch.to_vec());
+    let total = links.len();
+    let size = (total / threads.max(1)).max(1);
+    let (tx, rx) = mpsc::channel(size.max(16));
     let mut tasks = vec![];
-    window
-        .emit(
-            "check_connections://progress",
-            ProgressPayload { progress: 0, total },
-        )
-        .ok();
-    for c in chunck.into_iter() {
-        let w = window.clone();
-        let cnt = count.clone();
+    for (n, c) in links.chunks(size).enumerate() {
+        let c = c.to_vec();
+        let tx = tx.clone();
         let t = spawn(async move {
-            let mut items = vec![];
-            for mut i in c {
-                let ok = i
+            for (i, mut item) in c.into_iter().enumerate() {
+                let start = std::time::Instant::now();
+                let ok = item
                     .check(quick_mode, skip_ipv6)
                     .await
                     .ok()
                     .unwrap_or_default();
-                items.push(ConnectionStatus {
+                let latency = start.elapsed().as_millis() as u64;
+                if let Some(target) = item.target() {
+                    uptime::record(&target, ok, Some(latency).filter(|_| ok));
+                }
+                let status = ConnectionStatus {
                     connectable: ok,
-                    extra: i,
-                });
-                let mut c = cnt.lock().await;
-                *c += 1;
-                w.emit(
-                    "check_connections://progress",
-                    ProgressPayload {
-                        progress: *c as u64,
-                        total,
-                    },
-                )
-                .ok();
+                    latency: Some(latency),
+                    extra: item,
+                };
+                if tx.send((n * size + i, status)).await.is_err() {
+                    break;
+                }
             }
-            items
         });
         tasks.push(t);
     }
+    CheckStream {
+        rx,
+        total,
+        _tasks: tasks,
+    }
+}
+
+/// 支持断点续检的检测，`fresh`时间内已完成的结果直接从检查点返回
+/// `mode`为影响检测结果的其他参数，与输入内容一起作为检查点的key
+pub fn resume_stream<T>(
+    links: Vec<T>,
+    threads: usize,
+    quick_mode: bool,
+    skip_ipv6: bool,
+    mode: &str,
+    resume: &Resume,
+) -> CheckStream<T>
+where
+    T: Connection + serde::Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    let total = links.len();
+    let mode = format!("{}:{}:{}", quick_mode, skip_ipv6, mode);
+    let mut checkpoint = Checkpoint::open(&resume.dir, &Checkpoint::key(&links, &mode));
+    let mut cached = vec![];
+    let mut keys = vec![];
+    let mut stale = vec![];
+    for (index, item) in links.into_iter().enumerate() {
+        let key = Checkpoint::item_key(&item);
+        match checkpoint.fresh(&key, resume.fresh) {
+            Some(status) => cached.push((index, status.clone())),
+            None => {
+                keys.push((index, key));
+                stale.push(item);
+            }
+        }
+    }
+    let mut inner = check_stream(stale, threads, quick_mode, skip_ipv6);
+    let (tx, rx) = mpsc::channel(64);
+    let task = spawn(async move {
+        for item in cached {
+            if tx.send(item).await.is_err() {
+                return;
+            }
+        }
+        while let Some((i, status)) = inner.next().await {
+            let (index, key) = keys[i].clone();
+            checkpoint.record(key, status.clone());
+            if tx.send((index, status)).await.is_err() {
+                break;
+            }
+        }
+        checkpoint.flush();
+    });
+    CheckStream {
+        rx,
+        total,
+        _tasks: vec![task],
+    }
+}
+
+/// 检测并通过事件发送进度与每个结果，返回按输入顺序排列的结果
+/// 事件：`{event}://progress` 与 `{event}://item`
+pub async fn emit_stream<T, R>(
+    window: Window<R>,
+    job: Job,
+    event: &str,
+    mut stream: CheckStream<T>,
+) -> Vec<ConnectionStatus<T>>
+where
+    T: serde::Serialize + Clone,
+    R: Runtime,
+{
+    let total = stream.total() as u64;
+    let progress_event = format!("{}://progress", event);
+    let item_event = format!("{}://item", event);
+    window
+        .emit(&progress_event, ProgressPayload::new(&job, 0, total))
+        .ok();
     let mut items = vec![];
-    for t in tasks {
-        if let Ok(mut v) = t.await {
-            items.append(&mut v);
+    while let Some((index, status)) = stream.next().await {
+        window
+            .emit(
+                &item_event,
+                ItemPayload {
+                    job: job.id(),
+                    index,
+                    status: &status,
+                },
+            )
+            .ok();
+        items.push((index, status));
+        window
+            .emit(
+                &progress_event,
+                ProgressPayload::new(&job, items.len() as u64, total),
+            )
+            .ok();
+    }
+    uptime::save().await;
+    items.sort_by_key(|(index, _)| *index);
+    items.into_iter().map(|(_, status)| status).collect()
+}
+
+pub async fn check_connections<T, R>(
+    window: Window<R>,
+    job: Job,
+    links: Vec<T>,
+    quick_mode: bool,
+    skip_ipv6: Option<bool>,
+    resume: Option<Resume>,
+) -> Vec<ConnectionStatus<T>>
+where
+    T: for<'se> Connection + serde::Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    R: Runtime,
+{
+    if links.is_empty() {
+        return vec![];
+    }
+    let skip_ipv6 = skip_ipv6.unwrap_or_default();
+    let threads = threads(links.len(), num_cpus::get() / 2);
+    let stream = match resume.as_ref() {
+        Some(r) => resume_stream(links, threads, quick_mode, skip_ipv6, "", r),
+        None => check_stream(links, threads, quick_mode, skip_ipv6),
+    };
+    emit_stream(window, job, "check_connections", stream).await
+}
+
+#[tokio::test]
+async fn test_check_stream() {
+    use async_trait::async_trait;
+    #[derive(Clone)]
+    struct Delay(u64);
+    #[async_trait]
+    impl Connection for Delay {
+        async fn check(&mut self, _: bool, _: bool) -> anyhow::Result<bool> {
+            tokio::time::sleep(std::time::Duration::from_millis(self.0)).await;
+            Ok(self.0 < 50)
+        }
+    }
+    let mut stream = check_stream(vec![Delay(60), Delay(40), Delay(1)], 3, false, false);
+    let (index, status) = stream.next().await.unwrap();
+    assert_eq!(index, 2);
+    assert!(status.connectable);
+    let (index, _) = stream.next().await.unwrap();
+    assert_eq!(index, 1);
+    let (index, status) = stream.next().await.unwrap();
+    assert_eq!(index, 0);
+    assert!(!status.connectable);
+    assert!(stream.next().await.is_none());
+    assert_eq!(threads(3, 4), 1);
+    assert_eq!(threads(100, 4), 4);
+}
+
+#[tokio::test]
+async fn test_resume_stream() {
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static CHECKED: AtomicUsize = AtomicUsize::new(0);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Item(String);
+    #[async_trait]
+    impl Connection for Item {
+        async fn check(&mut self, _: bool, _: bool) -> anyhow::Result<bool> {
+            CHECKED.fetch_add(1, Ordering::SeqCst);
+            Ok(self.0.starts_with('a'))
         }
     }
-    items
+    let dir = std::env::temp_dir().join(format!("tvbox-checkpoint-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let resume = Resume::new(dir.clone(), 3600);
+    let items = vec![Item("a".to_string()), Item("b".to_string())];
+    let mut stream = resume_stream(items.clone(), 2, false, false, "", &resume);
+    while stream.next().await.is_some() {}
+    drop(stream);
+    assert_eq!(CHECKED.load(Ordering::SeqCst), 2);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    // 相同的输入直接使用检查点中的结果
+    let mut stream = resume_stream(items.clone(), 2, false, false, "", &resume);
+    let mut results = vec![];
+    while let Some((index, status)) = stream.next().await {
+        results.push((index, status.connectable));
+    }
+    results.sort();
+    assert_eq!(results, vec![(0, true), (1, false)]);
+    assert_eq!(CHECKED.load(Ordering::SeqCst), 2);
+
+    // 超出有效期后重新检测
+    let resume = Resume::new(dir.clone(), 0);
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let mut stream = resume_stream(items, 2, false, false, "", &resume);
+    while stream.next().await.is_some() {}
+    assert_eq!(CHECKED.load(Ordering::SeqCst), 4);
+    std::fs::remove_dir_all(&dir).ok();
 }
@@ -0,0 +1,154 @@
+use super::check::ConnectionStatus;
+use crate::utils;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// 检查点文件的保留时间
+const KEEP: Duration = Duration::from_secs(7 * 24 * 3600);
+/// 两次写入检查点文件的最小间隔
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 断点续检配置
+#[derive(Debug, Clone)]
+pub struct Resume {
+    /// 检查点文件所在目录
+    pub dir: PathBuf,
+    /// 只复用该时间内的检测结果
+    pub fresh: Duration,
+}
+
+impl Resume {
+    pub fn new(dir: PathBuf, fresh_secs: u64) -> Self {
+        Self {
+            dir,
+            fresh: Duration::from_secs(fresh_secs),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "T: Serialize + DeserializeOwned")]
+struct Entry<T> {
+    /// 检测时间(毫秒时间戳)
+    checked: i64,
+    status: ConnectionStatus<T>,
+}
+
+/// 检测项的内容，来源不会被序列化，同一内容重新获取后可以继续检测
+fn content<V: Serialize + ?Sized>(value: &V) -> String {
+    serde_json::to_value(value).unwrap_or_default().to_string()
+}
+
+/// 检测结果的检查点，以输入内容的hash为文件名，保存已完成的检测结果
+pub struct Checkpoint<T: Serialize + DeserializeOwned> {
+    path: PathBuf,
+    entries: HashMap<String, Entry<T>>,
+    dirty: bool,
+    flushed: Instant,
+}
+
+impl<T: Serialize + DeserializeOwned> Checkpoint<T> {
+    /// 输入内容的hash，`mode`为影响检测结果的参数, 如: quick_mode
+    pub fn key(items: &[T], mode: &str) -> String {
+        let content = content(items);
+        let name = std::any::type_name::<T>();
+        utils::hash(format!("{}\n{}\n{}", name, mode, content).as_bytes())
+    }
+
+    /// 单个检测项的hash
+    pub fn item_key(item: &T) -> String {
+        utils::hash(content(item).as_bytes())
+    }
+
+    /// 打开检查点，文件不存在或无法解析时为空，同时清理过期的检查点文件
+    pub fn open(dir: &Path, key: &str) -> Self {
+        prune(dir);
+        let path = dir.join(format!("{}.json", key));
+        let entries = std::fs::read(&path)
+            .ok()
+            .and_then(|c| serde_json::from_slice(&c).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            entries,
+            dirty: false,
+            flushed: Instant::now(),
+        }
+    }
+
+    /// `fresh`时间内的检测结果
+    pub fn fresh(&self, key: &str, fresh: Duration) -> Option<&ConnectionStatus<T>> {
+        let entry = self.entries.get(key)?;
+        let age = chrono::Local::now().timestamp_millis() - entry.checked;
+        if age >= 0 && (age as u128) <= fresh.as_millis() {
+            Some(&entry.status)
+        } else {
+            None
+        }
+    }
+
+    /// 记录检测结果，按间隔写入文件
+    pub fn record(&mut self, key: String, status: ConnectionStatus<T>) {
+        self.entries.insert(
+            key,
+            Entry {
+                checked: chrono::Local::now().timestamp_millis(),
+                status,
+            },
+        );
+        self.dirty = true;
+        if self.flushed.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    /// 写入检查点文件，先写入临时文件再重命名
+    pub fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        let res = (|| -> anyhow::Result<()> {
+            if let Some(dir) = self.path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let tmp = self.path.with_extension("json.part");
+            std::fs::write(&tmp, serde_json::to_vec(&self.entries)?)?;
+            std::fs::rename(&tmp, &self.path)?;
+            Ok(())
+        })();
+        if let Err(e) = res {
+            println!("checkpoint.error: {:?}", e);
+        }
+        self.dirty = false;
+        self.flushed = Instant::now();
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Drop for Checkpoint<T> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// 删除过期的检查点文件
+fn prune(dir: &Path) {
+    let Ok(files) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for f in files.flatten() {
+        let expired = f
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|m| SystemTime::now().duration_since(m).ok())
+            .map(|age| age > KEEP)
+            .unwrap_or_default();
+        if expired {
+            std::fs::remove_file(f.path()).ok();
+        }
+    }
+}
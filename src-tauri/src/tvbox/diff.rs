@@ -0,0 +1,471 @@
+use super::{
+    playlist::LivePlaylist,
+    source::{live::Live, origin::ORIGIN_FIELD, Source},
+};
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+
+/// 新增或删除的条目
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    /// 用于比较的标识, 如: 站点的key、直播与解析的地址
+    pub key: String,
+    pub name: String,
+}
+
+impl Item {
+    fn label(&self) -> String {
+        if self.name.is_empty() || self.name == self.key {
+            self.key.to_string()
+        } else {
+            format!("{}({})", self.name, self.key)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ListDiff<T> {
+    pub added: Vec<T>,
+    pub removed: Vec<T>,
+}
+
+impl<T> ListDiff<T> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// 字段的变化，字段不存在时为null
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// key相同但内容不同的站点
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SiteChange {
+    pub key: String,
+    pub name: String,
+    pub fields: Vec<FieldChange>,
+}
+
+/// 两个版本的tvbox配置之间的差异
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceDiff {
+    pub sites: ListDiff<Item>,
+    pub sites_changed: Vec<SiteChange>,
+    pub lives: ListDiff<Item>,
+    /// 直播中内置的频道
+    pub channels: ListDiff<String>,
+    pub parses: ListDiff<Item>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spider: Option<FieldChange>,
+    pub flags: ListDiff<String>,
+    pub ads: ListDiff<String>,
+}
+
+/// 两个版本的直播源之间的差异
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistDiff {
+    pub channels: ListDiff<String>,
+    /// 频道地址的变化，`name`为频道名称
+    pub urls: ListDiff<Item>,
+    pub epg: ListDiff<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Diff {
+    Tvbox(Box<SourceDiff>),
+    Playlist(PlaylistDiff),
+}
+
+/// 差异与对应的文本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffReport {
+    #[serde(flatten)]
+    pub diff: Diff,
+    /// 是否有差异
+    pub changed: bool,
+    pub text: String,
+}
+
+impl Diff {
+    /// 比较两个版本的内容，`kind`为空时两者都是有效的tvbox配置则按配置比较，
+    /// 两者都不是tvbox配置时按直播源比较，只有一方是tvbox配置时返回另一方的解析错误
+    pub fn parse(before: &str, after: &str, kind: Option<&str>) -> Result<Self> {
+        let sources = || -> Result<(Source, Source)> {
+            Ok((Source::parse(before, '#')?, Source::parse(after, '#')?))
+        };
+        let playlists = || {
+            Diff::Playlist(diff_playlist(
+                &LivePlaylist::parse(before),
+                &LivePlaylist::parse(after),
+            ))
+        };
+        match kind {
+            Some("tvbox") => {
+                let (b, a) = sources()?;
+                Ok(Diff::Tvbox(Box::new(diff_source(&b, &a))))
+            }
+            Some("playlist") => Ok(playlists()),
+            Some(k) => Err(anyhow!("不支持的类型: {}", k)),
+            None => match (Source::parse(before, '#'), Source::parse(after, '#')) {
+                (Ok(b), Ok(a)) => Ok(Diff::Tvbox(Box::new(diff_source(&b, &a)))),
+                (Err(_), Err(_)) => Ok(playlists()),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Diff::Tvbox(d) => d.is_empty(),
+            Diff::Playlist(d) => d.is_empty(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            Diff::Tvbox(d) => d.to_text(),
+            Diff::Playlist(d) => d.to_text(),
+        }
+    }
+
+    pub fn report(self) -> DiffReport {
+        let text = self.to_text();
+        DiffReport {
+            changed: !self.is_empty(),
+            diff: self,
+            text,
+        }
+    }
+}
+
+/// 按标识比较，保持各自的原有顺序，重复的标识只计一次
+fn list_diff<T, K, F>(before: &[T], after: &[T], key: F) -> ListDiff<T>
+where
+    T: Clone,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    let before_keys = before.iter().map(&key).collect::<BTreeSet<_>>();
+    let after_keys = after.iter().map(&key).collect::<BTreeSet<_>>();
+    let pick = |items: &[T], other: &BTreeSet<K>| {
+        let mut seen = BTreeSet::new();
+        items
+            .iter()
+            .filter(|i| {
+                let k = key(i);
+                !other.contains(&k) && seen.insert(k)
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    ListDiff {
+        added: pick(after, &before_keys),
+        removed: pick(before, &after_keys),
+    }
+}
+
+fn live_item(live: &Live) -> Item {
+    let name = live.name.clone().unwrap_or_default();
+    Item {
+        key: live.url.clone().unwrap_or_else(|| name.to_string()),
+        name,
+    }
+}
+
+/// 直播中内置的频道名称
+fn live_channels(source: &Source) -> Vec<String> {
+    source
+        .lives
+        .iter()
+        .flat_map(|l| l.channels.iter().flatten())
+        .map(|c| c.name.to_string())
+        .collect()
+}
+
+/// 对象中不同的字段，按字段名排序
+fn field_changes(before: &Value, after: &Value) -> Vec<FieldChange> {
+    let empty = serde_json::Map::new();
+    let b = before.as_object().unwrap_or(&empty);
+    let a = after.as_object().unwrap_or(&empty);
+    let fields = b.keys().chain(a.keys()).collect::<BTreeSet<_>>();
+    fields
+        .into_iter()
+        // 来源只是获取时的信息，不是配置的变化
+        .filter(|f| f.as_str() != ORIGIN_FIELD)
+        .filter_map(|f| {
+            let before = b.get(f).cloned().unwrap_or(Value::Null);
+            let after = a.get(f).cloned().unwrap_or(Value::Null);
+            (before != after).then(|| FieldChange {
+                field: f.to_string(),
+                before,
+                after,
+            })
+        })
+        .collect()
+}
+
+pub fn diff_source(before: &Source, after: &Source) -> SourceDiff {
+    let site_item = |s: &super::source::vod::Vod| Item {
+        key: s.key.to_string(),
+        name: s.name.to_string(),
+    };
+    let sites = |s: &Source| s.sites.iter().map(site_item).collect::<Vec<_>>();
+    let previous = before
+        .sites
+        .iter()
+        .map(|s| (s.key.as_str(), s))
+        .collect::<HashMap<_, _>>();
+    let mut sites_changed = vec![];
+    let mut seen = BTreeSet::new();
+    for site in &after.sites {
+        let Some(old) = previous.get(site.key.as_str()) else {
+            continue;
+        };
+        if !seen.insert(site.key.as_str()) {
+            continue;
+        }
+        let fields = field_changes(
+            &serde_json::to_value(old).unwrap_or_default(),
+            &serde_json::to_value(site).unwrap_or_default(),
+        );
+        if !fields.is_empty() {
+            sites_changed.push(SiteChange {
+                key: site.key.to_string(),
+                name: site.name.to_string(),
+                fields,
+            });
+        }
+    }
+    let parses = |s: &Source| {
+        s.parses
+            .iter()
+            .flatten()
+            .map(|p| Item {
+                key: p.url.to_string(),
+                name: p.name.to_string(),
+            })
+            .collect::<Vec<_>>()
+    };
+    let lives = |s: &Source| s.lives.iter().map(live_item).collect::<Vec<_>>();
+    let by_key = |i: &Item| i.key.to_string();
+    let strings = |v: &Option<Vec<String>>| v.clone().unwrap_or_default();
+    let spider = (before.spider != after.spider).then(|| FieldChange {
+        field: "spider".to_string(),
+        before: before.spider.clone().map(Value::String).unwrap_or_default(),
+        after: after.spider.clone().map(Value::String).unwrap_or_default(),
+    });
+    SourceDiff {
+        sites: list_diff(&sites(before), &sites(after), by_key),
+        sites_changed,
+        lives: list_diff(&lives(before), &lives(after), by_key),
+        channels: list_diff(&live_channels(before), &live_channels(after), |c| {
+            c.to_string()
+        }),
+        parses: list_diff(&parses(before), &parses(after), by_key),
+        spider,
+        flags: list_diff(&strings(&before.flags), &strings(&after.flags), |f| {
+            f.to_string()
+        }),
+        ads: list_diff(&strings(&before.ads), &strings(&after.ads), |f| {
+            f.to_string()
+        }),
+    }
+}
+
+pub fn diff_playlist(before: &LivePlaylist, after: &LivePlaylist) -> PlaylistDiff {
+    let names = |p: &LivePlaylist| {
+        p.channels
+            .iter()
+            .map(|c| c.name.to_string())
+            .collect::<Vec<_>>()
+    };
+    let urls = |p: &LivePlaylist| {
+        p.channels
+            .iter()
+            .flat_map(|c| {
+                c.urls.iter().map(|u| Item {
+                    key: u.to_string(),
+                    name: c.name.to_string(),
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    PlaylistDiff {
+        channels: list_diff(&names(before), &names(after), |n| n.to_string()),
+        urls: list_diff(&urls(before), &urls(after), |i| i.key.to_string()),
+        epg: list_diff(&before.epg, &after.epg, |e| e.to_string()),
+    }
+}
+
+impl SourceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+            && self.sites_changed.is_empty()
+            && self.lives.is_empty()
+            && self.channels.is_empty()
+            && self.parses.is_empty()
+            && self.spider.is_none()
+            && self.flags.is_empty()
+            && self.ads.is_empty()
+    }
+
+    /// 输出为文本, `+`为新增，`-`为删除，`~`为修改
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let mut sites = items_text(&self.sites);
+        for c in &self.sites_changed {
+            sites.push(format!(
+                "~ {}",
+                Item {
+                    key: c.key.to_string(),
+                    name: c.name.to_string()
+                }
+                .label()
+            ));
+            for f in &c.fields {
+                sites.push(format!("    {}: {} -> {}", f.field, f.before, f.after));
+            }
+        }
+        section(&mut out, "站点", sites);
+        section(&mut out, "直播", items_text(&self.lives));
+        section(&mut out, "频道", strings_text(&self.channels));
+        section(&mut out, "解析", items_text(&self.parses));
+        if let Some(spider) = self.spider.as_ref() {
+            section(
+                &mut out,
+                "spider",
+                vec![format!("~ {} -> {}", spider.before, spider.after)],
+            );
+        }
+        section(&mut out, "flags", strings_text(&self.flags));
+        section(&mut out, "ads", strings_text(&self.ads));
+        if out.is_empty() {
+            out.push_str("没有变化\n");
+        }
+        out
+    }
+}
+
+impl PlaylistDiff {
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.urls.is_empty() && self.epg.is_empty()
+    }
+
+    /// 输出为文本, `+`为新增，`-`为删除
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        section(&mut out, "频道", strings_text(&self.channels));
+        let urls = ListDiff {
+            added: self
+                .urls
+                .added
+                .iter()
+                .map(|i| format!("{},{}", i.name, i.key))
+                .collect(),
+            removed: self
+                .urls
+                .removed
+                .iter()
+                .map(|i| format!("{},{}", i.name, i.key))
+                .collect(),
+        };
+        section(&mut out, "地址", strings_text(&urls));
+        section(&mut out, "节目单", strings_text(&self.epg));
+        if out.is_empty() {
+            out.push_str("没有变化\n");
+        }
+        out
+    }
+}
+
+fn items_text(diff: &ListDiff<Item>) -> Vec<String> {
+    let added = diff.added.iter().map(|i| format!("+ {}", i.label()));
+    let removed = diff.removed.iter().map(|i| format!("- {}", i.label()));
+    added.chain(removed).collect()
+}
+
+fn strings_text(diff: &ListDiff<String>) -> Vec<String> {
+    let added = diff.added.iter().map(|i| format!("+ {}", i));
+    let removed = diff.removed.iter().map(|i| format!("- {}", i));
+    added.chain(removed).collect()
+}
+
+fn section(out: &mut String, title: &str, lines: Vec<String>) {
+    if lines.is_empty() {
+        return;
+    }
+    out.push_str(&format!("{}:\n", title));
+    for l in lines {
+        out.push_str(&format!("  {}\n", l));
+    }
+}
+
+#[test]
+fn test_diff() {
+    let before = r#"{
+        "spider": "a.jar",
+        "sites": [
+            {"key": "a", "name": "A", "type": 1, "api": "http://a/api"},
+            {"key": "b", "name": "B", "type": 1, "api": "http://b/api"}
+        ],
+        "lives": [{"name": "live", "url": "http://l/1.txt"}],
+        "parses": [{"name": "p1", "type": 1, "url": "http://p/1"}],
+        "flags": ["qq"],
+        "ads": ["ad.a"]
+    }"#;
+    let after = r#"{
+        "spider": "b.jar",
+        "sites": [
+            {"key": "a", "name": "A", "type": 1, "api": "http://a/api2"},
+            {"key": "c", "name": "C", "type": 1, "api": "http://c/api"}
+        ],
+        "lives": [{"name": "live", "url": "http://l/2.txt"}],
+        "parses": [{"name": "p1", "type": 1, "url": "http://p/1"}],
+        "flags": ["qq", "iqiyi"],
+        "ads": []
+    }"#;
+    let Diff::Tvbox(d) = Diff::parse(before, after, None).unwrap() else {
+        panic!("应该按tvbox配置比较");
+    };
+    assert_eq!(d.sites.added[0].key, "c");
+    assert_eq!(d.sites.removed[0].key, "b");
+    assert_eq!(d.sites_changed.len(), 1);
+    assert_eq!(d.sites_changed[0].fields[0].field, "api");
+    assert_eq!(d.lives.added[0].key, "http://l/2.txt");
+    assert!(d.parses.is_empty());
+    assert_eq!(d.spider.as_ref().unwrap().after, "b.jar");
+    assert_eq!(d.flags.added, vec!["iqiyi"]);
+    assert_eq!(d.ads.removed, vec!["ad.a"]);
+    let text = d.to_text();
+    assert!(text.contains("+ C(c)"));
+    assert!(text.contains("    api: \"http://a/api\" -> \"http://a/api2\""));
+    assert!(Diff::parse(before, before, None).unwrap().is_empty());
+    assert!(Diff::parse(before, after, None).unwrap().report().changed);
+
+    let diff = Diff::parse(
+        "央视,#genre#\nCCTV1,http://a/1\nCCTV2,http://a/2\n",
+        "央视,#genre#\nCCTV1,http://b/1\nCCTV3,http://a/3\n",
+        None,
+    )
+    .unwrap();
+    let Diff::Playlist(d) = &diff else {
+        panic!("应该按直播源比较");
+    };
+    assert_eq!(d.channels.added, vec!["CCTV3"]);
+    assert_eq!(d.channels.removed, vec!["CCTV2"]);
+    assert_eq!(d.urls.added.len(), 2);
+    assert!(diff.to_text().contains("- CCTV1,http://a/1"));
+    let json = serde_json::to_value(diff.report()).unwrap();
+    assert_eq!(json["kind"], "playlist");
+    assert!(json["text"].as_str().unwrap().starts_with("频道:"));
+    assert!(Diff::parse("", "", Some("xml")).is_err());
+    // 只有一方是tvbox配置时不会按直播源比较
+    assert!(Diff::parse(before, "{\"sites\": [", None).is_err());
+}
@@ -0,0 +1,467 @@
+use super::{channel::normalize, playlist::PlaylistChannel};
+use crate::utils;
+use anyhow::Result;
+use quick_xml::{escape::escape, events::Event, Reader};
+use std::collections::HashMap;
+
+// 参考： http://wiki.xmltv.org/index.php/XMLTVFormat
+
+/// 节目
+#[derive(Debug, Clone, Serialize)]
+pub struct Programme {
+    pub channel: String,
+    /// 20261018003000 +0800
+    pub start: String,
+    pub stop: String,
+    pub title: String,
+    pub desc: Option<String>,
+}
+
+impl Programme {
+    /// 节目所在日期, 如: 20261018
+    pub fn date(&self) -> &str {
+        self.start.get(..8).unwrap_or_default()
+    }
+}
+
+/// 节目单中的频道
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EpgChannel {
+    pub id: String,
+    pub names: Vec<String>,
+    pub icon: Option<String>,
+}
+
+/// DIYP格式的节目单
+#[derive(Debug, Clone, Serialize)]
+pub struct DiypEpg {
+    pub channel_name: String,
+    pub date: String,
+    pub epg_data: Vec<DiypProgramme>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiypProgramme {
+    pub start: String,
+    pub end: String,
+    pub title: String,
+    pub desc: String,
+}
+
+/// 节目单(XMLTV)
+#[derive(Debug, Default)]
+pub struct Guide {
+    /// 节目单的来源地址
+    pub sources: Vec<String>,
+    pub channels: Vec<EpgChannel>,
+    /// 按频道id索引的节目
+    programmes: HashMap<String, Vec<Programme>>,
+    /// 归一化后的频道名称 -> 频道id
+    names: HashMap<String, String>,
+}
+
+impl Guide {
+    /// 下载并合并多个节目单，支持 .xml 与 .xml.gz 以及GBK等编码
+    pub async fn load(urls: &[String]) -> Self {
+        let mut guide = Self::default();
+        for url in urls {
+            // http地址带有超时与重试
+            let doc = utils::read_content(url)
+                .await
+                .and_then(|xml| Self::parse(&xml));
+            match doc {
+                Ok(doc) => {
+                    guide.merge(doc);
+                    guide.sources.push(url.to_string());
+                }
+                Err(e) => println!("epg.error: {} {:?}", url, e),
+            }
+        }
+        guide
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        let mut reader = Reader::from_str(xml);
+        reader.trim_text(true);
+        let mut guide = Self::default();
+        let mut channel: Option<EpgChannel> = None;
+        let mut programme: Option<Programme> = None;
+        let mut tag = String::new();
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) | Event::Empty(e) => {
+                    let attrs = e
+                        .attributes()
+                        .filter_map(|a| a.ok())
+                        .filter_map(|a| {
+                            let key = String::from_utf8_lossy(a.key.as_ref()).to_string();
+                            a.unescape_value().ok().map(|v| (key, v.to_string()))
+                        })
+                        .collect::<HashMap<_, _>>();
+                    let attr = |k: &str| attrs.get(k).cloned().unwrap_or_default();
+                    tag = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                    match tag.as_str() {
+                        "channel" => {
+                            channel = Some(EpgChannel {
+                                id: attr("id"),
+                                ..Default::default()
+                            })
+                        }
+                        "programme" => {
+                            programme = Some(Programme {
+                                channel: attr("channel"),
+                                start: attr("start"),
+                                stop: attr("stop"),
+                                title: String::new(),
+                                desc: None,
+                            })
+                        }
+                        "icon" => {
+                            if let Some(c) = channel.as_mut() {
+                                c.icon = attrs.get("src").cloned();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Text(e) => {
+                    let text = e.unescape()?.to_string();
+                    Self::text(&tag, text, channel.as_mut(), programme.as_mut());
+                }
+                Event::CData(e) => {
+                    let text = String::from_utf8_lossy(&e.into_inner()).to_string();
+                    Self::text(&tag, text, channel.as_mut(), programme.as_mut());
+                }
+                Event::End(e) => {
+                    match e.name().as_ref() {
+                        b"channel" => {
+                            if let Some(c) = channel.take() {
+                                guide.add_channel(c);
+                            }
+                        }
+                        b"programme" => {
+                            if let Some(p) = programme.take() {
+                                guide.add_programme(p);
+                            }
+                        }
+                        _ => {}
+                    }
+                    tag.clear();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(guide)
+    }
+
+    fn text(
+        tag: &str,
+        text: String,
+        channel: Option<&mut EpgChannel>,
+        programme: Option<&mut Programme>,
+    ) {
+        match tag {
+            "display-name" => {
+                if let Some(c) = channel {
+                    c.names.push(text);
+                }
+            }
+            "title" => {
+                if let Some(p) = programme {
+                    p.title = text;
+                }
+            }
+            "desc" => {
+                if let Some(p) = programme {
+                    p.desc = Some(text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn add_channel(&mut self, channel: EpgChannel) {
+        if channel.id.is_empty() {
+            return;
+        }
+        for name in channel.names.iter().chain(std::iter::once(&channel.id)) {
+            self.names
+                .entry(normalize(name))
+                .or_insert(channel.id.to_string());
+        }
+        match self.channels.iter_mut().find(|c| c.id == channel.id) {
+            Some(c) => {
+                for n in channel.names {
+                    if !c.names.contains(&n) {
+                        c.names.push(n);
+                    }
+                }
+                if c.icon.is_none() {
+                    c.icon = channel.icon;
+                }
+            }
+            None => self.channels.push(channel),
+        }
+    }
+
+    fn add_programme(&mut self, programme: Programme) {
+        self.programmes
+            .entry(programme.channel.to_string())
+            .or_default()
+            .push(programme);
+    }
+
+    /// 合并节目单，频道按display-name对应到已有的频道，id相同但名称不同的频道使用新的id
+    /// 不同来源常用"1"等数字作为id，不能直接按id合并
+    /// 没有频道信息的节目同样保留，同一频道相同开始时间的节目只保留先加载的
+    pub fn merge(&mut self, other: Guide) {
+        let mut ids: HashMap<String, String> = HashMap::new();
+        for mut c in other.channels {
+            let id = match c.names.iter().find_map(|n| self.names.get(&normalize(n))) {
+                Some(id) => id.to_string(),
+                None if self.has_id(&c.id) => self.unique_id(&c.id),
+                None => c.id.to_string(),
+            };
+            ids.insert(c.id.to_string(), id.to_string());
+            c.id = id;
+            self.add_channel(c);
+        }
+        for (id, items) in other.programmes {
+            let id = ids
+                .get(&id)
+                .or_else(|| self.names.get(&normalize(&id)))
+                .cloned()
+                .unwrap_or(id);
+            for mut p in items {
+                let exists = self
+                    .programmes
+                    .get(&id)
+                    .map(|l| l.iter().any(|i| i.start == p.start))
+                    .unwrap_or_default();
+                if !exists {
+                    p.channel = id.to_string();
+                    self.add_programme(p);
+                }
+            }
+        }
+    }
+
+    fn has_id(&self, id: &str) -> bool {
+        self.programmes.contains_key(id) || self.channels.iter().any(|c| c.id == id)
+    }
+
+    /// 没有被使用的id, 如: 1#2
+    fn unique_id(&self, id: &str) -> String {
+        (1..)
+            .map(|n| format!("{}#{}", id, n))
+            .find(|i| !self.has_id(i))
+            .unwrap_or_default()
+    }
+
+    /// 按频道id或频道名称查找节目单中的频道id
+    pub fn find(&self, name: &str) -> Option<&str> {
+        if let Some((id, _)) = self.programmes.get_key_value(name) {
+            return Some(id.as_str());
+        }
+        self.names.get(&normalize(name)).map(|id| id.as_str())
+    }
+
+    /// 查找直播频道对应的节目单频道id
+    pub fn find_channel(&self, channel: &PlaylistChannel) -> Option<&str> {
+        channel
+            .tvg_id
+            .iter()
+            .chain(channel.tvg_name.iter())
+            .chain(std::iter::once(&channel.name))
+            .find_map(|n| self.find(n))
+    }
+
+    /// 频道在某天的节目，date: 2026-10-18
+    pub fn programmes(&self, name: &str, date: &str) -> Vec<&Programme> {
+        let date = date.replace('-', "");
+        self.find(name)
+            .and_then(|id| self.programmes.get(id))
+            .map(|items| items.iter().filter(|p| p.date() == date).collect())
+            .unwrap_or_default()
+    }
+
+    /// DIYP格式的节目单
+    pub fn diyp(&self, name: &str, date: &str) -> DiypEpg {
+        let hm = |t: &str| {
+            t.get(8..12)
+                .map(|t| format!("{}:{}", &t[..2], &t[2..]))
+                .unwrap_or_default()
+        };
+        let epg_data = self
+            .programmes(name, date)
+            .into_iter()
+            .map(|p| DiypProgramme {
+                start: hm(&p.start),
+                end: hm(&p.stop),
+                title: p.title.to_string(),
+                desc: p.desc.clone().unwrap_or_default(),
+            })
+            .collect();
+        DiypEpg {
+            channel_name: name.to_string(),
+            date: date.to_string(),
+            epg_data,
+        }
+    }
+
+    /// 只包含直播频道的XMLTV节目单，频道名称会追加到display-name中
+    pub fn to_xmltv(&self, channels: &[PlaylistChannel]) -> String {
+        let mut matched: Vec<(&str, Vec<&str>)> = vec![];
+        for c in channels {
+            if let Some(id) = self.find_channel(c) {
+                match matched.iter_mut().find(|(i, _)| *i == id) {
+                    Some((_, names)) => {
+                        if !names.contains(&c.name.as_str()) {
+                            names.push(&c.name)
+                        }
+                    }
+                    None => matched.push((id, vec![c.name.as_str()])),
+                }
+            }
+        }
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<tv generator-info-name=\"tvbox-provider\">\n");
+        for (id, names) in &matched {
+            out.push_str(&format!("  <channel id=\"{}\">\n", escape(id)));
+            let mut all = names.clone();
+            if let Some(c) = self.channels.iter().find(|c| c.id == *id) {
+                c.names
+                    .iter()
+                    .filter(|n| !names.contains(&n.as_str()))
+                    .for_each(|n| all.push(n));
+                if let Some(icon) = c.icon.as_ref() {
+                    out.push_str(&format!("    <icon src=\"{}\"/>\n", escape(icon)));
+                }
+            }
+            for n in all {
+                out.push_str(&format!("    <display-name>{}</display-name>\n", escape(n)));
+            }
+            out.push_str("  </channel>\n");
+        }
+        for (id, _) in &matched {
+            for p in self.programmes.get(*id).into_iter().flatten() {
+                out.push_str(&format!(
+                    "  <programme start=\"{}\" stop=\"{}\" channel=\"{}\">\n",
+                    escape(&p.start),
+                    escape(&p.stop),
+                    escape(id)
+                ));
+                out.push_str(&format!("    <title>{}</title>\n", escape(&p.title)));
+                if let Some(desc) = p.desc.as_ref() {
+                    out.push_str(&format!("    <desc>{}</desc>\n", escape(desc)));
+                }
+                out.push_str("  </programme>\n");
+            }
+        }
+        out.push_str("</tv>\n");
+        out
+    }
+}
+
+/// 是否为XMLTV地址，排除DIYP接口模板
+pub fn is_xmltv_url(url: &str) -> bool {
+    let path = url.split('?').next().unwrap_or_default().to_lowercase();
+    !url.contains('{') && (path.ends_with(".xml") || path.ends_with(".gz"))
+}
+
+#[test]
+fn test_guide() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tv>
+  <channel id="1"><display-name lang="zh">CCTV-1 综合</display-name></channel>
+  <channel id="2"><display-name>湖南卫视</display-name><icon src="http://logo/hn.png"/></channel>
+  <programme start="20261018000000 +0800" stop="20261018010000 +0800" channel="1">
+    <title>新闻联播</title><desc>&lt;重播&gt;</desc>
+  </programme>
+  <programme start="20261019000000 +0800" stop="20261019010000 +0800" channel="1">
+    <title>晚间新闻</title>
+  </programme>
+  <programme start="20261018000000 +0800" stop="20261018010000 +0800" channel="2">
+    <title>快乐大本营</title>
+  </programme>
+</tv>"#;
+    let guide = Guide::parse(xml).unwrap();
+    assert_eq!(guide.find("CCTV1"), Some("1"));
+    assert_eq!(guide.find("CCTV-1高清"), Some("1"));
+    let epg = guide.diyp("CCTV1", "2026-10-18");
+    assert_eq!(epg.epg_data.len(), 1);
+    assert_eq!(epg.epg_data[0].start, "00:00");
+    assert_eq!(epg.epg_data[0].desc, "<重播>");
+
+    let channels = vec![PlaylistChannel {
+        name: "CCTV1HD".to_string(),
+        ..Default::default()
+    }];
+    let out = guide.to_xmltv(&channels);
+    assert!(out.contains("<display-name>CCTV1HD</display-name>"));
+    assert!(out.contains("&lt;重播&gt;"));
+    assert!(!out.contains("快乐大本营"));
+
+    // 合并时按节目去重，没有<channel>的节目同样保留
+    let mut guide = guide;
+    let other = r#"<tv>
+  <channel id="1"><display-name>CCTV1</display-name></channel>
+  <programme start="20261018000000 +0800" stop="20261018010000 +0800" channel="1">
+    <title>重复的节目</title>
+  </programme>
+  <programme start="20261018010000 +0800" stop="20261018020000 +0800" channel="1">
+    <title>焦点访谈</title>
+  </programme>
+  <programme start="20261018000000 +0800" stop="20261018010000 +0800" channel="CCTV5">
+    <title>体育新闻</title>
+  </programme>
+</tv>"#;
+    guide.merge(Guide::parse(other).unwrap());
+    let epg = guide.diyp("CCTV1", "2026-10-18");
+    assert_eq!(epg.epg_data.len(), 2);
+    assert_eq!(epg.epg_data[0].title, "新闻联播");
+    assert_eq!(guide.diyp("CCTV5", "2026-10-18").epg_data.len(), 1);
+
+    // 不同来源中相同的id不会合并为同一频道
+    let other = r#"<tv>
+  <channel id="2"><display-name>东方卫视</display-name></channel>
+  <programme start="20261018000000 +0800" stop="20261018010000 +0800" channel="2">
+    <title>极限挑战</title>
+  </programme>
+</tv>"#;
+    guide.merge(Guide::parse(other).unwrap());
+    assert_eq!(
+        guide.diyp("湖南卫视", "2026-10-18").epg_data[0].title,
+        "快乐大本营"
+    );
+    assert_eq!(guide.find("东方卫视"), Some("2#1"));
+    assert_eq!(
+        guide.diyp("东方卫视", "2026-10-18").epg_data[0].title,
+        "极限挑战"
+    );
+}
+
+#[tokio::test]
+async fn test_load_guide() {
+    use std::io::Write;
+    let xml = r#"<?xml version="1.0" encoding="GBK"?>
+<tv>
+  <channel id="1"><display-name>CCTV-1 综合</display-name></channel>
+  <programme start="20261018000000 +0800" stop="20261018010000 +0800" channel="1">
+    <title>新闻联播</title>
+  </programme>
+</tv>"#;
+    let (gbk, _, _) = encoding_rs::GBK.encode(xml);
+    let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    gz.write_all(&gbk).unwrap();
+    let path = std::env::temp_dir().join(format!("tvbox-epg-{}.xml.gz", std::process::id()));
+    std::fs::write(&path, gz.finish().unwrap()).unwrap();
+    let guide = Guide::load(&[path.to_string_lossy().to_string()]).await;
+    std::fs::remove_file(&path).ok();
+    assert_eq!(guide.sources.len(), 1);
+    assert_eq!(
+        guide.diyp("CCTV1", "2026-10-18").epg_data[0].title,
+        "新闻联播"
+    );
+}
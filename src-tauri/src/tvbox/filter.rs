@@ -0,0 +1,430 @@
+//! 配置与直播源的过滤与转换规则，按顺序执行
+//!
+//! ```json5
+//! [
+//!   { action: "include", target: "sites", searchable: true },
+//!   { action: "exclude", target: "sites", field: "name", pattern: "18\\+|成人" },
+//!   { action: "exclude", target: "urls", ipv6: true },
+//!   { action: "include", target: "channels", groups: ["央视", "卫视"] },
+//!   { action: "rename", target: "sites", field: "key", pattern: "^", replace: "my_" },
+//!   { action: "reorder", target: "sites", field: "type", order: ["^3$", "^1$"] },
+//! ]
+//! ```
+use super::{
+    playlist::LivePlaylist,
+    source::{
+        origin::{Origin, Tagged, ORIGIN_FIELD},
+        Source,
+    },
+};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// 只保留满足条件的条目
+    Include,
+    /// 移除满足条件的条目
+    Exclude,
+    /// 替换满足条件的条目中`field`匹配`pattern`的部分
+    Rename,
+    /// 按`order`中的正则对`field`排序，先匹配的排在前面，其他的保持原来的顺序
+    Reorder,
+}
+
+/// 规则作用的条目
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// 点播站点
+    Sites,
+    /// 直播
+    Lives,
+    /// 解析
+    Parses,
+    /// 直播中内置的频道或直播源中的频道，字段有name、group、urls等
+    Channels,
+    /// 频道中的每个地址，字段有url、name(频道名称)、group
+    Urls,
+}
+
+/// 条件，所有设置的条件都满足时才算匹配，没有条件时匹配所有条目
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Condition {
+    /// 字段名，与配置中的名称相同, 如: key、name、api、type、group
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// 匹配字段值的正则，数组中任一项匹配即可
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// 站点、直播或解析的类型
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub types: Option<Vec<i32>>,
+    /// 所在的分组
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+    /// 是否可搜索
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub searchable: Option<bool>,
+    /// 是否使用ipv6地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<bool>,
+    /// 来源的配置地址或域名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+}
+
+/// 一条规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterRule {
+    pub action: Action,
+    pub target: Target,
+    #[serde(flatten)]
+    pub when: Condition,
+    /// rename的替换内容，可以引用正则中的捕获组, 如: ${1}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replace: Option<String>,
+    /// reorder的排序规则
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Vec<String>>,
+}
+
+struct Compiled {
+    rule: FilterRule,
+    pattern: Option<regex::Regex>,
+    order: Vec<regex::Regex>,
+}
+
+/// 编译后的规则
+#[derive(Default)]
+pub struct Filter {
+    rules: Vec<Compiled>,
+}
+
+impl Filter {
+    pub fn new(rules: Vec<FilterRule>) -> Result<Self> {
+        let mut items = vec![];
+        for (i, rule) in rules.into_iter().enumerate() {
+            let invalid = |msg: &str| anyhow!("第{}条规则{}", i + 1, msg);
+            let pattern = match rule.when.pattern.as_ref() {
+                Some(p) => Some(regex::Regex::new(p).map_err(|e| invalid(&e.to_string()))?),
+                None => None,
+            };
+            if pattern.is_some() && rule.when.field.is_none() {
+                return Err(invalid("缺少field"));
+            }
+            if rule.action == Action::Rename && (pattern.is_none() || rule.replace.is_none()) {
+                return Err(invalid("缺少pattern或replace"));
+            }
+            let mut order = vec![];
+            if rule.action == Action::Reorder {
+                if rule.when.field.is_none() {
+                    return Err(invalid("缺少field"));
+                }
+                for o in rule.order.iter().flatten() {
+                    order.push(regex::Regex::new(o).map_err(|e| invalid(&e.to_string()))?);
+                }
+            }
+            items.push(Compiled {
+                rule,
+                pattern,
+                order,
+            });
+        }
+        Ok(Self { rules: items })
+    }
+
+    /// 解析json/json5格式的规则列表
+    pub fn parse(content: &str) -> Result<Self> {
+        let rules: Vec<FilterRule> = json5::from_str(content)?;
+        Self::new(rules)
+    }
+
+    pub fn apply_source(&self, source: &mut Source) {
+        for c in &self.rules {
+            match c.rule.target {
+                Target::Sites => c.apply(&mut source.sites),
+                Target::Lives => c.apply(&mut source.lives),
+                Target::Parses => {
+                    if let Some(parses) = source.parses.as_mut() {
+                        c.apply(parses);
+                    }
+                }
+                Target::Channels | Target::Urls => {
+                    for live in source.lives.iter_mut() {
+                        let group = live.group.clone();
+                        if let Some(channels) = live.channels.as_mut() {
+                            c.apply_channels(channels, group.as_deref());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 只处理频道与地址，其他目标的规则会被忽略
+    pub fn apply_playlist(&self, playlist: &mut LivePlaylist) {
+        for c in &self.rules {
+            if matches!(c.rule.target, Target::Channels | Target::Urls) {
+                c.apply_channels(&mut playlist.channels, None);
+            }
+        }
+    }
+}
+
+impl Compiled {
+    /// 带上来源字段匹配，还原时来源会被重新读取
+    fn apply<T: Tagged + DeserializeOwned>(&self, items: &mut Vec<T>) {
+        let values = items.iter().map(Tagged::to_value_with_origin).collect();
+        let values = self.transform(values);
+        // 无法还原时保持原样
+        let restored = values
+            .into_iter()
+            .map(|v| serde_json::from_value(v))
+            .collect::<Result<Vec<T>, _>>();
+        match restored {
+            Ok(restored) => *items = restored,
+            Err(e) => println!("filter.error: {:?}", e),
+        }
+    }
+
+    /// 频道没有分组时使用`group`，地址全部被移除的频道也会被移除
+    fn apply_channels<T: Serialize + DeserializeOwned>(
+        &self,
+        channels: &mut Vec<T>,
+        group: Option<&str>,
+    ) {
+        let with_group = |c: &T| {
+            let mut v = serde_json::to_value(c).unwrap_or_default();
+            if let (Some(map), Some(group)) = (v.as_object_mut(), group) {
+                map.entry("group").or_insert_with(|| Value::from(group));
+            }
+            v
+        };
+        if self.rule.target == Target::Channels {
+            let values = channels.iter().map(with_group).collect();
+            let values = self.transform(values);
+            // 去掉为了匹配而加上的分组
+            let restored = values
+                .into_iter()
+                .map(|mut v| {
+                    if group.is_some() {
+                        if let Some(map) = v.as_object_mut() {
+                            if map.get("group").and_then(|g| g.as_str()) == group {
+                                map.remove("group");
+                            }
+                        }
+                    }
+                    serde_json::from_value(v)
+                })
+                .collect::<Result<Vec<T>, _>>();
+            match restored {
+                Ok(restored) => *channels = restored,
+                Err(e) => println!("filter.error: {:?}", e),
+            }
+            return;
+        }
+        let mut items = vec![];
+        for c in channels.drain(..) {
+            let mut channel = with_group(&c);
+            let urls = channel
+                .get("urls")
+                .and_then(|u| u.as_array())
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|u| {
+                    let mut v = serde_json::json!({ "url": u });
+                    for f in ["name", "group"] {
+                        if let Some(x) = channel.get(f) {
+                            v[f] = x.clone();
+                        }
+                    }
+                    v
+                })
+                .collect();
+            let urls = self
+                .transform(urls)
+                .into_iter()
+                .filter_map(|mut v| v.get_mut("url").map(Value::take))
+                .collect::<Vec<_>>();
+            if urls.is_empty() {
+                continue;
+            }
+            channel["urls"] = Value::from(urls);
+            match serde_json::from_value(channel) {
+                Ok(channel) => items.push(channel),
+                Err(_) => items.push(c),
+            }
+        }
+        *channels = items;
+    }
+
+    fn transform(&self, mut values: Vec<Value>) -> Vec<Value> {
+        let rule = &self.rule;
+        match rule.action {
+            Action::Include => values.retain(|v| self.matches(v)),
+            Action::Exclude => values.retain(|v| !self.matches(v)),
+            Action::Rename => {
+                let (Some(field), Some(pattern), Some(replace)) = (
+                    rule.when.field.as_ref(),
+                    self.pattern.as_ref(),
+                    rule.replace.as_ref(),
+                ) else {
+                    return values;
+                };
+                for v in values.iter_mut().filter(|v| self.matches(v)) {
+                    if let Some(Value::String(s)) = v.get_mut(field.as_str()) {
+                        *s = pattern.replace_all(s, replace.as_str()).to_string();
+                    }
+                }
+            }
+            Action::Reorder => {
+                let field = rule.when.field.as_deref().unwrap_or_default();
+                values.sort_by_cached_key(|v| {
+                    let texts = texts(v.get(field));
+                    self.order
+                        .iter()
+                        .position(|o| texts.iter().any(|t| o.is_match(t)))
+                        .unwrap_or(self.order.len())
+                });
+            }
+        }
+        values
+    }
+
+    fn matches(&self, value: &Value) -> bool {
+        let when = &self.rule.when;
+        if let (Some(field), Some(pattern)) = (when.field.as_ref(), self.pattern.as_ref()) {
+            if !texts(value.get(field)).iter().any(|t| pattern.is_match(t)) {
+                return false;
+            }
+        }
+        if let Some(types) = when.types.as_ref() {
+            let t = value.get("type").and_then(|t| t.as_i64());
+            if !t
+                .map(|t| types.iter().any(|x| *x as i64 == t))
+                .unwrap_or_default()
+            {
+                return false;
+            }
+        }
+        if let Some(groups) = when.groups.as_ref() {
+            let g = value.get("group").and_then(|g| g.as_str());
+            if !g.map(|g| groups.iter().any(|x| x == g)).unwrap_or_default() {
+                return false;
+            }
+        }
+        if let Some(searchable) = when.searchable {
+            let s = value
+                .get("searchable")
+                .and_then(|s| s.as_i64())
+                .unwrap_or_default();
+            if (s != 0) != searchable {
+                return false;
+            }
+        }
+        if let Some(ipv6) = when.ipv6 {
+            if uses_ipv6(value) != ipv6 {
+                return false;
+            }
+        }
+        if let Some(provider) = when.provider.as_ref() {
+            let origin = value
+                .get(ORIGIN_FIELD)
+                .and_then(|o| serde_json::from_value::<Origin>(o.clone()).ok());
+            if !origin.map(|o| o.matches(provider)).unwrap_or_default() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// 字段值的文本形式，数组中的每一项分别返回
+fn texts(value: Option<&Value>) -> Vec<String> {
+    match value {
+        None | Some(Value::Null) => vec![],
+        Some(Value::String(s)) => vec![s.to_string()],
+        Some(Value::Array(items)) => items.iter().flat_map(|i| texts(Some(i))).collect(),
+        Some(v) => vec![v.to_string()],
+    }
+}
+
+/// 地址中是否有ipv6地址, 如: http://[::1]/live.m3u8
+fn uses_ipv6(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.contains("://["),
+        Value::Array(items) => items.iter().any(uses_ipv6),
+        Value::Object(map) => map
+            .iter()
+            .any(|(k, v)| k.as_str() != ORIGIN_FIELD && uses_ipv6(v)),
+        _ => false,
+    }
+}
+
+#[test]
+fn test_filter() {
+    let filter = Filter::parse(
+        r#"[
+        { action: "include", target: "sites", searchable: true },
+        { action: "exclude", target: "sites", field: "name", pattern: "18\\+|成人" },
+        { action: "exclude", target: "urls", ipv6: true },
+        { action: "include", target: "channels", groups: ["央视", "卫视"] },
+        { action: "rename", target: "sites", field: "key", pattern: "^", replace: "my_" },
+        { action: "reorder", target: "sites", field: "type", order: ["^3$"] },
+        { action: "exclude", target: "parses", types: [0] },
+    ]"#,
+    )
+    .unwrap();
+    let mut source = Source::parse(
+        r#"{
+        "sites": [
+            {"key": "a", "name": "A", "type": 1, "api": "http://a.com/api", "searchable": 1},
+            {"key": "b", "name": "B 18+", "type": 1, "api": "http://b.com/api", "searchable": 1},
+            {"key": "c", "name": "C", "type": 3, "api": "csp_C", "searchable": 1},
+            {"key": "d", "name": "D", "type": 3, "api": "csp_D", "searchable": 0}
+        ],
+        "lives": [
+            {"group": "央视", "channels": [{"name": "CCTV1", "urls": ["http://[::1]/1.m3u8", "http://x.com/1.m3u8"]}]},
+            {"group": "其他", "channels": [{"name": "其他", "urls": ["http://x.com/2.m3u8"]}]}
+        ],
+        "parses": [{"name": "嗅探", "url": "http://p.com/?url="}, {"name": "json", "type": 1, "url": "http://j.com/?url="}]
+    }"#,
+        '#',
+    )
+    .unwrap();
+    filter.apply_source(&mut source);
+    let keys = source
+        .sites
+        .iter()
+        .map(|s| s.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, vec!["my_c", "my_a"]);
+    let channels = source.lives[0].channels.as_ref().unwrap();
+    assert_eq!(channels[0].urls, vec!["http://x.com/1.m3u8"]);
+    assert!(source.lives[1].channels.as_ref().unwrap().is_empty());
+    let parses = source.parses.as_ref().unwrap();
+    assert_eq!(parses.len(), 1);
+    assert_eq!(parses[0].name, "json");
+
+    let mut playlist = LivePlaylist::parse(
+        "央视,#genre#\nCCTV1,http://[::1]/1\nCCTV1,http://x.com/1\n卫视,#genre#\n湖南卫视,http://[::1]/2\n少儿,#genre#\n卡酷,http://x.com/3\n",
+    );
+    filter.apply_playlist(&mut playlist);
+    let names = playlist
+        .channels
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["CCTV1"]);
+    assert_eq!(playlist.channels[0].group.as_deref(), Some("央视"));
+
+    assert!(Filter::parse(r#"[{action: "rename", target: "sites", field: "key"}]"#).is_err());
+    assert!(Filter::parse(
+        r#"[{action: "exclude", target: "sites", field: "name", pattern: "("}]"#
+    )
+    .is_err());
+}
@@ -0,0 +1,158 @@
+use super::{channel::Normalizer, playlist::PlaylistChannel};
+use anyhow::Result;
+
+/// 分组规则，按顺序匹配，第一个命中的规则生效
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupRule {
+    /// 分组名称，可以引用正则中的捕获组, 如: ${1}
+    pub group: String,
+    /// 匹配归一化后的频道名称
+    pub pattern: Option<String>,
+    /// 匹配频道名称中的画质等标记, 如: 4K
+    pub tag: Option<String>,
+}
+
+impl GroupRule {
+    fn new(group: &str, pattern: Option<&str>, tag: Option<&str>) -> Self {
+        Self {
+            group: group.to_string(),
+            pattern: pattern.map(|p| p.to_string()),
+            tag: tag.map(|t| t.to_string()),
+        }
+    }
+
+    /// 默认分组规则：4K、央视、卫视、体育、少儿、按省份划分的地方台
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new("4K", Some(r"[48]K$"), Some("4K")),
+            Self::new("4K", None, Some("8K")),
+            Self::new("央视", Some(r"^(CCTV|CETV|CGTN)"), None),
+            Self::new("卫视", Some(r"卫视"), None),
+            Self::new("体育", Some(r"体育|足球|篮球|赛事|NBA|高尔夫|台球"), None),
+            Self::new(
+                "少儿",
+                Some(r"少儿|卡通|动画|动漫|儿童|卡酷|炫动|优漫"),
+                None,
+            ),
+            Self::new(
+                "${1}",
+                Some(concat!(
+                    r"^(北京|天津|上海|重庆|河北|山西|辽宁|吉林|黑龙江|江苏|浙江|安徽|福建|江西|山东|河南|",
+                    r"湖北|湖南|广东|海南|四川|贵州|云南|陕西|甘肃|青海|台湾|内蒙古|广西|西藏|宁夏|新疆|香港|澳门)"
+                )),
+                None,
+            ),
+        ]
+    }
+}
+
+/// 频道自动分组与台标
+pub struct Classifier {
+    rules: Vec<(GroupRule, Option<regex::Regex>)>,
+    /// 台标地址模板, 如: https://live.fanmingming.com/tv/{name}.png
+    logo: Option<String>,
+    normalizer: Normalizer,
+}
+
+impl Classifier {
+    pub fn new(
+        rules: Vec<GroupRule>,
+        logo: Option<String>,
+        normalizer: Normalizer,
+    ) -> Result<Self> {
+        let mut items = vec![];
+        for r in rules {
+            let pattern = match r.pattern.as_ref() {
+                Some(p) => Some(regex::Regex::new(p)?),
+                None => None,
+            };
+            items.push((r, pattern));
+        }
+        Ok(Self {
+            rules: items,
+            logo: logo.filter(|l| !l.is_empty()),
+            normalizer,
+        })
+    }
+
+    /// 频道所属的分组
+    pub fn group(&self, name: &str) -> Option<String> {
+        let parsed = self.normalizer.parse(name);
+        for (rule, pattern) in &self.rules {
+            if let Some(r) = pattern {
+                if let Some(cap) = r.captures(&parsed.name) {
+                    let mut group = String::new();
+                    cap.expand(&rule.group, &mut group);
+                    return Some(group);
+                }
+            }
+            if let Some(tag) = rule.tag.as_ref() {
+                if parsed.has_tag(tag) {
+                    return Some(rule.group.to_string());
+                }
+            }
+        }
+        None
+    }
+
+    /// 频道的台标地址, 模板中的{name}为归一化后的频道名称，{id}为tvg-id
+    pub fn logo(&self, channel: &PlaylistChannel) -> Option<String> {
+        let template = self.logo.as_ref()?;
+        let name = self.normalizer.key(&channel.name);
+        let id = channel.tvg_id.as_ref().unwrap_or(&name);
+        let encode =
+            |s: &str| url::form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>();
+        Some(
+            template
+                .replace("{name}", &encode(&name))
+                .replace("{id}", &encode(id)),
+        )
+    }
+
+    /// 为频道设置分组与台标，`force`为false时只处理缺失的字段
+    pub fn classify(&self, channel: &mut PlaylistChannel, force: bool) {
+        if force || channel.group.is_none() {
+            if let Some(group) = self.group(&channel.name) {
+                channel.group = Some(group);
+            }
+        }
+        if force || channel.logo.is_none() {
+            if let Some(logo) = self.logo(channel) {
+                channel.logo = Some(logo);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_classify() {
+    let classifier = Classifier::new(
+        GroupRule::defaults(),
+        Some("https://logo.example.com/{name}.png".to_string()),
+        Normalizer::default(),
+    )
+    .unwrap();
+    assert_eq!(classifier.group("CCTV-1综合").as_deref(), Some("央视"));
+    assert_eq!(classifier.group("CCTV16-4K").as_deref(), Some("4K"));
+    assert_eq!(
+        classifier.group("欢笑剧场4K[3840*2160]").as_deref(),
+        Some("4K")
+    );
+    assert_eq!(classifier.group("湖南衛視 HD").as_deref(), Some("卫视"));
+    assert_eq!(classifier.group("广东体育").as_deref(), Some("体育"));
+    assert_eq!(classifier.group("金鹰卡通").as_deref(), Some("少儿"));
+    assert_eq!(classifier.group("广东珠江").as_deref(), Some("广东"));
+    assert_eq!(classifier.group("直播中国"), None);
+
+    let mut c = PlaylistChannel {
+        name: "CCTV-1 HD".to_string(),
+        group: Some("自定义".to_string()),
+        ..Default::default()
+    };
+    classifier.classify(&mut c, false);
+    assert_eq!(c.group.as_deref(), Some("自定义"));
+    assert_eq!(
+        c.logo.as_deref(),
+        Some("https://logo.example.com/CCTV1.png")
+    );
+}
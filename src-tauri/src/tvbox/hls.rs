@@ -0,0 +1,310 @@
+use super::source::rule::Rule;
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use url::Url;
+
+/// 标签属性中的uri, 如: #EXT-X-KEY:METHOD=AES-128,URI="key.bin"
+static ATTR_URI: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r#"URI="([^"]*)""#).unwrap());
+
+/// 判断内容是否为m3u8播放列表
+pub fn is_playlist(content: &str) -> bool {
+    content
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with("#EXTM3U")
+}
+
+/// 将播放列表中的相对地址转换为绝对地址
+pub fn resolve(base: &Url, uri: &str) -> String {
+    base.join(uri)
+        .map(|u| u.to_string())
+        .unwrap_or(uri.to_string())
+}
+
+/// 重写播放列表中所有的uri(分片、子播放列表、密钥、音轨等)
+/// `map` 接收已转换为绝对地址的uri，返回替换后的uri
+pub fn rewrite_uris<F>(content: &str, base: &Url, map: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let mut out = String::with_capacity(content.len());
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            out.push_str(line);
+        } else if trimmed.starts_with('#') {
+            let line = ATTR_URI.replace_all(trimmed, |caps: &regex::Captures| {
+                format!("URI=\"{}\"", map(&resolve(base, &caps[1])))
+            });
+            out.push_str(&line);
+        } else {
+            out.push_str(&map(&resolve(base, trimmed)));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// 广告过滤规则，来自配置中的 ads 与 rules
+#[derive(Debug, Default)]
+pub struct AdFilter {
+    /// 广告域名
+    hosts: Vec<String>,
+    /// 适用于当前播放地址的广告正则
+    patterns: Vec<regex::Regex>,
+}
+
+impl AdFilter {
+    /// `url` 为播放列表地址，仅使用 hosts/host 与其匹配的规则
+    pub fn new(ads: &[String], rules: &[Rule], url: &str) -> Self {
+        let hosts = ads
+            .iter()
+            .map(|h| h.trim().to_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect();
+        let patterns = rules
+            .iter()
+            .filter(|r| r.matches(url))
+            .flat_map(|r| r.patterns())
+            .filter_map(|p| regex::Regex::new(p).ok())
+            .collect();
+        Self { hosts, patterns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty() && self.patterns.is_empty()
+    }
+
+    /// 分片地址是否为广告，`uri` 为分片的绝对地址
+    pub fn is_ad(&self, uri: &str) -> bool {
+        let host = Url::parse(uri)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_lowercase()));
+        if let Some(host) = host {
+            let hit = self
+                .hosts
+                .iter()
+                .any(|h| host == *h || host.ends_with(&format!(".{}", h)));
+            if hit {
+                return true;
+            }
+        }
+        self.patterns.iter().any(|r| r.is_match(uri))
+    }
+
+    /// 与TVBox一样对整个播放列表匹配正则，返回命中的范围
+    /// 可以匹配跨多行的内容, 如: `#EXT-X-DISCONTINUITY\r*\n*#EXTINF:3.3,[\s\S]*?#EXT-X-DISCONTINUITY`
+    pub fn find(&self, content: &str) -> Vec<std::ops::Range<usize>> {
+        self.patterns
+            .iter()
+            .flat_map(|r| r.find_iter(content).map(|m| m.range()))
+            .filter(|m| !m.is_empty())
+            .collect()
+    }
+}
+
+/// 属于分片本身的标签，分片被移除时一并移除
+fn is_segment_tag(line: &str) -> bool {
+    line.starts_with("#EXTINF")
+        || line.starts_with("#EXT-X-BYTERANGE")
+        || line.starts_with("#EXT-X-DISCONTINUITY")
+        || line.starts_with("#EXT-X-PROGRAM-DATE-TIME")
+}
+
+enum Item {
+    Tag(String),
+    Segment {
+        tags: Vec<String>,
+        uri: String,
+        discontinuity: bool,
+    },
+}
+
+/// 移除媒体播放列表中的广告分片
+///
+/// 地址命中规则，或地址被对整个播放列表匹配的正则覆盖的分片会被移除；
+/// 当播放列表由 `#EXT-X-DISCONTINUITY` 分为多段时，包含广告分片的非主体段落会被整段移除
+pub fn strip_ads(content: &str, base: &Url, filter: &AdFilter) -> String {
+    if filter.is_empty() {
+        return content.to_string();
+    }
+    let mut items = vec![];
+    let mut pending: Vec<String> = vec![];
+    // 去掉空行后的播放列表，以及每个分片地址在其中的范围
+    let mut text = String::with_capacity(content.len());
+    let mut ranges = vec![];
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let start = text.len();
+        text.push_str(line);
+        text.push('\n');
+        if line.starts_with('#') {
+            if is_segment_tag(line) {
+                pending.push(line.to_string());
+            } else {
+                items.push(Item::Tag(line.to_string()));
+            }
+        } else {
+            ranges.push(start..start + line.len());
+            let tags = std::mem::take(&mut pending);
+            let discontinuity = tags.iter().any(|t| t.starts_with("#EXT-X-DISCONTINUITY"));
+            items.push(Item::Segment {
+                tags,
+                uri: line.to_string(),
+                discontinuity,
+            });
+        }
+    }
+    // 按 discontinuity 分段，并标记广告分片
+    let matches = filter.find(&text);
+    let mut group = 0;
+    let mut groups = vec![];
+    let mut ads = vec![];
+    for i in &items {
+        if let Item::Segment {
+            uri, discontinuity, ..
+        } = i
+        {
+            if *discontinuity && !groups.is_empty() {
+                group += 1;
+            }
+            let range = &ranges[groups.len()];
+            let hit = matches
+                .iter()
+                .any(|m| m.start < range.end && range.start < m.end);
+            groups.push(group);
+            ads.push(hit || filter.is_ad(&resolve(base, uri)));
+        }
+    }
+    if groups.is_empty() {
+        return content.to_string();
+    }
+    // 分片数最多的段落视为正片，不整段移除
+    let mut sizes = vec![0; group + 1];
+    groups.iter().for_each(|g| sizes[*g] += 1);
+    let main = (0..sizes.len())
+        .max_by_key(|g| sizes[*g])
+        .unwrap_or_default();
+    let ad_groups = groups
+        .iter()
+        .zip(ads.iter())
+        .filter(|(g, ad)| **ad && **g != main)
+        .map(|(g, _)| *g)
+        .collect::<HashSet<_>>();
+
+    let mut out = String::with_capacity(content.len());
+    let mut index = 0;
+    let mut discontinuity_pending = false;
+    for i in items {
+        match i {
+            Item::Tag(line) => {
+                out.push_str(&line);
+                out.push('\n');
+            }
+            Item::Segment {
+                tags,
+                uri,
+                discontinuity,
+            } => {
+                let removed = ads[index] || ad_groups.contains(&groups[index]);
+                index += 1;
+                if removed {
+                    discontinuity_pending |= discontinuity;
+                    continue;
+                }
+                if discontinuity_pending && !discontinuity {
+                    out.push_str("#EXT-X-DISCONTINUITY\n");
+                }
+                discontinuity_pending = false;
+                for t in tags {
+                    out.push_str(&t);
+                    out.push('\n');
+                }
+                out.push_str(&uri);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_strip_ads() {
+    let content = r#"#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-TARGETDURATION:10
+#EXTINF:10,
+a-1.ts
+#EXTINF:10,
+a-2.ts
+#EXT-X-DISCONTINUITY
+#EXTINF:5,
+https://ad.example.net/x/1.ts
+#EXTINF:5,
+/adjump/2.ts
+#EXT-X-DISCONTINUITY
+#EXTINF:10,
+a-3.ts
+#EXTINF:10,
+a-4.ts
+#EXT-X-ENDLIST
+"#;
+    let base = Url::parse("http://vip.example.com/live/index.m3u8").unwrap();
+    let rules = vec![Rule {
+        hosts: Some(vec!["vip.example".to_string()]),
+        name: None,
+        regex: Some(vec!["adjump".to_string()]),
+        host: None,
+        rule: None,
+    }];
+    let filter = AdFilter::new(&["ad.example.net".to_string()], &rules, base.as_str());
+    let out = strip_ads(content, &base, &filter);
+    assert!(!out.contains("ad.example.net"));
+    assert!(!out.contains("adjump"));
+    assert!(out.contains("a-2.ts\n#EXT-X-DISCONTINUITY\n#EXTINF:10,\na-3.ts"));
+    assert_eq!(out.matches("#EXTINF").count(), 4);
+    assert!(out.contains("#EXT-X-ENDLIST"));
+
+    // 不适用于该地址的规则不生效
+    let filter = AdFilter::new(&[], &rules, "http://other.com/index.m3u8");
+    assert!(filter.is_empty());
+
+    // 嗅探规则不用于过滤广告，跨多行的正则对整个播放列表匹配
+    let rules = vec![Rule {
+        hosts: None,
+        name: None,
+        regex: Some(vec![
+            r"#EXT-X-DISCONTINUITY\r*\n*#EXTINF:5,[\s\S]*?#EXT-X-DISCONTINUITY".to_string(),
+        ]),
+        host: Some("*".to_string()),
+        rule: Some(vec![r"\.ts".to_string()]),
+    }];
+    let filter = AdFilter::new(&[], &rules, base.as_str());
+    let out = strip_ads(content, &base, &filter);
+    assert!(!out.contains("ad.example.net") && !out.contains("adjump"));
+    assert!(out.contains("a-2.ts\n#EXT-X-DISCONTINUITY\n#EXTINF:10,\na-3.ts"));
+    assert_eq!(out.matches("#EXTINF").count(), 4);
+}
+
+#[test]
+fn test_rewrite_uris() {
+    let content = r#"#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-KEY:METHOD=AES-128,URI="key.bin"
+#EXTINF:10,
+seg-1.ts
+#EXTINF:10,
+/live/seg-2.ts
+#EXT-X-ENDLIST
+"#;
+    let base = Url::parse("http://example.com/live/index.m3u8").unwrap();
+    let out = rewrite_uris(content, &base, |uri| format!("/relay?url={}", uri));
+    assert!(is_playlist(&out));
+    assert!(out.contains(r#"URI="/relay?url=http://example.com/live/key.bin""#));
+    assert!(out.contains("/relay?url=http://example.com/live/seg-1.ts\n"));
+    assert!(out.contains("/relay?url=http://example.com/live/seg-2.ts\n"));
+    assert!(out.contains("#EXT-X-ENDLIST"));
+}
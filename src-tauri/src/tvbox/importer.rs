@@ -0,0 +1,246 @@
+use super::{playlist::LivePlaylist, source::Source, spawn};
+use crate::utils;
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// 文本中的链接，不包含空白、引号、括号及中文标点
+static LINK: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(r#"https?://[^\s"'`<>()\[\]{}，。；、！？“”‘’（）【】]+"#).unwrap()
+});
+
+/// 同时检测的链接数量
+const CONCURRENCY: usize = 8;
+
+/// 链接类型
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// tvbox配置
+    Config,
+    /// 仓库索引(单仓/多仓)
+    Warehouse,
+    /// txt格式直播源
+    TxtPlaylist,
+    /// m3u格式直播源
+    M3uPlaylist,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub url: String,
+    pub kind: LinkKind,
+    /// 仓库索引中的名称
+    pub name: Option<String>,
+    /// 仓库索引中包含的地址
+    pub children: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// 导入结果
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+    /// 可直接用于检测与合并的tvbox配置
+    #[serde(default)]
+    pub configs: Vec<String>,
+    /// 可直接用于检测与合并的直播源
+    #[serde(default)]
+    pub playlists: Vec<String>,
+}
+
+impl Manifest {
+    fn urls(&self, kind: LinkKind) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|e| e.kind == kind)
+            .map(|e| e.url.to_string())
+            .collect()
+    }
+
+    /// 按类型汇总可直接使用的地址
+    fn collect(&mut self) {
+        self.configs = self.urls(LinkKind::Config);
+        self.playlists = self.urls(LinkKind::TxtPlaylist);
+        self.playlists.append(&mut self.urls(LinkKind::M3uPlaylist));
+    }
+}
+
+/// 从markdown、文本或json中提取所有链接，保持原有顺序并去重
+pub fn extract_links(content: &str) -> Vec<String> {
+    let mut links: Vec<String> = vec![];
+    for m in LINK.find_iter(content) {
+        let link = m
+            .as_str()
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', '*', '_', '\\'])
+            .to_string();
+        if url::Url::parse(&link).is_ok() && !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+/// 仓库索引中的条目
+/// 单仓: {"urls":[{"name":"","url":""}]}
+/// 多仓: {"storeHouse":[{"sourceName":"","sourceUrl":""}]}
+fn warehouse(content: &str) -> Option<Vec<(Option<String>, String)>> {
+    let doc = json5::from_str::<serde_json::Value>(content).ok()?;
+    let (items, name, url) = if let Some(items) = doc.get("urls") {
+        (items, "name", "url")
+    } else if let Some(items) = doc.get("storeHouse") {
+        (items, "sourceName", "sourceUrl")
+    } else {
+        return None;
+    };
+    let items = items
+        .as_array()?
+        .iter()
+        .filter_map(|i| {
+            let u = i.get(url)?.as_str()?.trim().to_string();
+            let n = i.get(name).and_then(|n| n.as_str()).map(|n| n.to_string());
+            Some((n, u))
+        })
+        .collect::<Vec<_>>();
+    Some(items)
+}
+
+/// 根据内容判断链接类型
+pub fn sniff(content: &str) -> (LinkKind, Vec<(Option<String>, String)>) {
+    let text = content.trim_start_matches('\u{feff}').trim_start();
+    if text.starts_with("#EXTM3U") {
+        return (LinkKind::M3uPlaylist, vec![]);
+    }
+    if text.starts_with('{') {
+        if let Some(items) = warehouse(text) {
+            return (LinkKind::Warehouse, items);
+        }
+    }
+    if let Ok(source) = Source::parse(text, '#') {
+        if !source.sites.is_empty() || !source.lives.is_empty() {
+            return (LinkKind::Config, vec![]);
+        }
+    }
+    let playlist = LivePlaylist::parse(text);
+    let playable = playlist
+        .channels
+        .iter()
+        .any(|c| c.urls.iter().any(|u| u.contains("://")));
+    if playable {
+        return (LinkKind::TxtPlaylist, vec![]);
+    }
+    (LinkKind::Unknown, vec![])
+}
+
+async fn classify(url: String) -> (ManifestEntry, Vec<(Option<String>, String)>) {
+    let mut entry = ManifestEntry {
+        url,
+        kind: LinkKind::Unknown,
+        name: None,
+        children: vec![],
+        error: None,
+    };
+    let mut items = vec![];
+    match utils::read_content(&entry.url).await {
+        Ok(content) => {
+            let (kind, children) = sniff(&content);
+            entry.kind = kind;
+            entry.children = children.iter().map(|(_, u)| u.to_string()).collect();
+            items = children;
+        }
+        Err(e) => entry.error = Some(e.to_string()),
+    }
+    (entry, items)
+}
+
+/// 提取链接并逐一下载判断类型，`expand`为true时继续导入仓库索引中的地址
+pub async fn import(content: &str, expand: bool) -> Manifest {
+    let mut manifest = Manifest::default();
+    let mut names = std::collections::HashMap::new();
+    let mut pending = extract_links(content);
+    let semaphore = Arc::new(Semaphore::new(CONCURRENCY));
+    while !pending.is_empty() {
+        let tasks = pending
+            .drain(..)
+            .map(|url| {
+                let semaphore = semaphore.clone();
+                spawn(async move {
+                    let _permit = semaphore.acquire().await;
+                    classify(url).await
+                })
+            })
+            .collect::<Vec<_>>();
+        for t in tasks {
+            let Ok((mut entry, children)) = t.await else {
+                continue;
+            };
+            entry.name = names.remove(&entry.url).flatten();
+            if expand {
+                for (name, url) in children {
+                    let known = manifest.entries.iter().any(|e| e.url == url)
+                        || pending.contains(&url)
+                        || url == entry.url;
+                    if !known && utils::is_http_url(&url) {
+                        names.insert(url.to_string(), name);
+                        pending.push(url);
+                    }
+                }
+            }
+            manifest.entries.push(entry);
+        }
+    }
+    manifest.collect();
+    manifest
+}
+
+#[test]
+fn test_extract_links() {
+    let content = std::fs::read_to_string("../data/list.md").unwrap();
+    let links = extract_links(&content);
+    assert!(links.contains(&"https://pastebin.com/raw/gtbKvnE1".to_string()));
+    assert!(links.contains(&"https://神器每日推送.tk/pz.json".to_string()));
+    assert_eq!(
+        links
+            .iter()
+            .filter(|l| *l == "https://pastebin.com/raw/gtbKvnE1")
+            .count(),
+        1
+    );
+    let links =
+        extract_links(r#"见[配置](https://a.com/b.json)，或 {"url":"http://c.com/d.txt"}。"#);
+    assert_eq!(links, vec!["https://a.com/b.json", "http://c.com/d.txt"]);
+
+    let (kind, items) = sniff(r#"{"urls":[{"name":"a","url":"http://a.com/1.json"}]}"#);
+    assert_eq!(kind, LinkKind::Warehouse);
+    assert_eq!(items[0].1, "http://a.com/1.json");
+    assert_eq!(sniff("#EXTM3U\n").0, LinkKind::M3uPlaylist);
+    assert_eq!(
+        sniff("央视,#genre#\nCCTV1,http://a/1.m3u8").0,
+        LinkKind::TxtPlaylist
+    );
+    assert_eq!(
+        sniff(r#"{"sites":[{"key":"a","name":"a","type":1,"api":"http://a"}],"lives":[]}"#).0,
+        LinkKind::Config
+    );
+    assert_eq!(sniff("<html></html>").0, LinkKind::Unknown);
+
+    let entry = |url: &str, kind| ManifestEntry {
+        url: url.to_string(),
+        kind,
+        name: None,
+        children: vec![],
+        error: None,
+    };
+    let mut manifest = Manifest {
+        entries: vec![
+            entry("http://a.com/1.json", LinkKind::Config),
+            entry("http://a.com/2.m3u", LinkKind::M3uPlaylist),
+            entry("http://a.com/3", LinkKind::Unknown),
+        ],
+        ..Default::default()
+    };
+    manifest.collect();
+    assert_eq!(manifest.configs, vec!["http://a.com/1.json"]);
+    assert_eq!(manifest.playlists, vec!["http://a.com/2.m3u"]);
+}
@@ -0,0 +1,251 @@
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tauri::{Runtime, Window};
+use tokio::sync::Notify;
+
+/// 保留的已结束任务数量，用于查询任务状态
+const FINISHED_LIMIT: usize = 50;
+
+static JOBS: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static SEQ: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Cancelled,
+}
+
+/// 检测任务的状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    /// 任务类型, 如: vods、lives、parses、urls
+    pub kind: String,
+    pub status: JobStatus,
+    pub progress: u64,
+    pub total: u64,
+    /// 开始时间(毫秒时间戳)
+    pub started: i64,
+    /// 结束时间(毫秒时间戳)
+    pub finished: Option<i64>,
+}
+
+/// 任务开始的通知，`id`为实际使用的任务id，与请求的id重复时会追加序号
+#[derive(Debug, Clone, Serialize)]
+pub struct StartedPayload {
+    pub requested: Option<String>,
+    pub id: String,
+    pub kind: String,
+}
+
+struct Entry {
+    info: JobInfo,
+    cancel: Arc<Notify>,
+}
+
+/// 检测任务的句柄，用于更新进度
+#[derive(Debug, Clone)]
+pub struct Job {
+    id: String,
+    cancel: Arc<Notify>,
+}
+
+impl Job {
+    /// 登记任务，`id`为空时自动生成
+    /// 与正在执行的任务id重复时追加序号, 如: custom-3，不会覆盖原有任务的取消句柄
+    pub fn new(kind: &str, id: Option<String>) -> Self {
+        let seq = || SEQ.fetch_add(1, Ordering::Relaxed);
+        let base = id
+            .filter(|i| !i.trim().is_empty())
+            .unwrap_or_else(|| format!("{}-{}", kind, seq()));
+        let mut id = base.to_string();
+        let cancel = Arc::new(Notify::new());
+        let mut jobs = JOBS.lock().unwrap();
+        prune(&mut jobs);
+        while jobs
+            .get(&id)
+            .map(|e| e.info.status == JobStatus::Running)
+            .unwrap_or_default()
+        {
+            id = format!("{}-{}", base, seq());
+        }
+        jobs.insert(
+            id.to_string(),
+            Entry {
+                info: JobInfo {
+                    id: id.to_string(),
+                    kind: kind.to_string(),
+                    status: JobStatus::Running,
+                    progress: 0,
+                    total: 0,
+                    started: chrono::Local::now().timestamp_millis(),
+                    finished: None,
+                },
+                cancel: cancel.clone(),
+            },
+        );
+        Self { id, cancel }
+    }
+
+    /// 登记任务并通过`job://started`通知前端实际使用的id
+    pub fn start<R: Runtime>(window: &Window<R>, kind: &str, id: Option<String>) -> Self {
+        let job = Self::new(kind, id.clone());
+        window
+            .emit(
+                "job://started",
+                StartedPayload {
+                    requested: id,
+                    id: job.id().to_string(),
+                    kind: kind.to_string(),
+                },
+            )
+            .ok();
+        job
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// 更新任务进度
+    pub fn progress(&self, progress: u64, total: u64) {
+        if let Some(e) = JOBS.lock().unwrap().get_mut(&self.id) {
+            e.info.progress = progress;
+            e.info.total = total;
+        }
+    }
+
+    fn finish(&self, status: JobStatus) {
+        if let Some(e) = JOBS.lock().unwrap().get_mut(&self.id) {
+            e.info.status = status;
+            e.info.finished = Some(chrono::Local::now().timestamp_millis());
+        }
+    }
+
+    /// 执行任务，任务被取消时返回空，未完成的子任务会随之中止
+    /// 命令被丢弃或任务panic时同样标记为已取消，不会一直处于执行中
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        let _guard = Guard(self);
+        tokio::select! {
+            output = future => {
+                self.finish(JobStatus::Completed);
+                Some(output)
+            }
+            _ = self.cancel.notified() => {
+                self.finish(JobStatus::Cancelled);
+                None
+            }
+        }
+    }
+}
+
+/// 任务未正常结束时标记为已取消
+struct Guard<'a>(&'a Job);
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        // panic时锁可能已中毒，仍然更新状态
+        let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(e) = jobs.get_mut(&self.0.id) {
+            if e.info.status == JobStatus::Running {
+                e.info.status = JobStatus::Cancelled;
+                e.info.finished = Some(chrono::Local::now().timestamp_millis());
+            }
+        }
+    }
+}
+
+/// 移除多余的已结束任务，保留最近结束的任务
+fn prune(jobs: &mut HashMap<String, Entry>) {
+    let mut finished = jobs
+        .values()
+        .filter_map(|e| e.info.finished.map(|f| (f, e.info.id.to_string())))
+        .collect::<Vec<_>>();
+    if finished.len() < FINISHED_LIMIT {
+        return;
+    }
+    finished.sort();
+    for (_, id) in finished.iter().take(finished.len() + 1 - FINISHED_LIMIT) {
+        jobs.remove(id);
+    }
+}
+
+/// 取消正在执行的任务
+pub fn cancel(id: &str) -> bool {
+    match JOBS.lock().unwrap().get(id) {
+        Some(e) if e.info.status == JobStatus::Running => {
+            e.cancel.notify_one();
+            true
+        }
+        _ => false,
+    }
+}
+
+pub fn status(id: &str) -> Option<JobInfo> {
+    JOBS.lock().unwrap().get(id).map(|e| e.info.clone())
+}
+
+/// 正在执行的任务，按开始时间排序
+pub fn running() -> Vec<JobInfo> {
+    let mut items = JOBS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|e| e.info.status == JobStatus::Running)
+        .map(|e| e.info.clone())
+        .collect::<Vec<_>>();
+    items.sort_by_key(|i| i.started);
+    items
+}
+
+#[tokio::test]
+async fn test_cancel_job() {
+    let job = Job::new("urls", None);
+    let id = job.id().to_string();
+    assert!(running().iter().any(|j| j.id == id));
+    let handle = tokio::spawn({
+        let job = job.clone();
+        async move {
+            job.run(async {
+                let _task = super::spawn(tokio::time::sleep(std::time::Duration::from_secs(60)));
+                job.progress(1, 2);
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                1
+            })
+            .await
+        }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(status(&id).unwrap().progress, 1);
+    assert!(cancel(&id));
+    assert_eq!(handle.await.unwrap(), None);
+    assert_eq!(status(&id).unwrap().status, JobStatus::Cancelled);
+    assert!(!cancel(&id));
+
+    let job = Job::new("urls", Some("custom".to_string()));
+    // 正在执行的任务id不会被覆盖
+    let other = Job::new("urls", Some("custom".to_string()));
+    assert_ne!(other.id(), "custom");
+    assert!(cancel(other.id()));
+    assert_eq!(job.run(async { 2 }).await, Some(2));
+    assert_eq!(status("custom").unwrap().status, JobStatus::Completed);
+
+    // 命令被丢弃后任务不再处于执行中
+    let job = Job::new("urls", None);
+    let id = job.id().to_string();
+    let handle = tokio::spawn(async move { job.run(std::future::pending::<()>()).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    handle.abort();
+    assert!(handle.await.is_err());
+    assert_eq!(status(&id).unwrap().status, JobStatus::Cancelled);
+    assert!(!running().iter().any(|j| j.id == id));
+}
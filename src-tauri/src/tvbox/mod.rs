@@ -1,120 +1,118 @@
+pub mod channel;
 pub mod check;
+pub mod checkpoint;
+pub mod diff;
+pub mod epg;
+pub mod filter;
+pub mod group;
+pub mod hls;
+pub mod importer;
+pub mod job;
 pub mod playlist;
 pub mod source;
+pub mod uptime;
 use crate::utils;
 
 use async_trait::async_trait;
 use core::future::Future;
 use core::pin::Pin;
 use core::task::{Context, Poll};
-use std::sync::Arc;
 use tauri::{Runtime, Window};
-use tokio::sync::Mutex;
 
 #[async_trait]
 pub trait Connection {
+    /// 检测的主要地址，用于记录历史可用性
+    fn target(&self) -> Option<String> {
+        None
+    }
     /// 检测是否可以连通
     async fn check(&mut self, quick_mode: bool, skip_ipv6: bool) -> anyhow::Result<bool>;
 }
 
+/// 待检测的地址
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlItem {
+    pub url: String,
+    /// 检测m3u8内容是否有效
+    #[serde(skip)]
+    pub check_m3u8: bool,
+}
+
+#[async_trait]
+impl Connection for UrlItem {
+    fn target(&self) -> Option<String> {
+        Some(self.url.to_string())
+    }
+    async fn check(&mut self, quick_mode: bool, skip_ipv6: bool) -> anyhow::Result<bool> {
+        let i = &self.url;
+        if url::Url::parse(i).is_err() {
+            return Ok(false);
+        }
+        if skip_ipv6 && i.contains("://[") {
+            // http://[ipv6]:port/path?query or https://[ipv6]:port/path?query
+            return Ok(false);
+        }
+        if quick_mode {
+            utils::url_connectivity(i).await
+        } else if self.check_m3u8 {
+            utils::url_m3u8_accessibility(i).await
+        } else {
+            utils::url_accessibility(i).await
+        }
+    }
+}
+
 /// 地址可访问性，
 /// quick_mode开启时检测url服务器是否可连接，
 /// quick_mode关闭时检测url地址是否可以访问
 /// skip_ipv6：跳过ipv6可以加快检测速度
 pub async fn urls_accessibility<R: Runtime>(
     window: Window<R>,
+    job: job::Job,
     urls: Vec<String>,
     quick_mode: bool,
     skip_ipv6: Option<bool>,
     check_m3u8: Option<bool>,
+    resume: Option<checkpoint::Resume>,
 ) -> Vec<String> {
     if urls.is_empty() {
         return vec![];
     }
-    let skip_ipv6 = skip_ipv6.unwrap_or_default();
-    let check_m3u8 = check_m3u8.unwrap_or_default();
-    // 生成一个合理线程数
-    let threads = {
-        let tasks = urls.len();
-        if tasks == 0 {
-            1
-        } else {
-            let threads = num_cpus::get();
-            if tasks > threads * threads {
-                threads
-            } else {
-                let threads = tasks / threads;
-                if threads == 0 {
-                    1
-                } else {
-                    threads
-                }
-            }
-        }
-    };
-    let mut size = urls.len() / threads;
-    if size == 0 {
-        size = urls.len();
-    }
-    let total = urls.len() as u64;
-    let count = Arc::new(Mutex::new(0));
-    // 每50份为一个处理单元
-    let chunck = urls.chunks(size);
-    // 使用多线程处理数据
-    let mut tasks = vec![];
-    window
-        .emit(
-            "urls_accessibility://progress",
-            check::ProgressPayload { progress: 0, total },
-        )
-        .ok();
-    for c in chunck {
-        let c = c.to_vec();
-        let w = window.clone();
-        let cnt = count.clone();
-        let t = spawn(async move {
-            let mut items = vec![];
-            for i in c {
-                if url::Url::parse(&i).is_ok() {
-                    if skip_ipv6 && i.contains("://[") {
-                        // http://[ipv6]:port/path?query or https://[ipv6]:port/path?query
-                        continue;
-                    }
-                    let ok = if quick_mode {
-                        utils::url_connectivity(&i).await.unwrap_or_default()
-                    } else {
-                        if check_m3u8  {
-                            utils::url_m3u8_accessibility(&i).await.unwrap_or_default()
-                        }else{
-                            utils::url_accessibility(&i).await.unwrap_or_default()
-                        }                        
-                    };
-                    if ok {
-                        items.push(i);
-                    }
-                    let mut c = cnt.lock().await;
-                    *c += 1;
-                    w.emit(
-                        "urls_accessibility://progress",
-                        check::ProgressPayload {
-                            progress: *c as u64,
-                            total,
-                        },
-                    )
-                    .ok();
-                }
-            }
-            items
-        });
-        tasks.push(t);
-    }
-    let mut items = vec![];
-    for t in tasks {
-        if let Ok(mut v) = t.await {
-            items.append(&mut v);
+    let stream = urls_stream(
+        urls,
+        quick_mode,
+        skip_ipv6.unwrap_or_default(),
+        check_m3u8.unwrap_or_default(),
+        resume.as_ref(),
+    );
+    check::emit_stream(window, job, "urls_accessibility", stream)
+        .await
+        .into_iter()
+        .filter(|s| s.connectable)
+        .map(|s| s.extra.url)
+        .collect()
+}
+
+/// 以流的方式检测地址可访问性，`resume`不为空时跳过有效期内已检测的地址
+pub fn urls_stream(
+    urls: Vec<String>,
+    quick_mode: bool,
+    skip_ipv6: bool,
+    check_m3u8: bool,
+    resume: Option<&checkpoint::Resume>,
+) -> check::CheckStream<UrlItem> {
+    let threads = check::threads(urls.len(), num_cpus::get());
+    let items = urls
+        .into_iter()
+        .map(|url| UrlItem { url, check_m3u8 })
+        .collect();
+    match resume {
+        Some(r) => {
+            let mode = format!("m3u8:{}", check_m3u8);
+            check::resume_stream(items, threads, quick_mode, skip_ipv6, &mode, r)
         }
+        None => check::check_stream(items, threads, quick_mode, skip_ipv6),
     }
-    items
 }
 
 /// Spawn a new tokio Task and cancel it on drop.
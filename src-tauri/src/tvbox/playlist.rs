@@ -1,6 +1,12 @@
+use super::{channel::Normalizer, group::Classifier};
 use crate::utils;
 use anyhow::Result;
 use m3u8_rs::{AlternativeMedia, MediaSegment, Playlist, VariantStream};
+use once_cell::sync::Lazy;
+
+/// m3u中的属性, 如: tvg-id="CCTV1" 或 x-tvg-url=http://...
+static M3U_ATTR: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r#"([\w-]+)=(?:"([^"]*)"|([^\s"]+))"#).unwrap());
 
 #[derive(Debug, Default, Serialize)]
 pub struct PlaylistCheckResult {
@@ -18,6 +24,15 @@ pub struct PlaylistSource {
     /// 跳过IPv6的URL地址检查
     pub skip_ipv6: Option<bool>,
 }
+
+impl PlaylistSource {
+    /// 自动分组并设置台标，内容会被转换为m3u格式
+    pub fn classify(&mut self, classifier: &Classifier, force: bool) {
+        let mut playlist = LivePlaylist::parse(&self.content);
+        playlist.classify(classifier, force);
+        self.content = playlist.to_m3u();
+    }
+}
 /// TODO 待实现进度功能
 impl PlaylistSource {
     pub fn threads(&self, tasks: usize) -> usize {
@@ -36,7 +51,7 @@ impl PlaylistSource {
 
     pub async fn check(&self) -> Result<PlaylistCheckResult> {
         let content = self.content.as_bytes();
-        match m3u8_rs::parse_playlist_res(&content) {
+        match m3u8_rs::parse_playlist_res(content) {
             Ok(Playlist::MasterPlaylist(mut pl)) => {
                 println!("{:#?}", pl);
                 let count = pl.alternatives.len();
@@ -220,6 +235,230 @@ impl PlaylistSource {
     }
 }
 
+/// 直播频道，对应txt/m3u播放列表中的一项
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PlaylistChannel {
+    pub name: String,
+    pub group: Option<String>,
+    #[serde(rename = "tvgId")]
+    pub tvg_id: Option<String>,
+    #[serde(rename = "tvgName")]
+    pub tvg_name: Option<String>,
+    pub logo: Option<String>,
+    pub urls: Vec<String>,
+}
+
+/// 直播播放列表(txt/m3u)
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LivePlaylist {
+    /// 节目单地址(x-tvg-url)
+    pub epg: Vec<String>,
+    pub channels: Vec<PlaylistChannel>,
+}
+
+impl LivePlaylist {
+    /// 解析txt或m3u格式的直播源
+    pub fn parse(content: &str) -> Self {
+        let content = content.trim_start_matches('\u{feff}');
+        if content.trim_start().starts_with("#EXTM3U") {
+            Self::parse_m3u(content)
+        } else {
+            Self::parse_txt(content)
+        }
+    }
+
+    fn parse_txt(content: &str) -> Self {
+        let mut pl = Self::default();
+        let mut group = None;
+        for line in content.lines() {
+            let line = line.trim();
+            let Some((name, value)) = line.split_once(',') else {
+                continue;
+            };
+            let (name, value) = (name.trim(), value.trim());
+            if value.starts_with("#genre#") {
+                group = Some(name.to_string());
+                continue;
+            }
+            let urls = value
+                .split('#')
+                .map(|u| u.trim().to_string())
+                .filter(|u| !u.is_empty())
+                .collect::<Vec<_>>();
+            if name.is_empty() || urls.is_empty() {
+                continue;
+            }
+            pl.channels.push(PlaylistChannel {
+                name: name.to_string(),
+                group: group.clone(),
+                urls,
+                ..Default::default()
+            });
+        }
+        pl
+    }
+
+    fn parse_m3u(content: &str) -> Self {
+        let mut pl = Self::default();
+        let mut current: Option<PlaylistChannel> = None;
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix("#EXTM3U") {
+                for cap in M3U_ATTR.captures_iter(header) {
+                    let key = cap[1].to_lowercase();
+                    if key == "x-tvg-url" || key == "url-tvg" {
+                        let value = cap.get(2).or(cap.get(3)).map(|m| m.as_str());
+                        pl.epg.extend(
+                            value
+                                .unwrap_or_default()
+                                .split(',')
+                                .map(|u| u.trim().to_string())
+                                .filter(|u| !u.is_empty()),
+                        );
+                    }
+                }
+            } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+                // 频道名称在最后一个不在引号内的逗号之后
+                let mut quoted = false;
+                let mut split = None;
+                for (i, c) in info.char_indices() {
+                    match c {
+                        '"' => quoted = !quoted,
+                        ',' if !quoted => split = Some(i),
+                        _ => {}
+                    }
+                }
+                let (attrs, name) = match split {
+                    Some(i) => (&info[..i], info[i + 1..].trim()),
+                    None => (info, ""),
+                };
+                let mut channel = PlaylistChannel {
+                    name: name.to_string(),
+                    ..Default::default()
+                };
+                for cap in M3U_ATTR.captures_iter(attrs) {
+                    let value = cap
+                        .get(2)
+                        .or(cap.get(3))
+                        .map(|m| m.as_str().to_string())
+                        .filter(|v| !v.is_empty());
+                    match cap[1].to_lowercase().as_str() {
+                        "tvg-id" => channel.tvg_id = value,
+                        "tvg-name" => channel.tvg_name = value,
+                        "tvg-logo" => channel.logo = value,
+                        "group-title" => channel.group = value,
+                        _ => {}
+                    }
+                }
+                current = Some(channel);
+            } else if let Some(group) = line.strip_prefix("#EXTGRP:") {
+                if let Some(c) = current.as_mut() {
+                    c.group = Some(group.trim().to_string());
+                }
+            } else if !line.starts_with('#') {
+                let mut channel = current.take().unwrap_or_default();
+                if channel.name.is_empty() {
+                    channel.name = line.to_string();
+                }
+                channel.urls.push(line.to_string());
+                pl.channels.push(channel);
+            }
+        }
+        pl
+    }
+
+    /// 合并同一频道的多个条目
+    pub fn dedup(&mut self, normalizer: &Normalizer) {
+        let channels = std::mem::take(&mut self.channels);
+        self.channels = normalizer.merge(channels);
+    }
+
+    /// 合并同一频道的多个条目，历史上更稳定的地址排在前面
+    pub async fn dedup_stable(&mut self, normalizer: &Normalizer) {
+        use super::uptime;
+        let channels = std::mem::take(&mut self.channels);
+        let urls = channels.iter().flat_map(|c| c.urls.clone()).collect();
+        let scores = uptime::stabilities(urls).await;
+        self.channels = normalizer.merge_by(channels, |u| {
+            scores.get(u).copied().unwrap_or(uptime::UNKNOWN_STABILITY)
+        });
+    }
+
+    /// 自动分组并设置台标，同一分组的频道会排列在一起
+    pub fn classify(&mut self, classifier: &Classifier, force: bool) {
+        let mut groups: Vec<Option<String>> = vec![];
+        for c in self.channels.iter_mut() {
+            classifier.classify(c, force);
+            if !groups.contains(&c.group) {
+                groups.push(c.group.clone());
+            }
+        }
+        self.channels.sort_by_key(|c| groups.iter().position(|g| *g == c.group));
+    }
+
+    /// 输出为m3u格式
+    pub fn to_m3u(&self) -> String {
+        let mut out = String::from("#EXTM3U");
+        if !self.epg.is_empty() {
+            out.push_str(&format!(" x-tvg-url=\"{}\"", self.epg.join(",")));
+        }
+        out.push('\n');
+        for c in &self.channels {
+            let mut attrs = String::new();
+            let fields = [
+                ("tvg-id", &c.tvg_id),
+                ("tvg-name", &c.tvg_name),
+                ("tvg-logo", &c.logo),
+                ("group-title", &c.group),
+            ];
+            for (key, value) in fields {
+                if let Some(value) = value {
+                    attrs.push_str(&format!(" {}=\"{}\"", key, value));
+                }
+            }
+            for url in &c.urls {
+                out.push_str(&format!("#EXTINF:-1{},{}\n{}\n", attrs, c.name, url));
+            }
+        }
+        out
+    }
+
+    /// 输出为txt格式
+    pub fn to_txt(&self) -> String {
+        let mut out = String::new();
+        let mut group: Option<&String> = None;
+        for c in &self.channels {
+            if c.group.is_some() && c.group.as_ref() != group {
+                group = c.group.as_ref();
+                out.push_str(&format!("{},#genre#\n", group.unwrap()));
+            }
+            for url in &c.urls {
+                out.push_str(&format!("{},{}\n", c.name, url));
+            }
+        }
+        out
+    }
+}
+
+#[test]
+fn test_live_playlist() {
+    let content = std::fs::read_to_string("../data/test.m3u8").unwrap();
+    let pl = LivePlaylist::parse(&content);
+    assert_eq!(pl.epg.len(), 2);
+    assert_eq!(pl.channels.len(), 248);
+    let c = pl.channels.iter().find(|c| c.name == "CCTV16-4K").unwrap();
+    assert_eq!(c.tvg_id.as_deref(), Some("CCTV16"));
+
+    let txt = "央视,#genre#\nCCTV1,http://a/1.m3u8#http://b/1.m3u8\n";
+    let pl = LivePlaylist::parse(txt);
+    assert_eq!(pl.channels[0].group.as_deref(), Some("央视"));
+    assert_eq!(pl.channels[0].urls.len(), 2);
+    assert!(pl.to_m3u().contains("group-title=\"央视\",CCTV1\nhttp://b/1.m3u8"));
+}
+
 #[tokio::test]
 async fn test_m3u8() {
     let file = "../data/test.m3u8";
@@ -0,0 +1,405 @@
+//! drpy规则文件的静态分析，不执行js，只读取`rule = {...}`中以字符串字面量给出的字段
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+/// 检测搜索接口时使用的关键字
+const SEARCH_KEYWORD: &str = "我";
+
+static RULE: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"^rule\s*=").unwrap());
+/// py等没有rule对象的爬虫中常见的站点地址变量
+static HOST_VAR: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(r#"(?i)\b(?:host|site_?url|base_?url)\s*[=:]\s*['"](https?://[^'"\s]+)['"]"#)
+        .unwrap()
+});
+static FIRST_URL: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new("https?://[0-9A-Za-z.%-]+").unwrap());
+
+/// drpy规则中与站点地址有关的字段
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub title: Option<String>,
+    pub host: Option<String>,
+    /// 分类地址，通常相对于host
+    pub url: Option<String>,
+    /// 搜索地址，`**`为关键字，`fypage`为页码
+    pub search_url: Option<String>,
+    pub home_url: Option<String>,
+}
+
+impl Rule {
+    /// 解析规则文件，没有rule对象时为空
+    pub fn parse(script: &str) -> Option<Self> {
+        let body = rule_object(script)?;
+        let fields = string_fields(body);
+        let get = |k: &str| fields.get(k).filter(|v| !v.trim().is_empty()).cloned();
+        Some(Self {
+            title: get("title"),
+            host: get("host"),
+            url: get("url"),
+            search_url: get("searchUrl"),
+            home_url: get("homeUrl"),
+        })
+    }
+
+    /// 相对于host的完整地址
+    pub fn resolve(&self, path: &str) -> Option<String> {
+        let path = path.trim();
+        if crate::utils::is_http_url(path) {
+            return Some(path.to_string());
+        }
+        let host = url::Url::parse(self.host.as_ref()?.trim()).ok()?;
+        host.join(path).ok().map(|u| u.to_string())
+    }
+
+    /// 站点首页
+    pub fn home(&self) -> Option<String> {
+        self.home_url
+            .as_ref()
+            .and_then(|h| self.resolve(h))
+            .or_else(|| self.host.as_ref().and_then(|h| self.resolve(h)))
+            .or_else(|| {
+                let url = url::Url::parse(self.url.as_ref()?).ok()?;
+                Some(url.origin().ascii_serialization())
+            })
+    }
+
+    /// 搜索第一页的地址，去除`;post`等附加参数
+    pub fn search(&self, keyword: &str) -> Option<String> {
+        let search = self.search_url.as_ref()?;
+        let search = search.split(';').next().unwrap_or_default();
+        let search = search.replace("**", keyword).replace("fypage", "1");
+        self.resolve(&search)
+    }
+
+    /// 检测时使用的搜索地址，`;post`的接口只接受POST请求，不检测
+    pub fn search_probe(&self) -> Option<String> {
+        let post = self
+            .search_url
+            .as_ref()?
+            .split(';')
+            .skip(1)
+            .any(|p| p.trim().eq_ignore_ascii_case("post"));
+        if post {
+            return None;
+        }
+        self.search(SEARCH_KEYWORD)
+    }
+}
+
+/// 没有rule对象时推测站点地址，优先使用host等变量，否则使用第一个地址
+pub fn guess_host(script: &str) -> Option<String> {
+    HOST_VAR
+        .captures(script)
+        .and_then(|c| url::Url::parse(&c[1]).ok())
+        .map(|u| u.origin().ascii_serialization())
+        .or_else(|| FIRST_URL.find(script).map(|m| m.as_str().to_string()))
+}
+
+/// `rule = {...}`中大括号内的内容，跳过注释与字符串中的`rule =`
+fn rule_object(script: &str) -> Option<&str> {
+    let mut starts = vec![];
+    Scanner::new(script).walk(|i, c, _| {
+        let word = script[..i]
+            .chars()
+            .next_back()
+            .map(|p| p.is_alphanumeric() || p == '_' || p == '$')
+            .unwrap_or_default();
+        if c == 'r' && !word {
+            if let Some(m) = RULE.find(&script[i..]) {
+                starts.push(i + m.end());
+            }
+        }
+        true
+    });
+    for end in starts {
+        let rest = &script[end..];
+        // 跳过 rule == xxx
+        if rest.starts_with('=') {
+            continue;
+        }
+        // 兼容 rule = Object.assign(muban.xxx, {...})
+        let Some(start) = statement_brace(rest) else {
+            continue;
+        };
+        let end = Scanner::new(&rest[start..]).matching_brace()?;
+        return Some(&rest[start + 1..start + end]);
+    }
+    None
+}
+
+/// 赋值语句中第一个`{`的位置，语句在`;`或完整表达式后的换行处结束, 如: rule = muban.mxpro;
+fn statement_brace(rest: &str) -> Option<usize> {
+    let mut pos = None;
+    let mut last = '=';
+    Scanner::new(rest).walk(|i, c, depth| {
+        if depth < 0 || (depth == 0 && c == ';') {
+            return false;
+        }
+        if c == '{' {
+            pos = Some(i);
+            return false;
+        }
+        let complete = last.is_alphanumeric() || matches!(last, '_' | '$' | ')' | ']');
+        if depth == 0 && c == '\n' && complete {
+            return false;
+        }
+        if !c.is_whitespace() {
+            last = c;
+        }
+        true
+    });
+    pos
+}
+
+/// 对象第一层中值为字符串字面量的字段，函数、对象等其他值会被跳过
+fn string_fields(body: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    for entry in Scanner::new(body).split_top_level(',') {
+        let Some(colon) = Scanner::new(entry).find_top_level(':') else {
+            continue;
+        };
+        // 去除字段前的注释行
+        let key = entry[..colon]
+            .lines()
+            .map(str::trim)
+            .rfind(|l| !l.is_empty() && !l.starts_with("//"))
+            .unwrap_or_default()
+            .trim_matches(|c| c == '\'' || c == '"');
+        if let Some(value) = string_literal(entry[colon + 1..].trim()) {
+            fields.insert(key.to_string(), value);
+        }
+    }
+    fields
+}
+
+/// 解析字符串字面量，包含插值的模板字符串返回空
+fn string_literal(value: &str) -> Option<String> {
+    let quote = value.chars().next()?;
+    if !matches!(quote, '\'' | '"' | '`') || value.len() < 2 || !value.ends_with(quote) {
+        return None;
+    }
+    let inner = &value[1..value.len() - 1];
+    if quote == '`' && inner.contains("${") {
+        return None;
+    }
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            if c == quote {
+                // 字符串拼接等表达式
+                return None;
+            }
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    Some(out)
+}
+
+/// 上一个有效字符之后应为表达式，此时的`/`是正则的开始而不是除号
+fn expects_operand(prev: char) -> bool {
+    "(,=:[!&|?{};+-*%<>~^".contains(prev)
+}
+
+/// 跳过字符串、正则与注释的简单扫描器
+struct Scanner<'a> {
+    text: &'a str,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text }
+    }
+
+    /// 依次返回不在字符串、正则与注释中的字符及其位置与嵌套深度
+    fn walk<F: FnMut(usize, char, i32) -> bool>(&self, mut f: F) {
+        let bytes = self.text.as_bytes();
+        let mut depth = 0;
+        // 上一个有效字符，用于区分正则与除号
+        let mut prev = None;
+        let mut iter = self.text.char_indices().peekable();
+        while let Some((i, c)) = iter.next() {
+            match c {
+                '\'' | '"' | '`' => {
+                    let mut escaped = false;
+                    for (_, n) in iter.by_ref() {
+                        if escaped {
+                            escaped = false;
+                        } else if n == '\\' {
+                            escaped = true;
+                        } else if n == c {
+                            break;
+                        }
+                    }
+                    prev = Some(c);
+                    continue;
+                }
+                '/' if bytes.get(i + 1) == Some(&b'/') => {
+                    for (_, n) in iter.by_ref() {
+                        if n == '\n' {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                '/' if bytes.get(i + 1) == Some(&b'*') => {
+                    iter.next();
+                    let mut star = false;
+                    for (_, n) in iter.by_ref() {
+                        if star && n == '/' {
+                            break;
+                        }
+                        star = n == '*';
+                    }
+                    continue;
+                }
+                '/' if prev.map(expects_operand).unwrap_or(true) => {
+                    // 正则字面量, 如: /'(.*?)'/g，字符类中的/不结束正则
+                    let mut escaped = false;
+                    let mut class = false;
+                    while let Some((_, n)) = iter.next_if(|(_, n)| *n != '\n') {
+                        if escaped {
+                            escaped = false;
+                        } else if n == '\\' {
+                            escaped = true;
+                        } else if n == '[' {
+                            class = true;
+                        } else if n == ']' {
+                            class = false;
+                        } else if n == '/' && !class {
+                            break;
+                        }
+                    }
+                    prev = Some(c);
+                    continue;
+                }
+                _ => {}
+            }
+            if !c.is_whitespace() {
+                prev = Some(c);
+            }
+            if matches!(c, '}' | ']' | ')') {
+                depth -= 1;
+            }
+            if !f(i, c, depth) {
+                return;
+            }
+            if matches!(c, '{' | '[' | '(') {
+                depth += 1;
+            }
+        }
+    }
+
+    /// 以`{`开头时，对应的`}`的位置
+    fn matching_brace(&self) -> Option<usize> {
+        let mut end = None;
+        self.walk(|i, c, depth| {
+            if c == '}' && depth == 0 {
+                end = Some(i);
+                return false;
+            }
+            true
+        });
+        end
+    }
+
+    fn find_top_level(&self, sep: char) -> Option<usize> {
+        let mut pos = None;
+        self.walk(|i, c, depth| {
+            if c == sep && depth == 0 {
+                pos = Some(i);
+                return false;
+            }
+            true
+        });
+        pos
+    }
+
+    fn split_top_level(&self, sep: char) -> Vec<&'a str> {
+        let mut parts = vec![];
+        let mut start = 0;
+        self.walk(|i, c, depth| {
+            if c == sep && depth == 0 {
+                parts.push(&self.text[start..i]);
+                start = i + c.len_utf8();
+            }
+            true
+        });
+        parts.push(&self.text[start..]);
+        parts
+    }
+}
+
+#[test]
+fn test_drpy_rule() {
+    let script = r#"
+    // 引用 https://cdn.jsdelivr.net/npm/cheerio
+    var rule = {
+        title: '测试',
+        host: 'https://www.example.com',
+        // url: 'https://wrong.example.com',
+        url: '/vodshow/fyclass--------fypage---.html',
+        "searchUrl": "/search/**----------fypage---.html;post",
+        homeUrl: `/`,
+        headers: { 'User-Agent': 'MOBILE_UA' },
+        class_parse: '.nav li;a&&Text;a&&href;/(\\d+).html',
+        推荐: '*',
+        lazy: `js:
+            let html = request(input);
+            input = 'https://cdn.example.net/' + html
+        `,
+        一级: function() { return { host: 'https://wrong.example.org' } },
+        filter: 'H4sIAAAAAAAAA',
+    }
+    "#;
+    let rule = Rule::parse(script).unwrap();
+    assert_eq!(rule.title.as_deref(), Some("测试"));
+    assert_eq!(rule.host.as_deref(), Some("https://www.example.com"));
+    assert_eq!(rule.home().unwrap(), "https://www.example.com/");
+    assert_eq!(
+        rule.search("abc").unwrap(),
+        "https://www.example.com/search/abc----------1---.html"
+    );
+    assert_eq!(rule.search_probe(), None);
+    assert_eq!(
+        rule.url.as_deref(),
+        Some("/vodshow/fyclass--------fypage---.html")
+    );
+
+    let rule = Rule::parse(
+        "var rule = Object.assign(muban.mxpro, {host: \"http://a.com\" + '/x', url: 'http://b.com/list'});",
+    )
+    .unwrap();
+    assert_eq!(rule.host, None);
+    assert_eq!(rule.home().unwrap(), "http://b.com");
+    assert!(Rule::parse("if (rule == null) {}").is_none());
+    // 注释、字符串中的rule与没有对象的赋值都会被跳过
+    let script = "// rule = {host: 'http://c.com'}\nlet s = 'rule = {}';\nvar rule = muban.mxpro;\nvar rule2 = {host: 'http://d.com'};\nconst rule = {\n  host: 'http://f.com',\n  url: '/list?wd=**',\n  searchUrl: '/s?wd=**&pg=fypage',\n};";
+    let rule = Rule::parse(script).unwrap();
+    assert_eq!(rule.host.as_deref(), Some("http://f.com"));
+    assert_eq!(
+        rule.search_probe().unwrap(),
+        "http://f.com/s?wd=%E6%88%91&pg=1"
+    );
+    let rule = Rule::parse("var rule = muban.mxpro\nvar other = {host: 'http://e.com'}");
+    assert!(rule.is_none());
+    // 正则中的引号与括号不影响字段的解析，除号不会被当作正则
+    let script = "var rule = {\n  lazy: function() { let m = html.match(/'(.*?)'/g); return m[0] / 2 },\n  host: 'http://g.com',\n  class_parse: 'a;b',\n  search: x => x.replace(/[/}]/, ''),\n  url: '/list',\n};";
+    let rule = Rule::parse(script).unwrap();
+    assert_eq!(rule.host.as_deref(), Some("http://g.com"));
+    assert_eq!(rule.url.as_deref(), Some("/list"));
+
+    let py = "import x\nclass Spider:\n  doc = 'https://docs.python.org'\n  siteUrl = 'https://py.example.com/api'\n";
+    assert_eq!(guess_host(py).unwrap(), "https://py.example.com");
+    assert_eq!(
+        guess_host("fetch('https://cdn.x.com/a.js')").unwrap(),
+        "https://cdn.x.com"
+    );
+}
@@ -0,0 +1,194 @@
+use super::base_url;
+use crate::utils;
+use base64::{engine::general_purpose, Engine};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+/// 判断为base64内容的最小长度，避免把普通的短字符串当作base64
+const BASE64_MIN_LEN: usize = 24;
+
+/// 图片等资源的字段名, 如: logo、img、wallpaper
+static ASSET_KEY: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(
+        r"(?i)logo|icon|img|image|pic|wallpaper|background|cover|poster|thumb|图标|图片|壁纸|背景",
+    )
+    .unwrap()
+});
+/// 图片的扩展名
+static ASSET_EXT: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r"(?i)\.(?:png|jpe?g|gif|webp|svg|ico|bmp)(?:[?#]|$)").unwrap());
+
+/// 站点或解析的扩展数据(ext)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ext {
+    /// http(s)或本地文件地址
+    Url(String),
+    /// 相对于配置文件的路径, 如: ./js/a.js
+    Path(String),
+    /// base64编码的内容
+    Base64(String),
+    /// 其他字符串, 如: 站点标识、关键字
+    Text(String),
+    /// json对象或数组，其中可能包含地址, 如: drpy、XBPQ的配置
+    Object(Value),
+    /// 数字、布尔等
+    Other(Value),
+}
+
+impl Ext {
+    pub fn parse(value: Value) -> Self {
+        match value {
+            Value::String(s) => Self::parse_str(s),
+            v @ (Value::Object(_) | Value::Array(_)) => Self::Object(v),
+            v => Self::Other(v),
+        }
+    }
+
+    fn parse_str(s: String) -> Self {
+        if utils::is_http_url(&s) || s.starts_with("file:") {
+            Self::Url(s)
+        } else if s.starts_with('.') || s.starts_with('/') {
+            Self::Path(s)
+        } else if decode_base64(&s).is_some() {
+            Self::Base64(s)
+        } else {
+            Self::Text(s)
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Url(s) | Self::Path(s) | Self::Base64(s) | Self::Text(s) => {
+                Value::String(s.to_string())
+            }
+            Self::Object(v) | Self::Other(v) => v.clone(),
+        }
+    }
+
+    /// 将相对路径转换为完整地址
+    /// 对象中只转换`./`与`../`开头的路径，以`/`开头的通常是相对于站点的地址, 如: XBPQ的分类url
+    pub fn base(&mut self, base: &str) {
+        match self {
+            Self::Path(p) => {
+                let url = base_url(base, p);
+                if url != *p {
+                    *self = Self::parse_str(url);
+                }
+            }
+            Self::Object(v) => rebase_value(v, base),
+            _ => {}
+        }
+    }
+
+    /// 引用的http地址，不重复，用于检测站点是否可用
+    /// 对象中台标、图片、壁纸等资源的地址失效不影响站点，不包含在内
+    pub fn urls(&self) -> Vec<String> {
+        let mut urls = vec![];
+        match self {
+            Self::Url(u) if utils::is_http_url(u) => urls.push(u.to_string()),
+            Self::Object(v) => collect_urls(v, "", &mut urls),
+            Self::Base64(s) => {
+                if let Some(v) = decode_base64(s).and_then(|c| serde_json::from_str(&c).ok()) {
+                    collect_urls(&v, "", &mut urls);
+                }
+            }
+            _ => {}
+        }
+        urls
+    }
+}
+
+impl serde::Serialize for Ext {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.to_value().serialize(s)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Ext {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Value::deserialize(d).map(Self::parse)
+    }
+}
+
+/// 解码base64，内容需要是有效的utf-8文本
+fn decode_base64(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.len() < BASE64_MIN_LEN || s.contains(char::is_whitespace) {
+        return None;
+    }
+    let s = s.trim_end_matches('=');
+    let buff = general_purpose::STANDARD_NO_PAD
+        .decode(s)
+        .or_else(|_| general_purpose::URL_SAFE_NO_PAD.decode(s))
+        .ok()?;
+    let text = String::from_utf8(buff).ok()?;
+    // 解码后应该是可读的文本
+    if text.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+        return None;
+    }
+    Some(text)
+}
+
+fn rebase_value(value: &mut Value, base: &str) {
+    match value {
+        Value::String(s) if s.starts_with("./") || s.starts_with("../") => {
+            *s = base_url(base, s);
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| rebase_value(v, base)),
+        Value::Object(map) => map.values_mut().for_each(|v| rebase_value(v, base)),
+        _ => {}
+    }
+}
+
+/// `key`为值所在的字段名，数组中的值使用数组的字段名
+fn collect_urls(value: &Value, key: &str, urls: &mut Vec<String>) {
+    if ASSET_KEY.is_match(key) {
+        return;
+    }
+    match value {
+        Value::String(s) => {
+            let s = s.trim();
+            let asset = ASSET_EXT.is_match(s);
+            if utils::is_http_url(s) && !asset && !urls.iter().any(|u| u == s) {
+                urls.push(s.to_string());
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_urls(v, key, urls)),
+        Value::Object(map) => map.iter().for_each(|(k, v)| collect_urls(v, k, urls)),
+        _ => {}
+    }
+}
+
+#[test]
+fn test_ext() {
+    let ext = |v: Value| Ext::parse(v);
+    assert!(matches!(ext(Value::from("https://a/b.js")), Ext::Url(_)));
+    assert!(matches!(ext(Value::from("./js/a.js")), Ext::Path(_)));
+    assert!(matches!(ext(Value::from("csp_Test")), Ext::Text(_)));
+    assert!(matches!(ext(Value::from(1)), Ext::Other(_)));
+    let encoded = general_purpose::STANDARD.encode(r#"{"host":"https://b.com/","x":1}"#);
+    let b64 = ext(Value::from(encoded.as_str()));
+    assert!(matches!(b64, Ext::Base64(_)));
+    assert_eq!(b64.urls(), vec!["https://b.com/"]);
+
+    let mut path = ext(Value::from("./js/a.js"));
+    path.base("http://c.com/cfg/tv.json");
+    assert_eq!(path, Ext::Url("http://c.com/cfg/js/a.js".to_string()));
+
+    let mut obj = ext(serde_json::json!({
+        "主页url": "https://d.com",
+        "分类url": "/vodshow/{cateId}.html",
+        "rule": "../rules/d.js",
+        "list": [{"img": "https://img.d.com/a"}, "https://d.com"],
+        "logo": "https://d.com/logo",
+        "bg": "https://img.d.com/bg.jpg?v=1",
+    }));
+    obj.base("http://c.com/cfg/tv.json");
+    let v = obj.to_value();
+    assert_eq!(v["rule"], "http://c.com/rules/d.js");
+    assert_eq!(v["分类url"], "/vodshow/{cateId}.html");
+    let mut urls = obj.urls();
+    urls.sort();
+    assert_eq!(urls, vec!["http://c.com/rules/d.js", "https://d.com"]);
+    assert_eq!(serde_json::to_value(&obj).unwrap(), v);
+}
@@ -0,0 +1,157 @@
+//! 配置中以整数表示的类型，序列化时保持原始的数值，未知的值保存在`Other`中
+use serde_aux::prelude::*;
+
+macro_rules! int_enum {
+    ($(#[$meta:meta])* $name:ident { $($(#[$vmeta:meta])* $variant:ident = $value:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$vmeta])* $variant,)*
+            /// 未知的类型，保留原始值
+            Other(i32),
+        }
+
+        impl From<i32> for $name {
+            fn from(v: i32) -> Self {
+                match v {
+                    $($value => Self::$variant,)*
+                    v => Self::Other(v),
+                }
+            }
+        }
+
+        impl From<$name> for i32 {
+            fn from(v: $name) -> i32 {
+                match v {
+                    $($name::$variant => $value,)*
+                    $name::Other(v) => v,
+                }
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_i32((*self).into())
+            }
+        }
+
+        /// 兼容数字与数字字符串, 如: 3、"3"
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                deserialize_number_from_string::<i32, D>(d).map(Self::from)
+            }
+        }
+
+        impl $name {
+            /// 用于`Option`字段，null与空字符串为`None`, 如: "playerType": ""
+            #[allow(dead_code)]
+            pub fn deserialize_option<'de, D: serde::Deserializer<'de>>(
+                d: D,
+            ) -> Result<Option<Self>, D::Error> {
+                deserialize_option_number_from_string::<i32, D>(d).map(|v| v.map(Self::from))
+            }
+        }
+    };
+}
+
+int_enum! {
+    /// 点播站点类型
+    VodType {
+        Xml = 0,
+        Json = 1,
+        /// 爬虫(jar/js/py)
+        Spider = 3,
+        /// 远程站点
+        Remote = 4,
+    }
+}
+
+int_enum! {
+    /// 播放器类型
+    PlayerType {
+        /// 以参数设置页面的为准
+        Auto = -1,
+        System = 0,
+        Ijk = 1,
+        Exo = 2,
+        MxPlayer = 10,
+    }
+}
+
+int_enum! {
+    /// 直播类型
+    LiveType {
+        /// txt/m3u直播源
+        Playlist = 0,
+        /// 爬虫(jar/js/py)
+        Spider = 3,
+    }
+}
+
+int_enum! {
+    /// 解析类型
+    #[derive(Default)]
+    ParseType {
+        /// 网页嗅探
+        #[default]
+        Sniff = 0,
+        Json = 1,
+        /// json扩展
+        JsonExt = 2,
+        /// json聚合
+        JsonMix = 3,
+        /// 超级解析
+        Super = 4,
+    }
+}
+
+impl VodType {
+    /// 苹果CMS等采集接口
+    pub fn is_cms(&self) -> bool {
+        matches!(self, Self::Xml | Self::Json)
+    }
+
+    /// 盒子可以搜索的类型，搜索只支持采集接口与爬虫，远程站点与未知的类型不会被加入搜索
+    pub fn is_searchable(&self) -> bool {
+        matches!(self, Self::Xml | Self::Json | Self::Spider)
+    }
+}
+
+impl ParseType {
+    /// 返回json结果的解析
+    pub fn is_json(&self) -> bool {
+        matches!(self, Self::Json | Self::JsonExt)
+    }
+}
+
+#[test]
+fn test_int_enum() {
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Item {
+        #[serde(rename = "type")]
+        kind: VodType,
+        #[serde(default)]
+        parse: ParseType,
+        #[serde(default, deserialize_with = "PlayerType::deserialize_option")]
+        player: Option<PlayerType>,
+    }
+    let item: Item = serde_json::from_str(r#"{"type":"3","player":10}"#).unwrap();
+    assert_eq!(item.kind, VodType::Spider);
+    assert_eq!(item.parse, ParseType::Sniff);
+    assert_eq!(item.player, Some(PlayerType::MxPlayer));
+    let item: Item = serde_json::from_str(r#"{"type":7,"parse":2,"player":-1}"#).unwrap();
+    assert_eq!(item.kind, VodType::Other(7));
+    assert!(item.parse.is_json());
+    assert_eq!(item.player, Some(PlayerType::Auto));
+    assert_eq!(
+        serde_json::to_string(&item).unwrap(),
+        r#"{"type":7,"parse":2,"player":-1}"#
+    );
+    let item: Item = serde_json::from_str(r#"{"type":0,"player":""}"#).unwrap();
+    assert_eq!(item.player, None);
+    assert!(serde_json::from_str::<Item>(r#"{"type":"x"}"#).is_err());
+    assert!(VodType::from(1).is_cms());
+    assert!(VodType::Spider.is_searchable());
+    assert!(!VodType::Remote.is_searchable());
+    assert_eq!(i32::from(LiveType::Other(5)), 5);
+}
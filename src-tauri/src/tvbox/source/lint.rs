@@ -0,0 +1,469 @@
+//! 配置的静态检查，不访问网络，只找出会导致盒子加载失败或功能异常的配置
+use super::{
+    ext::Ext,
+    kind::{LiveType, ParseType, VodType},
+    Source,
+};
+use crate::utils;
+use std::collections::HashMap;
+
+/// ijk选项的分类: 1 format、2 codec、3 sws、4 player
+const IJK_CATEGORIES: [i32; 4] = [1, 2, 3, 4];
+
+/// 问题的严重程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// 可以加载，但可能不是预期的效果
+    Warning,
+    /// 盒子加载或使用时会出错
+    Error,
+}
+
+/// 一个问题，`path`为问题所在的json路径, 如: $.sites[3].api
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Issue {
+    pub path: String,
+    pub severity: Severity,
+    /// 问题类型, 如: duplicate-key
+    pub code: String,
+    pub message: String,
+}
+
+impl Issue {
+    fn new(path: String, severity: Severity, code: &str, message: String) -> Self {
+        Self {
+            path,
+            severity,
+            code: code.to_string(),
+            message,
+        }
+    }
+}
+
+/// 检查结果，问题按在配置中出现的顺序排列
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Report {
+    pub errors: usize,
+    pub warnings: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.errors == 0
+    }
+
+    /// 每个问题一行: 级别 路径 说明
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for i in &self.issues {
+            let level = match i.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            out.push_str(&format!("{:<7} {} {}\n", level, i.path, i.message));
+        }
+        out.push_str(&format!(
+            "{} 个错误, {} 个警告\n",
+            self.errors, self.warnings
+        ));
+        out
+    }
+}
+
+/// 检查配置，相对地址需要事先通过`Source::base`转换，否则会被当作问题
+pub fn lint(source: &Source) -> Report {
+    let mut lint = Lint::default();
+    lint.sites(source);
+    lint.lives(source);
+    lint.parses(source);
+    lint.rules(source);
+    lint.ijk(source);
+    if let Some(spider) = source.spider.as_deref() {
+        lint.relative("$.spider".to_string(), spider);
+        lint.local_file("$.spider".to_string(), spider);
+    }
+    let issues = lint.issues;
+    Report {
+        errors: issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count(),
+        warnings: issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+            .count(),
+        issues,
+    }
+}
+
+#[derive(Default)]
+struct Lint {
+    issues: Vec<Issue>,
+}
+
+impl Lint {
+    fn error(&mut self, path: String, code: &str, message: String) {
+        self.issues
+            .push(Issue::new(path, Severity::Error, code, message));
+    }
+
+    fn warning(&mut self, path: String, code: &str, message: String) {
+        self.issues
+            .push(Issue::new(path, Severity::Warning, code, message));
+    }
+
+    fn sites(&mut self, source: &Source) {
+        let mut keys: HashMap<&str, usize> = HashMap::new();
+        for (i, site) in source.sites.iter().enumerate() {
+            let path = format!("$.sites[{}]", i);
+            let key = site.key.trim();
+            if key.is_empty() {
+                self.error(format!("{}.key", path), "empty-key", "key为空".to_string());
+            } else if let Some(first) = keys.get(key) {
+                self.error(
+                    format!("{}.key", path),
+                    "duplicate-key",
+                    format!(
+                        "key `{}` 与 $.sites[{}] 重复，盒子只会保留其中一个",
+                        key, first
+                    ),
+                );
+            } else {
+                keys.insert(key, i);
+            }
+
+            let api = site.api.trim();
+            if api.is_empty() {
+                self.error(
+                    format!("{}.api", path),
+                    "empty-api",
+                    "接口地址为空".to_string(),
+                );
+            }
+            if let VodType::Other(t) = site.src_type {
+                self.error(
+                    format!("{}.type", path),
+                    "unknown-type",
+                    format!("未知的站点类型: {}，可用的类型为0、1、3、4", t),
+                );
+            }
+            if !site.src_type.is_searchable() {
+                if site.searchable != 0 {
+                    self.warning(
+                        format!("{}.searchable", path),
+                        "unsearchable-type",
+                        "该类型的站点不支持搜索，应设置为0".to_string(),
+                    );
+                }
+                if site.quick_search != 0 {
+                    self.warning(
+                        format!("{}.quickSearch", path),
+                        "unsearchable-type",
+                        "该类型的站点不支持快速搜索，应设置为0".to_string(),
+                    );
+                }
+            } else if site.quick_search != 0 && site.searchable == 0 {
+                self.warning(
+                    format!("{}.quickSearch", path),
+                    "quick-search-without-search",
+                    "站点不可搜索时快速搜索不会生效".to_string(),
+                );
+            }
+
+            if site.src_type.is_cms() && !api.is_empty() && !utils::is_http_url(api) {
+                self.relative(format!("{}.api", path), api);
+                if !api.starts_with('.') && !api.starts_with('/') {
+                    self.error(
+                        format!("{}.api", path),
+                        "invalid-api",
+                        "采集接口必须是http地址".to_string(),
+                    );
+                }
+            }
+            if site.src_type == VodType::Spider {
+                self.spider(&path, site.api.as_str(), site.jar.as_deref(), source);
+            }
+            if let Some(jar) = site.jar.as_deref() {
+                self.relative(format!("{}.jar", path), jar);
+                self.local_file(format!("{}.jar", path), jar);
+            }
+            if let Some(Ext::Path(p)) = site.ext.as_ref() {
+                self.relative(format!("{}.ext", path), p);
+            }
+        }
+    }
+
+    /// 爬虫站点的api为js/py地址或jar中的类名, 如: csp_Bili，类名需要有可用的jar
+    fn spider(&mut self, path: &str, api: &str, jar: Option<&str>, source: &Source) {
+        let api = api.trim();
+        if api.is_empty() {
+            return;
+        }
+        let script = [".js", ".py"]
+            .iter()
+            .any(|e| api.split('?').next().unwrap_or_default().ends_with(e));
+        if script || utils::is_http_url(api) {
+            self.relative(format!("{}.api", path), api);
+            self.local_file(format!("{}.api", path), api);
+            return;
+        }
+        let has_jar = jar
+            .or(source.spider.as_deref())
+            .map(|j| !j.trim().is_empty());
+        if !has_jar.unwrap_or_default() {
+            self.error(
+                format!("{}.api", path),
+                "unresolved-spider",
+                format!(
+                    "爬虫 `{}` 没有可用的jar，需要设置站点的jar或全局的spider",
+                    api
+                ),
+            );
+        }
+    }
+
+    fn lives(&mut self, source: &Source) {
+        for (i, live) in source.lives.iter().enumerate() {
+            let path = format!("$.lives[{}]", i);
+            let url = live.url.as_deref().map(str::trim).filter(|u| !u.is_empty());
+            let channels = live.channels.as_ref().filter(|c| !c.is_empty());
+            if url.is_none() && channels.is_none() {
+                self.error(
+                    path.clone(),
+                    "empty-live",
+                    "直播没有url也没有channels".to_string(),
+                );
+            }
+            if let Some(LiveType::Other(t)) = live.src_type {
+                self.warning(
+                    format!("{}.type", path),
+                    "unknown-type",
+                    format!("未知的直播类型: {}，可用的类型为0、3", t),
+                );
+            }
+            if let Some(url) = url {
+                self.relative(format!("{}.url", path), url);
+                self.local_file(format!("{}.url", path), url);
+            }
+            for (j, c) in channels.into_iter().flatten().enumerate() {
+                for (k, u) in c.urls.iter().enumerate() {
+                    self.relative(format!("{}.channels[{}].urls[{}]", path, j, k), u);
+                }
+            }
+        }
+    }
+
+    fn parses(&mut self, source: &Source) {
+        for (i, parse) in source.parses.iter().flatten().enumerate() {
+            let path = format!("$.parses[{}]", i);
+            if let ParseType::Other(t) = parse.src_type {
+                self.warning(
+                    format!("{}.type", path),
+                    "unknown-type",
+                    format!("未知的解析类型: {}，可用的类型为0~4", t),
+                );
+            }
+            // 聚合解析与超级解析可以没有地址
+            let url = parse.url.trim();
+            if url.is_empty() && !matches!(parse.src_type, ParseType::JsonMix | ParseType::Super) {
+                self.error(
+                    format!("{}.url", path),
+                    "empty-url",
+                    "解析地址为空".to_string(),
+                );
+            }
+            self.relative(format!("{}.url", path), url);
+            if let Some(Ext::Path(p)) = parse.ext.as_ref() {
+                self.relative(format!("{}.ext", path), p);
+            }
+        }
+    }
+
+    fn rules(&mut self, source: &Source) {
+        for (i, rule) in source.rules.iter().flatten().enumerate() {
+            let path = format!("$.rules[{}]", i);
+            let fields = [("regex", &rule.regex), ("rule", &rule.rule)];
+            for (field, patterns) in fields {
+                for (j, p) in patterns.iter().flatten().enumerate() {
+                    if let Err(e) = regex::Regex::new(p) {
+                        // 盒子使用java的正则，环视与反向引用是支持的
+                        let msg = e.to_string();
+                        if msg.contains("look-around") || msg.contains("backreferences") {
+                            continue;
+                        }
+                        let reason = msg.lines().last().unwrap_or_default().trim().to_string();
+                        self.error(
+                            format!("{}.{}[{}]", path, field, j),
+                            "invalid-regex",
+                            format!("无效的正则表达式 `{}`: {}", p, reason),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn ijk(&mut self, source: &Source) {
+        for (i, ijk) in source.ijk.iter().flatten().enumerate() {
+            let path = format!("$.ijk[{}]", i);
+            if ijk.group.trim().is_empty() {
+                self.warning(
+                    format!("{}.group", path),
+                    "ijk-group",
+                    "分组名称为空".to_string(),
+                );
+            }
+            if ijk.options.is_empty() {
+                self.warning(
+                    format!("{}.options", path),
+                    "ijk-option",
+                    "没有任何选项".to_string(),
+                );
+            }
+            let mut names: HashMap<(i32, &str), usize> = HashMap::new();
+            for (j, opt) in ijk.options.iter().enumerate() {
+                let opt_path = format!("{}.options[{}]", path, j);
+                if !IJK_CATEGORIES.contains(&opt.category) {
+                    self.error(
+                        format!("{}.category", opt_path),
+                        "ijk-option",
+                        format!("无效的选项分类: {}，可用的分类为1~4", opt.category),
+                    );
+                }
+                let name = opt.name.trim();
+                if name.is_empty() {
+                    self.error(
+                        format!("{}.name", opt_path),
+                        "ijk-option",
+                        "选项名称为空".to_string(),
+                    );
+                } else if let Some(first) = names.get(&(opt.category, name)) {
+                    self.warning(
+                        format!("{}.name", opt_path),
+                        "ijk-option",
+                        format!(
+                            "选项 `{}` 与 {}.options[{}] 重复，后面的值会覆盖前面的",
+                            name, path, first
+                        ),
+                    );
+                } else {
+                    names.insert((opt.category, name), j);
+                }
+            }
+        }
+    }
+
+    /// 没有转换的相对地址，盒子无法确定其位置
+    fn relative(&mut self, path: String, url: &str) {
+        if url.starts_with('.') || url.starts_with('/') {
+            self.error(
+                path,
+                "relative-url",
+                format!("相对地址 `{}` 没有基础地址，请使用完整地址", url),
+            );
+        }
+    }
+
+    /// 本地文件需要存在，地址后可能带有;md5;xxx
+    fn local_file(&mut self, path: String, url: &str) {
+        let url = url.split(';').next().unwrap_or_default();
+        if url.starts_with("file:") && utils::local_path(url).is_none() {
+            self.error(path, "missing-file", format!("文件不存在: {}", url));
+        }
+    }
+}
+
+#[test]
+fn test_lint() {
+    let source = Source::parse(
+        r#"{
+        "spider": "./jar/custom.jar;md5;abc",
+        "sites": [
+            {"key": "a", "name": "A", "type": 1, "api": "http://a.com/api.php/provide/vod/"},
+            {"key": "a", "name": "B", "type": 3, "api": "csp_B", "searchable": 1},
+            {"key": "c", "name": "C", "type": 9, "api": "", "searchable": 1, "quickSearch": 1},
+            {"key": "d", "name": "D", "type": 0, "api": "", "quickSearch": "1"},
+            {"key": "e", "name": "E", "type": 4, "api": "http://e.com/", "searchable": 1}
+        ],
+        "lives": [{"name": "直播"}, {"url": "../live.txt"}],
+        "parses": [{"name": "json", "type": 1, "url": "http://p.com/?url="}],
+        "rules": [{"host": "*", "regex": ["(?<=a)b", "[a-"]}],
+        "ijk": [{"group": "软解码", "options": [
+            {"category": 4, "name": "mediacodec", "value": "0"},
+            {"category": 5, "name": "", "value": "0"},
+            {"category": "4", "name": "mediacodec", "value": "1"}
+        ]}]
+    }"#,
+        '#',
+    )
+    .unwrap();
+    let report = lint(&source);
+    let found = |path: &str, code: &str| {
+        report
+            .issues
+            .iter()
+            .find(|i| i.path == path && i.code == code)
+            .map(|i| i.severity)
+    };
+    assert_eq!(found("$.spider", "relative-url"), Some(Severity::Error));
+    assert_eq!(
+        found("$.sites[1].key", "duplicate-key"),
+        Some(Severity::Error)
+    );
+    assert_eq!(found("$.sites[2].api", "empty-api"), Some(Severity::Error));
+    assert_eq!(
+        found("$.sites[2].type", "unknown-type"),
+        Some(Severity::Error)
+    );
+    assert_eq!(
+        found("$.sites[2].quickSearch", "unsearchable-type"),
+        Some(Severity::Warning)
+    );
+    assert_eq!(
+        found("$.sites[3].quickSearch", "quick-search-without-search"),
+        Some(Severity::Warning)
+    );
+    // 远程站点不支持搜索
+    assert_eq!(
+        found("$.sites[4].searchable", "unsearchable-type"),
+        Some(Severity::Warning)
+    );
+    assert_eq!(found("$.sites[1].searchable", "unsearchable-type"), None);
+    assert_eq!(found("$.lives[0]", "empty-live"), Some(Severity::Error));
+    assert_eq!(
+        found("$.lives[1].url", "relative-url"),
+        Some(Severity::Error)
+    );
+    assert_eq!(found("$.rules[0].regex[0]", "invalid-regex"), None);
+    assert_eq!(
+        found("$.rules[0].regex[1]", "invalid-regex"),
+        Some(Severity::Error)
+    );
+    assert_eq!(
+        found("$.ijk[0].options[1].category", "ijk-option"),
+        Some(Severity::Error)
+    );
+    assert_eq!(
+        found("$.ijk[0].options[1].name", "ijk-option"),
+        Some(Severity::Error)
+    );
+    assert_eq!(
+        found("$.ijk[0].options[2].name", "ijk-option"),
+        Some(Severity::Warning)
+    );
+    assert!(found("$.parses[0].url", "empty-url").is_none());
+    assert!(found("$.sites[1].api", "unresolved-spider").is_none());
+
+    let mut source = source;
+    source.spider = None;
+    source.base("http://x.com/tv/config.json").unwrap();
+    let report = lint(&source);
+    assert!(report.issues.iter().all(|i| i.code != "relative-url"));
+    assert!(report
+        .issues
+        .iter()
+        .any(|i| i.path == "$.sites[1].api" && i.code == "unresolved-spider"));
+    assert!(!report.is_ok());
+}
@@ -3,7 +3,12 @@ use anyhow::Result;
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine};
 
-use super::{super::Connection, base_url};
+use super::{
+    super::{uptime, Connection},
+    base_url,
+    kind::LiveType,
+    origin::Origin,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Live {
@@ -11,15 +16,22 @@ pub struct Live {
     pub group: Option<String>,
     pub channels: Option<Vec<Channel>>,
     pub epg: Option<String>,
-    #[serde(rename = "type")]
-    pub src_type: Option<i32>,
+    #[serde(
+        rename = "type",
+        default,
+        deserialize_with = "LiveType::deserialize_option"
+    )]
+    pub src_type: Option<LiveType>,
     pub url: Option<String>,
+    /// 来源，只在读取时接受，序列化时省略，输出配置时由`WriteOptions::provenance`决定
+    #[serde(rename = "_origin", default, skip_serializing)]
+    pub origin: Option<Origin>,
 }
 
 impl Live {
     pub fn base(&mut self, base: &str) {
         if let Some(url) = self.url.as_mut() {
-            *url = base_url(base, &url);
+            *url = base_url(base, url);
         }
         if let Some(chns) = self.channels.as_mut() {
             chns.iter_mut().for_each(|c| c.base(base))
@@ -29,6 +41,9 @@ impl Live {
 
 #[async_trait]
 impl Connection for Live {
+    fn target(&self) -> Option<String> {
+        self.url.clone().filter(|u| utils::is_http_url(u))
+    }
     async fn check(&mut self, quick_mode: bool, skip_ipv6: bool) -> Result<bool> {
         let mut ok = false;
         // url
@@ -36,10 +51,16 @@ impl Connection for Live {
             if skip_ipv6 && url.contains("://[") {
                 self.url = None;
             } else {
+                let playlist = matches!(self.src_type, None | Some(LiveType::Playlist));
                 let connectable = if quick_mode {
                     utils::url_connectivity(url).await.unwrap_or_default()
+                } else if playlist {
+                    utils::url_txt_playlist_accessibility(url)
+                        .await
+                        .unwrap_or_default()
                 } else {
-                    utils::url_txt_playlist_accessibility(url).await.unwrap_or_default()
+                    // 爬虫等其他类型的地址不是直播源，只要能访问就行
+                    utils::url_accessibility(url).await.unwrap_or_default()
                 };
                 // if !connectable {
                 //     self.url = None;
@@ -83,15 +104,18 @@ impl Connection for Channel {
     async fn check(&mut self, quick_mode: bool, skip_ipv6: bool) -> Result<bool> {
         let mut connectable = vec![];
         for i in &self.urls {
-            if utils::is_http_url(&i) {
+            if utils::is_http_url(i) {
                 if skip_ipv6 && i.contains("://[") {
                     continue;
                 }
+                let start = std::time::Instant::now();
                 let ok = if quick_mode {
-                    utils::url_connectivity(&i).await.unwrap_or_default()
+                    utils::url_connectivity(i).await.unwrap_or_default()
                 } else {
-                    utils::url_accessibility(&i).await.unwrap_or_default()
+                    utils::url_accessibility(i).await.unwrap_or_default()
                 };
+                let latency = start.elapsed().as_millis() as u64;
+                uptime::record(i, ok, Some(latency).filter(|_| ok));
                 if ok {
                     connectable.push(i.to_string())
                 }
@@ -111,6 +135,8 @@ impl Connection for Channel {
                         connectable.push(i.to_string())
                     }
                 }
+            } else if i.starts_with("file:") && utils::local_path(i).is_some() {
+                connectable.push(i.to_string())
             }
         }
         self.urls = connectable;
@@ -136,10 +162,9 @@ impl Proxy {
         if utils::is_http_url(&self.ext) {
             return Some(self.ext.to_string());
         }
-        let decode = general_purpose::URL_SAFE
+        general_purpose::URL_SAFE
             .decode(&self.ext)
             .ok()
-            .and_then(|buff| String::from_utf8(buff).ok());
-        decode
+            .and_then(|buff| String::from_utf8(buff).ok())
     }
 }
@@ -2,11 +2,17 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use indicatif::ProgressBar;
+pub mod drpy;
+pub mod ext;
 pub mod ijk;
+pub mod kind;
+pub mod lint;
 pub mod live;
+pub mod origin;
 pub mod parse;
 pub mod rule;
 pub mod vod;
+pub mod writer;
 use ijk::Ijk;
 use live::Live;
 use parse::Parse;
@@ -36,6 +42,9 @@ impl Source {
     pub fn base(&mut self, base: &str) -> Result<()> {
         self.sites.iter_mut().for_each(|item| item.base(base));
         self.lives.iter_mut().for_each(|item| item.base(base));
+        if let Some(parses) = self.parses.as_mut() {
+            parses.iter_mut().for_each(|item| item.base(base));
+        }
         if let Some(spider) = self.spider.as_mut() {
             let mut s = spider.split(";").collect::<Vec<_>>();
             let p = s.first_mut().unwrap();
@@ -46,15 +55,15 @@ impl Source {
         Ok(())
     }
     pub fn parse(i: &str, illegal_comment: char) -> Result<Self> {
+        let i = i.trim_start_matches('\u{feff}');
         // 过滤[#]
         let r = regex::Regex::new(&format!("^{}.*", illegal_comment))?;
-        let i = r.replace_all(&i, "").to_string();
+        let i = r.replace_all(i, "").to_string();
         let r = regex::Regex::new(&format!("\n{}.*", illegal_comment)).unwrap();
         let i = r.replace_all(&i, "").to_string();
-        let doc = json5::from_str::<Self>(&i);
-        if doc.is_ok() {
+        if let Ok(doc) = json5::from_str::<Self>(&i) {
             // debug!("json5 解析成功!");
-            return Ok(doc.unwrap());
+            return Ok(doc);
         }
         // 过滤[/]
         let r = regex::Regex::new("^//.*")?;
@@ -68,6 +77,99 @@ impl Source {
         // debug!("json 解析成功!");
         Ok(doc)
     }
+
+    /// 合并多个配置，站点按api与ext、直播按地址与频道、解析按地址去重，其他字段以第一个配置为准
+    pub fn merge(sources: Vec<Source>) -> Source {
+        let site_key = |s: &Vod| {
+            let ext = s
+                .ext
+                .as_ref()
+                .and_then(|e| serde_json::to_string(e).ok())
+                .unwrap_or_default();
+            format!("{}{}", s.api, ext)
+        };
+        let live_key = |l: &Live| {
+            let urls = l
+                .channels
+                .iter()
+                .flatten()
+                .map(|c| c.urls.join(","))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{}{}", l.url.as_deref().unwrap_or_default(), urls)
+        };
+        let rule_key = |r: &Rule| match r.host.as_deref().filter(|h| !h.is_empty()) {
+            Some(host) => host.to_string(),
+            None => [&r.hosts, &r.regex, &r.rule]
+                .iter()
+                .map(|v| v.iter().flatten().cloned().collect::<Vec<_>>().join(","))
+                .collect(),
+        };
+        let mut sources = sources.into_iter();
+        let mut merged = sources.next().unwrap_or_default();
+        for source in sources {
+            for mut site in source.sites {
+                if merged.sites.iter().any(|s| site_key(s) == site_key(&site)) {
+                    continue;
+                }
+                // 其他配置的爬虫站点使用各自的spider
+                if site.jar.is_none() && source.spider != merged.spider {
+                    site.jar = source.spider.clone();
+                }
+                merged.sites.push(site);
+            }
+            for live in source.lives {
+                if !merged.lives.iter().any(|l| live_key(l) == live_key(&live)) {
+                    merged.lives.push(live);
+                }
+            }
+            let parses = merged.parses.get_or_insert_with(Vec::new);
+            for parse in source.parses.unwrap_or_default() {
+                if !parses.iter().any(|p| p.url == parse.url) {
+                    parses.push(parse);
+                }
+            }
+            for (to, from) in [
+                (&mut merged.flags, source.flags),
+                (&mut merged.ads, source.ads),
+            ] {
+                let to = to.get_or_insert_with(Vec::new);
+                for item in from.unwrap_or_default() {
+                    if !to.contains(&item) {
+                        to.push(item);
+                    }
+                }
+            }
+            let rules = merged.rules.get_or_insert_with(Vec::new);
+            for rule in source.rules.unwrap_or_default() {
+                if !rules.iter().any(|r| rule_key(r) == rule_key(&rule)) {
+                    rules.push(rule);
+                }
+            }
+        }
+        merged
+    }
+
+    /// 站点与直播按历史稳定性从高到低排序，稳定性相同时保持原来的顺序，盒子默认使用第一个直播
+    pub async fn sort_by_stability(&mut self) {
+        use super::{uptime, Connection};
+        let targets = self
+            .sites
+            .iter()
+            .filter_map(|s| s.target())
+            .chain(self.lives.iter().filter_map(|l| l.target()))
+            .collect();
+        let scores = uptime::stabilities(targets).await;
+        let score = |target: Option<String>| {
+            target
+                .and_then(|t| scores.get(&t).copied())
+                .unwrap_or(uptime::UNKNOWN_STABILITY)
+        };
+        self.sites
+            .sort_by(|a, b| score(b.target()).total_cmp(&score(a.target())));
+        self.lives
+            .sort_by(|a, b| score(b.target()).total_cmp(&score(a.target())));
+    }
 }
 
 ///
@@ -75,13 +177,14 @@ impl Source {
 /// pb.inc(1);
 /// pb.finish();
 ///
+#[allow(dead_code)]
 pub fn progress_bar(count: u64) -> Arc<ProgressBar> {
     let len = format!("{}", count).len();
     let template = format!(
         "[{{elapsed_precise}}] {{wide_bar:.white/white}} {{pos:>{}}}/{{len:{}}}",
         len, len
     );
-    let pb = ProgressBar::new(count as u64);
+    let pb = ProgressBar::new(count);
     let style = indicatif::ProgressStyle::with_template(&template)
         .unwrap()
         .progress_chars("█░");
@@ -95,7 +198,7 @@ fn base_url(base: &str, path: &str) -> String {
         if let Ok(base) = url::Url::parse(base) {
             return base
                 .join(path)
-                .and_then(|new| Ok(new.to_string()))
+                .map(|new| new.to_string())
                 .unwrap_or(path.to_string());
         }
     }
@@ -117,9 +220,9 @@ async fn test_d() {
     println!("sites: {}", src.sites.len());
     println!("spider: {:?}", src.spider);
     println!("parses: {}", src.parses.is_some());
-    println!("ads: {:?}", src.ads.and_then(|s| Some(s.len())));
-    println!("flags: {:?}", src.flags.and_then(|s| Some(s.len())));
-    println!("rules: {:?}", src.rules.and_then(|r| Some(r.len())));
+    println!("ads: {:?}", src.ads.map(|s| s.len()));
+    println!("flags: {:?}", src.flags.map(|s| s.len()));
+    println!("rules: {:?}", src.rules.map(|r| r.len()));
     println!("wallpaper: {:?}", src.wallpaper);
     println!("warning_text: {:?}", src.warning_text);
     src.sites.iter().for_each(|i| {
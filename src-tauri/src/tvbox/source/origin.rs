@@ -0,0 +1,291 @@
+//! 站点、直播与解析的来源，合并多个配置后用于找出并移除某个提供者的条目
+//! 来源不会随条目序列化，传给前端的条目通过条目的hash找回来源
+use super::{live::Live, parse::Parse, vod::Vod, Source};
+use crate::utils;
+use chrono::{Local, TimeZone};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::{collections::HashMap, sync::Mutex};
+
+/// 输出来源时使用的字段，json5中以注释的形式输出
+pub const ORIGIN_FIELD: &str = "_origin";
+/// 记录的来源超过该数量时清空，避免长时间运行时占用过多内存
+const REGISTRY_LIMIT: usize = 100_000;
+
+/// 条目序列化后的hash与来源
+static REGISTRY: Lazy<Mutex<HashMap<String, Origin>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 条目的来源
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Origin {
+    /// 配置的地址
+    pub url: String,
+    /// 获取时间(毫秒时间戳)
+    pub fetched: i64,
+    /// 配置内容的hash
+    pub hash: String,
+}
+
+impl Origin {
+    pub fn new(url: &str, content: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            fetched: Local::now().timestamp_millis(),
+            hash: utils::hash(content.as_bytes()),
+        }
+    }
+
+    /// 是否来自该提供者，`provider`为配置地址或域名
+    pub fn matches(&self, provider: &str) -> bool {
+        let provider = provider.trim();
+        if provider.is_empty() {
+            return false;
+        }
+        if self.url == provider {
+            return true;
+        }
+        url::Url::parse(&self.url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.eq_ignore_ascii_case(provider)))
+            .unwrap_or_default()
+    }
+
+    /// 用于注释的说明, 如: http://a.com/tv.json 2024-01-01 08:00:00 #0123456789ABCDEF
+    pub fn describe(&self) -> String {
+        let time = Local
+            .timestamp_millis_opt(self.fetched)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        format!("{} {} #{}", self.url, time, self.hash)
+    }
+}
+
+/// 一个提供者的条目数量
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Provider {
+    /// 为空时表示没有来源的条目
+    pub origin: Option<Origin>,
+    pub sites: usize,
+    pub lives: usize,
+    pub parses: usize,
+}
+
+/// 带有来源的条目
+pub trait Tagged: serde::Serialize {
+    fn origin(&self) -> Option<&Origin>;
+    fn origin_mut(&mut self) -> &mut Option<Origin>;
+
+    /// 序列化的结果，有来源时加上来源字段
+    fn to_value_with_origin(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let (Some(map), Some(origin)) = (value.as_object_mut(), self.origin()) {
+            map.insert(
+                ORIGIN_FIELD.to_string(),
+                serde_json::to_value(origin).unwrap_or_default(),
+            );
+        }
+        value
+    }
+}
+
+macro_rules! tagged {
+    ($($name:ty),*) => {
+        $(impl Tagged for $name {
+            fn origin(&self) -> Option<&Origin> {
+                self.origin.as_ref()
+            }
+            fn origin_mut(&mut self) -> &mut Option<Origin> {
+                &mut self.origin
+            }
+        })*
+    };
+}
+
+tagged!(Vod, Live, Parse);
+
+/// 条目的标识，序列化结果(不含来源)的hash
+fn identity<T: serde::Serialize>(item: &T) -> Option<String> {
+    let value = serde_json::to_value(item).ok()?;
+    Some(utils::hash(value.to_string().as_bytes()))
+}
+
+fn remember<T: Tagged>(registry: &mut HashMap<String, Origin>, items: &[T]) {
+    for item in items {
+        if let (Some(origin), Some(id)) = (item.origin(), identity(item)) {
+            registry.insert(id, origin.clone());
+        }
+    }
+}
+
+fn restore<T: Tagged>(registry: &HashMap<String, Origin>, items: &mut [T]) {
+    for item in items.iter_mut().filter(|i| i.origin().is_none()) {
+        if let Some(origin) = identity(item).and_then(|id| registry.get(&id)) {
+            *item.origin_mut() = Some(origin.clone());
+        }
+    }
+}
+
+/// 在序列化的数组中加上来源字段
+fn insert<T: Tagged>(value: Option<&mut Value>, items: &[T]) {
+    let Some(values) = value.and_then(Value::as_array_mut) else {
+        return;
+    };
+    for (v, item) in values.iter_mut().zip(items) {
+        if let (Some(map), Some(origin)) = (v.as_object_mut(), item.origin()) {
+            map.insert(
+                ORIGIN_FIELD.to_string(),
+                serde_json::to_value(origin).unwrap_or_default(),
+            );
+        }
+    }
+}
+
+impl Source {
+    /// 为还没有来源的条目设置来源，已合并的条目保留原来的来源
+    pub fn tag(&mut self, origin: &Origin) {
+        let tag = |o: &mut Option<Origin>| {
+            o.get_or_insert_with(|| origin.clone());
+        };
+        self.sites.iter_mut().for_each(|i| tag(&mut i.origin));
+        self.lives.iter_mut().for_each(|i| tag(&mut i.origin));
+        self.parses
+            .iter_mut()
+            .flatten()
+            .for_each(|i| tag(&mut i.origin));
+    }
+
+    /// 按来源统计条目，按第一次出现的顺序排列
+    pub fn providers(&self) -> Vec<Provider> {
+        let mut providers: Vec<Provider> = vec![];
+        let mut count = |origin: &Option<Origin>, f: fn(&mut Provider)| {
+            let index = match providers.iter().position(|p| p.origin == *origin) {
+                Some(i) => i,
+                None => {
+                    providers.push(Provider {
+                        origin: origin.clone(),
+                        sites: 0,
+                        lives: 0,
+                        parses: 0,
+                    });
+                    providers.len() - 1
+                }
+            };
+            f(&mut providers[index]);
+        };
+        self.sites
+            .iter()
+            .for_each(|i| count(&i.origin, |p| p.sites += 1));
+        self.lives
+            .iter()
+            .for_each(|i| count(&i.origin, |p| p.lives += 1));
+        self.parses
+            .iter()
+            .flatten()
+            .for_each(|i| count(&i.origin, |p| p.parses += 1));
+        providers
+    }
+
+    /// 只保留来源满足条件的条目，返回移除的数量
+    pub fn retain_origin<F: Fn(Option<&Origin>) -> bool>(&mut self, f: F) -> usize {
+        let before = self.sites.len() + self.lives.len() + self.parses.iter().flatten().count();
+        self.sites.retain(|i| f(i.origin.as_ref()));
+        self.lives.retain(|i| f(i.origin.as_ref()));
+        if let Some(parses) = self.parses.as_mut() {
+            parses.retain(|i| f(i.origin.as_ref()));
+        }
+        before - self.sites.len() - self.lives.len() - self.parses.iter().flatten().count()
+    }
+
+    /// 记录条目的来源，条目传给前端后再传回时可以通过`restore_origins`找回
+    pub fn remember_origins(&self) {
+        let mut registry = REGISTRY.lock().unwrap();
+        if registry.len() > REGISTRY_LIMIT {
+            registry.clear();
+        }
+        remember(&mut registry, &self.sites);
+        remember(&mut registry, &self.lives);
+        remember(&mut registry, self.parses.as_deref().unwrap_or_default());
+    }
+
+    /// 为没有来源的条目找回记录的来源
+    pub fn restore_origins(&mut self) {
+        let registry = REGISTRY.lock().unwrap();
+        restore(&registry, &mut self.sites);
+        restore(&registry, &mut self.lives);
+        restore(&registry, self.parses.as_deref_mut().unwrap_or_default());
+    }
+
+    /// 序列化的结果，站点、直播与解析带有来源字段，用于输出来源
+    pub fn to_value_with_origins(&self) -> serde_json::Result<Value> {
+        let mut value = serde_json::to_value(self)?;
+        insert(value.get_mut("sites"), &self.sites);
+        insert(value.get_mut("lives"), &self.lives);
+        if let Some(parses) = self.parses.as_ref() {
+            insert(value.get_mut("parses"), parses);
+        }
+        Ok(value)
+    }
+
+    /// 移除来自该提供者(配置地址或域名)的所有条目，返回移除的数量
+    pub fn remove_provider(&mut self, provider: &str) -> usize {
+        self.retain_origin(|o| !o.map(|o| o.matches(provider)).unwrap_or_default())
+    }
+}
+
+#[test]
+fn test_origin() {
+    let a = r#"{"sites": [{"key": "a", "name": "A", "type": 1, "api": "http://a.com/api"}],
+        "lives": [{"name": "直播", "url": "http://a.com/live.txt"}], "parses": []}"#;
+    let b = r#"{"sites": [{"key": "b", "name": "B", "type": 3, "api": "csp_B"}], "lives": [],
+        "parses": [{"name": "解析", "type": 1, "url": "http://b.net/?url="}]}"#;
+    let mut source = Source::parse(a, '#').unwrap();
+    source.tag(&Origin::new("http://a.com/tv.json", a));
+    let mut other = Source::parse(b, '#').unwrap();
+    let origin = Origin::new("https://b.net/cfg/tv.json", b);
+    other.tag(&origin);
+    source.sites.extend(other.sites);
+    source.parses = other.parses;
+    // 已有来源的条目不会被覆盖
+    source.tag(&Origin::new("http://merged.com/tv.json", ""));
+    assert_eq!(source.sites[1].origin.as_ref(), Some(&origin));
+
+    let providers = source.providers();
+    assert_eq!(providers.len(), 2);
+    assert_eq!((providers[0].sites, providers[0].lives), (1, 1));
+    assert_eq!((providers[1].sites, providers[1].parses), (1, 1));
+
+    // 来源不会随条目序列化，传回后通过记录找回
+    source.remember_origins();
+    let json = serde_json::to_string(&source).unwrap();
+    assert!(!json.contains(ORIGIN_FIELD));
+    let mut restored = Source::parse(&json, '#').unwrap();
+    assert!(restored.sites[1].origin.is_none());
+    restored.restore_origins();
+    assert_eq!(restored.sites[1].origin, Some(origin));
+    assert_eq!(restored.providers(), providers);
+
+    // 输出的配置中默认不包含来源，json5中以注释的形式输出
+    use super::writer::{Style, WriteOptions};
+    let out = source.to_string_with(&WriteOptions::default()).unwrap();
+    assert!(!out.contains(ORIGIN_FIELD));
+    let options = WriteOptions {
+        style: Style::Json5,
+        provenance: true,
+        ..Default::default()
+    };
+    let out = source.to_string_with(&options).unwrap();
+    assert!(out.contains("    // 来源: https://b.net/cfg/tv.json "));
+    assert!(!out.contains(ORIGIN_FIELD));
+    let options = WriteOptions {
+        provenance: true,
+        ..Default::default()
+    };
+    let out = source.to_string_with(&options).unwrap();
+    assert_eq!(Source::parse(&out, '#').unwrap().providers(), providers);
+
+    assert_eq!(source.remove_provider("b.net"), 2);
+    assert_eq!(source.sites.len(), 1);
+    assert_eq!(source.remove_provider("http://a.com/tv.json"), 2);
+    assert!(source.sites.is_empty() && source.lives.is_empty());
+}
@@ -1,39 +1,51 @@
-use super::super::Connection;
+use super::{super::Connection, base_url, ext::Ext, kind::ParseType, origin::Origin};
 use crate::utils;
 use anyhow::Result;
 use async_trait::async_trait;
-use serde_aux::prelude::*;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parse {
     pub name: String,
-    #[serde(
-        rename = "type",
-        default,
-        deserialize_with = "deserialize_number_from_string"
-    )]
-    pub src_type: i32,
+    #[serde(rename = "type", default)]
+    pub src_type: ParseType,
     pub url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub ext: Option<serde_json::Value>,
+    pub ext: Option<Ext>,
+    /// 来源，只在读取时接受，序列化时省略，输出配置时由`WriteOptions::provenance`决定
+    #[serde(rename = "_origin", default, skip_serializing)]
+    pub origin: Option<Origin>,
+}
+
+impl Parse {
+    pub fn base(&mut self, base: &str) {
+        self.url = base_url(base, &self.url);
+        if let Some(ext) = self.ext.as_mut() {
+            ext.base(base);
+        }
+    }
 }
 
 #[async_trait]
 impl Connection for Parse {
+    fn target(&self) -> Option<String> {
+        Some(self.url.to_string()).filter(|u| utils::is_http_url(u))
+    }
     async fn check(&mut self, quick_mode: bool, skip_ipv6: bool) -> Result<bool> {
-        if utils::is_http_url(&self.url) {
-            if skip_ipv6 && self.url.contains("://[") {
-                return Ok(false);
-            } else {
-                let connectable = if quick_mode {
-                    utils::url_connectivity(&self.url).await.unwrap_or_default()
-                } else {
-                    utils::url_accessibility(&self.url)
-                        .await
-                        .unwrap_or_default()
-                };
-                return Ok(connectable);
-            }
+        if !utils::is_http_url(&self.url) {
+            // 聚合解析与超级解析可以没有地址
+            return Ok(true);
+        }
+        if skip_ipv6 && self.url.contains("://[") {
+            return Ok(false);
         }
-        Ok(true)
+        // json解析的地址需要拼接视频地址才能返回有效内容，只检测服务器是否可以连接
+        // 嗅探解析需要网页可以访问
+        let connectable = if quick_mode || self.src_type.is_json() {
+            utils::url_connectivity(&self.url).await.unwrap_or_default()
+        } else {
+            utils::url_accessibility(&self.url)
+                .await
+                .unwrap_or_default()
+        };
+        Ok(connectable)
     }
 }
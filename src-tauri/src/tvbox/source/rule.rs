@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Rule {
     // 针对多个主机
     pub hosts: Option<Vec<String>>,
@@ -8,3 +8,18 @@ pub struct Rule {
     pub host: Option<String>,
     pub rule: Option<Vec<String>>,
 }
+
+impl Rule {
+    /// 规则是否适用于该地址，`*` 匹配所有地址
+    pub fn matches(&self, url: &str) -> bool {
+        self.hosts
+            .iter()
+            .flatten()
+            .chain(self.host.iter())
+            .any(|h| h == "*" || (!h.is_empty() && url.contains(h.as_str())))
+    }
+    /// 规则中的广告正则，`rule` 为嗅探视频地址的规则，不用于过滤广告
+    pub fn patterns(&self) -> impl Iterator<Item = &String> {
+        self.regex.iter().flatten()
+    }
+}
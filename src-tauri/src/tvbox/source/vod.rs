@@ -1,4 +1,10 @@
-use super::{super::Connection, base_url};
+use super::{
+    super::Connection,
+    base_url, drpy,
+    ext::Ext,
+    kind::{PlayerType, VodType},
+    origin::Origin,
+};
 use crate::utils;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -10,9 +16,8 @@ use serde_aux::prelude::*;
 pub struct Vod {
     pub key: String,
     pub name: String,
-    /// 0 xml 1 json 3 Spider
-    #[serde(rename = "type", deserialize_with = "deserialize_number_from_string")]
-    pub src_type: i32,
+    #[serde(rename = "type")]
+    pub src_type: VodType,
     // 接口地址
     pub api: String,
     /// 是否可搜索
@@ -33,49 +38,85 @@ pub struct Vod {
     pub player_url: Option<String>,
     /// 扩展数据
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub ext: Option<serde_json::Value>,
+    pub ext: Option<Ext>,
     // 自定义JAR刮削库 - 引用第三方
     pub jar: Option<String>,
-    /// 播放器类型<br>
-    ///  0 system 1 ikj 2 exo 10 mxplayer -1 以参数设置页面的为准
+    /// 播放器类型
     #[serde(
         rename = "playerType",
         default = "Vod::player_type_default",
-        deserialize_with = "deserialize_option_number_from_string"
+        deserialize_with = "PlayerType::deserialize_option"
     )]
-    pub player_type: Option<i32>,
+    pub player_type: Option<PlayerType>,
     /// 分类&排序
     pub categories: Option<Vec<String>>,
     /// 需要点击播放的嗅探站点selector   ddrk.me;#id
     pub click: Option<String>,
     #[serde(
-        default = "Vod::player_type_default",
+        default = "Vod::hide_default",
         deserialize_with = "deserialize_option_number_from_string"
     )]
     pub hide: Option<i32>,
+    /// 来源，只在读取时接受，序列化时省略，输出配置时由`WriteOptions::provenance`决定
+    #[serde(rename = "_origin", default, skip_serializing)]
+    pub origin: Option<Origin>,
 }
 impl Vod {
-    pub fn player_type_default() -> Option<i32> {
+    pub fn player_type_default() -> Option<PlayerType> {
+        Some(PlayerType::Auto)
+    }
+    pub fn hide_default() -> Option<i32> {
         Some(-1)
     }
     pub fn base(&mut self, base: &str) {
         self.api = base_url(base, &self.api);
         if let Some(ext) = self.ext.as_mut() {
-            match ext {
-                serde_json::Value::String(x) => {
-                    *x = base_url(base, x);
-                }
-                _ => {}
-            }
+            ext.base(base);
         }
     }
 }
 
 #[async_trait]
 impl Connection for Vod {
+    fn target(&self) -> Option<String> {
+        Some(self.api.to_string()).filter(|u| utils::is_http_url(u))
+    }
     async fn check(&mut self, quick_mode: bool, skip_ipv6: bool) -> Result<bool> {
-        // 当前测试仅针对，api和ext两个字段
+        match self.src_type {
+            // 采集接口只能是http地址
+            t if t.is_cms() => {
+                if !utils::is_http_url(&self.api) {
+                    return Err(anyhow!("无效的接口地址!"));
+                }
+                self.check_api(quick_mode, skip_ipv6).await
+            }
+            // 爬虫站点的api通常是类名(csp_xxx)或js/py地址，需要同时检测jar与ext
+            VodType::Spider => {
+                if !self.check_api(quick_mode, skip_ipv6).await? {
+                    return Ok(false);
+                }
+                if !self.check_jar(skip_ipv6).await? {
+                    return Ok(false);
+                }
+                self.check_ext(quick_mode, skip_ipv6).await
+            }
+            _ => {
+                if !self.check_api(quick_mode, skip_ipv6).await? {
+                    return Ok(false);
+                }
+                self.check_ext(quick_mode, skip_ipv6).await
+            }
+        }
+    }
+}
+
+impl Vod {
+    /// 检测http或本地文件形式的api，其他形式(如: 爬虫类名)直接通过
+    async fn check_api(&self, quick_mode: bool, skip_ipv6: bool) -> Result<bool> {
         if utils::is_http_url(&self.api) {
+            if skip_ipv6 && self.api.contains("://[") {
+                return Ok(false);
+            }
             let ok = if quick_mode {
                 utils::url_connectivity(&self.api).await?
             } else {
@@ -84,46 +125,80 @@ impl Connection for Vod {
             if !ok {
                 return Err(anyhow!("连接失败!"));
             }
-            if let Some(ext) = self.ext.as_ref() {
-                match ext {
-                    serde_json::Value::String(x) => {
-                        // 跳过本地ip
-                        if x.starts_with("http://127.0.0.1") || x.starts_with("http://localhost") {
-                            return Ok(true);
-                        }
-                        // 测试js-spider
-                        if utils::is_http_url(&x) {
-                            if skip_ipv6 && x.contains("://[") {
-                                return Ok(false);
-                            }
-                            // 检查配置文件在的点播站点是否还有效
-                            // js通常会配合drpy.js一起使用
-                            if x.ends_with(".js") || x.ends_with(".py") {
-                                let content = reqwest::get(x).await?.text().await?;
-                                let r = regex::Regex::new("https?://[0-9A-Za-z.%-]+")?;
-                                let host = r
-                                    .find(&content)
-                                    .and_then(|m| Some(m.as_str().to_string()))
-                                    .ok_or(anyhow!("找不到点播站点"))?;
-                                let ok = utils::url_connectivity(&host).await.unwrap_or_default();
-                                if !ok {
-                                    return Err(anyhow!("点播站点无法连接!"));
-                                }
-                            } else {
-                                // 其他地址只要能保证可以访问就行
-                                let ok = if quick_mode {
-                                    utils::url_connectivity(&x).await?
-                                } else {
-                                    utils::url_accessibility(&x).await?
-                                };
-                                if !ok {
-                                    return Err(anyhow!("连接失败!"));
-                                }
-                            }
-                        }
+        } else if self.api.starts_with("file:") && utils::local_path(&self.api).is_none() {
+            return Err(anyhow!("文件不存在!"));
+        }
+        Ok(true)
+    }
+
+    /// 检测站点自带的jar，地址后可能带有;md5;xxx
+    async fn check_jar(&self, skip_ipv6: bool) -> Result<bool> {
+        let Some(jar) = self.jar.as_ref() else {
+            return Ok(true);
+        };
+        let jar = jar.split(';').next().unwrap_or_default();
+        if !utils::is_http_url(jar) {
+            return Ok(true);
+        }
+        if skip_ipv6 && jar.contains("://[") {
+            return Ok(false);
+        }
+        if !utils::url_connectivity(jar).await.unwrap_or_default() {
+            return Err(anyhow!("jar无法连接!"));
+        }
+        Ok(true)
+    }
+
+    /// 检测ext中引用的地址, 如: drpy的规则、XBPQ配置中的站点，图片等资源不检测
+    async fn check_ext(&self, quick_mode: bool, skip_ipv6: bool) -> Result<bool> {
+        let Some(ext) = self.ext.as_ref() else {
+            return Ok(true);
+        };
+        let mut hosts: Vec<String> = vec![];
+        for x in ext.urls() {
+            // 跳过本地ip
+            if x.starts_with("http://127.0.0.1") || x.starts_with("http://localhost") {
+                continue;
+            }
+            if skip_ipv6 && x.contains("://[") {
+                return Ok(false);
+            }
+            // 检查配置文件在的点播站点是否还有效
+            // js通常是配合drpy.js一起使用的规则文件，优先使用规则中的host与搜索地址
+            if x.ends_with(".js") || x.ends_with(".py") {
+                let content = utils::read_content(&x).await?;
+                let rule = drpy::Rule::parse(&content);
+                let host = rule
+                    .as_ref()
+                    .and_then(|r| r.home())
+                    .or_else(|| drpy::guess_host(&content))
+                    .ok_or(anyhow!("找不到点播站点"))?;
+                if !hosts.contains(&host) {
+                    let ok = utils::url_connectivity(&host).await.unwrap_or_default();
+                    if !ok {
+                        return Err(anyhow!("点播站点无法连接!"));
                     }
-                    _ => {}
+                    hosts.push(host);
                 }
+                if let Some(search) = rule.and_then(|r| r.search_probe()).filter(|_| !quick_mode) {
+                    if !utils::url_accessibility(&search).await.unwrap_or_default() {
+                        return Err(anyhow!("搜索接口无法访问!"));
+                    }
+                }
+            } else if x.contains('{') || quick_mode {
+                // 模板地址, 如: /vodshow/{cateId}，只检测服务器是否可以连接
+                let host = url::Url::parse(&x)
+                    .map(|u| u.origin().ascii_serialization())
+                    .unwrap_or(x);
+                if !hosts.contains(&host) {
+                    if !utils::url_connectivity(&host).await? {
+                        return Err(anyhow!("连接失败!"));
+                    }
+                    hosts.push(host);
+                }
+            } else if !utils::url_accessibility(&x).await? {
+                // 其他地址只要能保证可以访问就行
+                return Err(anyhow!("连接失败!"));
             }
         }
         Ok(true)
@@ -0,0 +1,361 @@
+//! 配置的输出，字段按TVBox参考配置的顺序排列，便于在git中比较
+use super::{
+    origin::{Origin, ORIGIN_FIELD},
+    Source,
+};
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+const ROOT: &[&str] = &[
+    "spider",
+    "wallpaper",
+    "warningText",
+    "sites",
+    "lives",
+    "parses",
+    "flags",
+    "ijk",
+    "rules",
+    "ads",
+];
+const SITE: &[&str] = &[
+    "key",
+    "name",
+    "type",
+    "api",
+    "searchable",
+    "quickSearch",
+    "filterable",
+    "playerUrl",
+    "ext",
+    "jar",
+    "playerType",
+    "categories",
+    "click",
+    "hide",
+];
+const LIVE: &[&str] = &["name", "group", "type", "url", "epg", "channels"];
+const CHANNEL: &[&str] = &["name", "urls"];
+const PARSE: &[&str] = &["name", "type", "url", "ext"];
+const IJK: &[&str] = &["group", "options"];
+const IJK_OPTION: &[&str] = &["category", "name", "value"];
+const RULE: &[&str] = &["name", "host", "hosts", "regex", "rule"];
+
+/// 输出样式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    /// 带缩进的json
+    #[default]
+    Pretty,
+    /// 没有空白的json，适合内存较小的盒子
+    Minified,
+    /// 带缩进的json5，可以包含注释
+    Json5,
+}
+
+/// 输出选项
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteOptions {
+    pub style: Style,
+    /// 缩进的空格数
+    pub indent: usize,
+    /// 省略null与盒子的默认值, 如: "playerType": -1
+    pub omit_defaults: bool,
+    /// 输出站点、直播与解析的来源，json5中以注释的形式输出
+    pub provenance: bool,
+    /// json5中写在对应json路径前的注释, 如: {"$": "来源", "$.sites[0]": "..."}
+    pub comments: HashMap<String, String>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            style: Style::Pretty,
+            indent: 2,
+            omit_defaults: true,
+            provenance: false,
+            comments: HashMap::new(),
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn minified() -> Self {
+        Self {
+            style: Style::Minified,
+            ..Default::default()
+        }
+    }
+}
+
+impl Source {
+    /// 按选项输出配置
+    pub fn to_string_with(&self, options: &WriteOptions) -> Result<String> {
+        let value = if options.provenance {
+            self.to_value_with_origins()?
+        } else {
+            serde_json::to_value(self)?
+        };
+        Ok(write(&value, options))
+    }
+}
+
+/// 字段所在的对象，决定字段的顺序与默认值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Root,
+    Site,
+    Live,
+    Channel,
+    Parse,
+    Ijk,
+    IjkOption,
+    Rule,
+    /// ext等自定义的内容，只按字段名排序
+    Other,
+}
+
+impl Scope {
+    fn order(&self) -> &'static [&'static str] {
+        match self {
+            Self::Root => ROOT,
+            Self::Site => SITE,
+            Self::Live => LIVE,
+            Self::Channel => CHANNEL,
+            Self::Parse => PARSE,
+            Self::Ijk => IJK,
+            Self::IjkOption => IJK_OPTION,
+            Self::Rule => RULE,
+            Self::Other => &[],
+        }
+    }
+
+    /// 字段的值(或数组中的元素)所在的对象
+    fn child(&self, key: &str) -> Self {
+        match (self, key) {
+            (Self::Root, "sites") => Self::Site,
+            (Self::Root, "lives") => Self::Live,
+            (Self::Root, "parses") => Self::Parse,
+            (Self::Root, "ijk") => Self::Ijk,
+            (Self::Root, "rules") => Self::Rule,
+            (Self::Live, "channels") => Self::Channel,
+            (Self::Ijk, "options") => Self::IjkOption,
+            _ => Self::Other,
+        }
+    }
+
+    /// 带有来源的条目
+    fn has_origin(&self) -> bool {
+        matches!(self, Self::Site | Self::Live | Self::Parse)
+    }
+
+    /// 与盒子的默认值相同，可以省略
+    fn is_default(&self, key: &str, value: &Value) -> bool {
+        match (self, key) {
+            (Self::Other, _) => false,
+            (_, _) if value.is_null() => true,
+            (Self::Site, "playerType" | "hide") => value.as_i64() == Some(-1),
+            (Self::Parse, "type") => value.as_i64() == Some(0),
+            _ => false,
+        }
+    }
+}
+
+/// 输出json值，`value`通常为`Source`序列化的结果
+pub fn write(value: &Value, options: &WriteOptions) -> String {
+    let mut writer = Writer {
+        options,
+        out: String::new(),
+    };
+    writer.comment("$", 0);
+    writer.value(value, "$", Scope::Root, 0);
+    if options.style != Style::Minified {
+        writer.out.push('\n');
+    }
+    writer.out
+}
+
+struct Writer<'a> {
+    options: &'a WriteOptions,
+    out: String,
+}
+
+impl<'a> Writer<'a> {
+    fn pretty(&self) -> bool {
+        self.options.style != Style::Minified
+    }
+
+    fn newline(&mut self, depth: usize) {
+        if self.pretty() {
+            self.out.push('\n');
+            self.out.push_str(&" ".repeat(self.options.indent * depth));
+        }
+    }
+
+    fn comment(&mut self, path: &str, depth: usize) {
+        if self.options.style != Style::Json5 {
+            return;
+        }
+        let Some(comment) = self.options.comments.get(path) else {
+            return;
+        };
+        for line in comment.lines() {
+            self.out.push_str("// ");
+            self.out.push_str(line);
+            self.newline(depth);
+        }
+    }
+
+    /// json5中以注释的形式输出条目的来源
+    fn origin(&mut self, item: &Value, scope: Scope, depth: usize) {
+        if self.options.style != Style::Json5 || !self.options.provenance || !scope.has_origin() {
+            return;
+        }
+        let origin = item
+            .get(ORIGIN_FIELD)
+            .and_then(|o| serde_json::from_value::<Origin>(o.clone()).ok());
+        if let Some(origin) = origin {
+            self.out.push_str("// 来源: ");
+            self.out.push_str(&origin.describe());
+            self.newline(depth);
+        }
+    }
+
+    fn value(&mut self, value: &Value, path: &str, scope: Scope, depth: usize) {
+        match value {
+            Value::Object(map) => self.object(map, path, scope, depth),
+            Value::Array(items) => self.array(items, path, scope, depth),
+            v => self.out.push_str(&v.to_string()),
+        }
+    }
+
+    fn object(&mut self, map: &Map<String, Value>, path: &str, scope: Scope, depth: usize) {
+        let fields = self.fields(map, scope);
+        if fields.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push('{');
+        for (i, (key, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.newline(depth + 1);
+            let path = format!("{}.{}", path, key);
+            self.comment(&path, depth + 1);
+            self.key(key);
+            self.out.push(':');
+            if self.pretty() {
+                self.out.push(' ');
+            }
+            self.value(value, &path, scope.child(key), depth + 1);
+        }
+        self.newline(depth);
+        self.out.push('}');
+    }
+
+    fn array(&mut self, items: &[Value], path: &str, scope: Scope, depth: usize) {
+        if items.is_empty() {
+            self.out.push_str("[]");
+            return;
+        }
+        self.out.push('[');
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.newline(depth + 1);
+            let path = format!("{}[{}]", path, i);
+            self.comment(&path, depth + 1);
+            self.origin(item, scope, depth + 1);
+            self.value(item, &path, scope, depth + 1);
+        }
+        self.newline(depth);
+        self.out.push(']');
+    }
+
+    /// 已知的字段按参考配置的顺序，其他字段按字段名排在后面(serde_json的Map按字段名排序)
+    fn fields<'v>(&self, map: &'v Map<String, Value>, scope: Scope) -> Vec<(&'v str, &'v Value)> {
+        let order = scope.order();
+        let mut fields: Vec<_> = map
+            .iter()
+            .filter(|(k, v)| !(self.options.omit_defaults && scope.is_default(k, v)))
+            .filter(|(k, _)| {
+                // json5中的来源已经输出为注释
+                let origin = self.options.provenance && self.options.style != Style::Json5;
+                origin || !scope.has_origin() || k.as_str() != ORIGIN_FIELD
+            })
+            .map(|(k, v)| (k.as_str(), v))
+            .collect();
+        fields.sort_by_key(|(k, _)| order.iter().position(|o| o == k).unwrap_or(order.len()));
+        fields
+    }
+
+    /// json5中可以作为标识符的字段不加引号
+    fn key(&mut self, key: &str) {
+        let ident = key
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+            .unwrap_or_default()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+        if self.options.style == Style::Json5 && ident {
+            self.out.push_str(key);
+        } else {
+            self.out.push_str(&Value::from(key).to_string());
+        }
+    }
+}
+
+#[test]
+fn test_writer() {
+    let content = r#"{
+        "sites": [{"api": "csp_A", "name": "A", "key": "a", "type": 3, "ext": {"z": null, "a": "1"}}],
+        "spider": "http://a.com/a.jar",
+        "lives": [{"group": "央视", "channels": [{"urls": ["http://b.com/1.m3u8"], "name": "CCTV1"}]}],
+        "parses": [{"url": "http://p.com/?url=", "name": "解析"}],
+        "flags": []
+    }"#;
+    let source = Source::parse(content, '#').unwrap();
+
+    let minified = source.to_string_with(&WriteOptions::minified()).unwrap();
+    assert_eq!(
+        minified,
+        concat!(
+            r#"{"spider":"http://a.com/a.jar","sites":[{"key":"a","name":"A","type":3,"api":"csp_A","#,
+            r#""searchable":0,"quickSearch":0,"ext":{"a":"1","z":null}}],"#,
+            r#""lives":[{"group":"央视","channels":[{"name":"CCTV1","urls":["http://b.com/1.m3u8"]}]}],"#,
+            r#""parses":[{"name":"解析","url":"http://p.com/?url="}],"flags":[]}"#
+        )
+    );
+    let pretty = source.to_string_with(&WriteOptions::default()).unwrap();
+    assert!(pretty.starts_with("{\n  \"spider\": \"http://a.com/a.jar\",\n  \"sites\": [\n    {\n"));
+    assert_eq!(
+        serde_json::from_str::<Value>(&pretty).unwrap(),
+        serde_json::from_str::<Value>(&minified).unwrap()
+    );
+
+    let mut options = WriteOptions {
+        style: Style::Json5,
+        ..Default::default()
+    };
+    options
+        .comments
+        .insert("$".to_string(), "来源: http://a.com/tv.json".to_string());
+    options
+        .comments
+        .insert("$.sites[0]".to_string(), "站点A".to_string());
+    let json5 = source.to_string_with(&options).unwrap();
+    assert!(json5.starts_with("// 来源: http://a.com/tv.json\n{\n  spider: "));
+    assert!(json5.contains("\n    // 站点A\n    {\n      key: \"a\","));
+    let parsed = Source::parse(&json5, '#').unwrap();
+    assert_eq!(
+        parsed.to_string_with(&WriteOptions::minified()).unwrap(),
+        minified
+    );
+}
@@ -0,0 +1,386 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use rusqlite::{params, Connection as Db};
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+/// 检测记录达到该数量时写入数据库
+const BATCH: usize = 200;
+/// 统计时使用的最近记录数量
+const HISTORY: usize = 1000;
+/// 判断频繁切换状态时使用的最近记录数量
+const RECENT: usize = 20;
+/// 最近记录中状态切换的次数达到该值时认为不稳定
+const FLAPPING: usize = 4;
+/// 检测记录保留的天数，更早的记录在写入时删除
+const RETENTION_DAYS: i64 = 90;
+/// 批量查询时每条语句的地址数量，低于sqlite的参数数量限制
+const CHUNK: usize = 500;
+/// 没有检测记录的地址的稳定性，介于失效与只检测过一次的地址之间
+pub const UNKNOWN_STABILITY: f64 = 0.25;
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS checks (
+    url TEXT NOT NULL,
+    host TEXT NOT NULL,
+    ok INTEGER NOT NULL,
+    latency INTEGER,
+    checked INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_checks_url ON checks (url, checked);
+CREATE INDEX IF NOT EXISTS idx_checks_host ON checks (host, checked);
+CREATE INDEX IF NOT EXISTS idx_checks_checked ON checks (checked);
+"#;
+
+static DB: Lazy<Mutex<Option<Db>>> = Lazy::new(|| Mutex::new(None));
+static PENDING: Lazy<Mutex<Vec<Record>>> = Lazy::new(|| Mutex::new(vec![]));
+
+struct Record {
+    url: String,
+    host: String,
+    ok: bool,
+    latency: Option<u64>,
+    checked: i64,
+}
+
+/// 地址或主机的历史可用性
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Uptime {
+    /// 归一化后的地址或主机
+    pub key: String,
+    pub checks: u64,
+    pub alive: u64,
+    /// 可用率 0~1
+    pub uptime: f64,
+    /// 首次检测时间(毫秒时间戳)
+    pub first_checked: i64,
+    pub last_checked: i64,
+    /// 最后一次可用的时间(毫秒时间戳)
+    pub last_alive: Option<i64>,
+    /// 最近可用时的平均延迟(毫秒)
+    pub latency: Option<u64>,
+    /// 最近延迟的变化趋势(毫秒/次)，正数表示变慢
+    pub latency_trend: Option<f64>,
+    /// 最近的检测中频繁在可用与不可用之间切换
+    pub flapping: bool,
+    /// 稳定性 0~1，检测次数越多越可信，用于排序
+    pub stability: f64,
+}
+
+/// 打开历史数据库，之后的检测结果都会被记录
+pub fn open(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let db = Db::open(path)?;
+    db.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+    db.execute_batch(SCHEMA)?;
+    prune(&db, chrono::Local::now().timestamp_millis())?;
+    *DB.lock().unwrap() = Some(db);
+    Ok(())
+}
+
+/// 归一化地址，去除片段并统一大小写与默认端口，返回地址与主机
+pub fn normalize(url: &str) -> Option<(String, String)> {
+    let mut uri = url::Url::parse(url.trim()).ok()?;
+    let host = uri.host_str()?.to_lowercase();
+    uri.set_fragment(None);
+    Some((uri.to_string(), host))
+}
+
+/// 记录检测结果，数据库未打开时忽略
+pub fn record(url: &str, ok: bool, latency: Option<u64>) {
+    if DB.lock().unwrap().is_none() {
+        return;
+    }
+    let Some((url, host)) = normalize(url) else {
+        return;
+    };
+    let full = {
+        let mut pending = PENDING.lock().unwrap();
+        pending.push(Record {
+            url,
+            host,
+            ok,
+            latency,
+            checked: chrono::Local::now().timestamp_millis(),
+        });
+        pending.len() >= BATCH
+    };
+    if full {
+        // 检测在异步任务中进行，在阻塞线程中写入数据库
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => drop(handle.spawn_blocking(flush_logged)),
+            Err(_) => flush_logged(),
+        }
+    }
+}
+
+fn flush_logged() {
+    flush().map_err(|e| println!("uptime.error: {:?}", e)).ok();
+}
+
+/// 在阻塞线程中写入未保存的检测结果，检测结束后由调用者执行
+pub async fn save() {
+    tokio::task::spawn_blocking(flush_logged).await.ok();
+}
+
+/// 将未保存的检测结果写入数据库
+pub fn flush() -> Result<()> {
+    let records = std::mem::take(&mut *PENDING.lock().unwrap());
+    if records.is_empty() {
+        return Ok(());
+    }
+    let mut guard = DB.lock().unwrap();
+    let Some(db) = guard.as_mut() else {
+        return Ok(());
+    };
+    let tx = db.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO checks (url, host, ok, latency, checked) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for r in records {
+            stmt.execute(params![
+                r.url,
+                r.host,
+                r.ok,
+                r.latency.map(|l| l as i64),
+                r.checked
+            ])?;
+        }
+    }
+    prune(&tx, chrono::Local::now().timestamp_millis())?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// 删除超过保留天数的检测记录，返回删除的数量
+fn prune(db: &Db, now: i64) -> Result<usize> {
+    let before = now - RETENTION_DAYS * 24 * 3600 * 1000;
+    Ok(db.execute("DELETE FROM checks WHERE checked < ?1", params![before])?)
+}
+
+fn query(column: &str, key: &str) -> Option<Uptime> {
+    flush().ok();
+    let guard = DB.lock().unwrap();
+    let db = guard.as_ref()?;
+    let sql = format!(
+        "SELECT ok, latency, checked FROM checks WHERE {} = ?1 ORDER BY checked DESC LIMIT {}",
+        column, HISTORY
+    );
+    let mut stmt = db.prepare(&sql).ok()?;
+    let rows = stmt
+        .query_map(params![key], |r| {
+            Ok((
+                r.get::<_, bool>(0)?,
+                r.get::<_, Option<i64>>(1)?,
+                r.get::<_, i64>(2)?,
+            ))
+        })
+        .ok()?
+        .flatten()
+        .map(|(ok, latency, checked)| (ok, latency.map(|l| l as u64), checked))
+        .collect::<Vec<_>>();
+    summarize(key, rows)
+}
+
+/// 地址的历史可用性
+pub fn url(url: &str) -> Option<Uptime> {
+    let (url, _) = normalize(url)?;
+    query("url", &url)
+}
+
+/// 主机的历史可用性
+pub fn host(host: &str) -> Option<Uptime> {
+    query("host", &host.to_lowercase())
+}
+
+/// 批量查询地址的稳定性，在阻塞线程中读取数据库，没有记录的地址不在结果中
+pub async fn stabilities(urls: Vec<String>) -> HashMap<String, f64> {
+    tokio::task::spawn_blocking(move || {
+        flush_logged();
+        query_stabilities(&urls)
+            .map_err(|e| println!("uptime.error: {:?}", e))
+            .unwrap_or_default()
+    })
+    .await
+    .unwrap_or_default()
+}
+
+fn query_stabilities(urls: &[String]) -> Result<HashMap<String, f64>> {
+    let guard = DB.lock().unwrap();
+    let Some(db) = guard.as_ref() else {
+        return Ok(HashMap::new());
+    };
+    // 归一化后的地址对应的原始地址
+    let mut keys: HashMap<String, Vec<&str>> = HashMap::new();
+    for u in urls {
+        if let Some((url, _)) = normalize(u) {
+            keys.entry(url).or_default().push(u);
+        }
+    }
+    let normalized = keys.keys().collect::<Vec<_>>();
+    let mut rows: HashMap<String, Vec<(bool, Option<u64>, i64)>> = HashMap::new();
+    for chunk in normalized.chunks(CHUNK) {
+        let sql = format!(
+            "SELECT url, ok, latency, checked FROM checks WHERE url IN ({}) ORDER BY url, checked DESC",
+            vec!["?"; chunk.len()].join(",")
+        );
+        let mut stmt = db.prepare(&sql)?;
+        let mut query = stmt.query(rusqlite::params_from_iter(chunk))?;
+        while let Some(r) = query.next()? {
+            let items = rows.entry(r.get(0)?).or_default();
+            if items.len() < HISTORY {
+                let latency = r.get::<_, Option<i64>>(2)?.map(|l| l as u64);
+                items.push((r.get(1)?, latency, r.get(3)?));
+            }
+        }
+    }
+    let mut stabilities = HashMap::new();
+    for (url, rows) in rows {
+        let Some(uptime) = summarize(&url, rows) else {
+            continue;
+        };
+        for u in keys.get(&url).into_iter().flatten() {
+            stabilities.insert(u.to_string(), uptime.stability);
+        }
+    }
+    Ok(stabilities)
+}
+
+/// 统计检测记录，`rows`按时间倒序排列
+fn summarize(key: &str, rows: Vec<(bool, Option<u64>, i64)>) -> Option<Uptime> {
+    let last_checked = rows.first()?.2;
+    let first_checked = rows.last()?.2;
+    let checks = rows.len() as u64;
+    let alive = rows.iter().filter(|(ok, _, _)| *ok).count() as u64;
+    let last_alive = rows.iter().find(|(ok, _, _)| *ok).map(|(_, _, t)| *t);
+    let latencies = rows
+        .iter()
+        .filter(|(ok, _, _)| *ok)
+        .filter_map(|(_, l, _)| *l)
+        .take(RECENT)
+        .collect::<Vec<_>>();
+    let latency = if latencies.is_empty() {
+        None
+    } else {
+        Some(latencies.iter().sum::<u64>() / latencies.len() as u64)
+    };
+    // 按时间正序计算最小二乘斜率
+    let latency_trend = if latencies.len() < 2 {
+        None
+    } else {
+        let ys = latencies
+            .iter()
+            .rev()
+            .map(|l| *l as f64)
+            .collect::<Vec<_>>();
+        let n = ys.len() as f64;
+        let mx = (n - 1.0) / 2.0;
+        let my = ys.iter().sum::<f64>() / n;
+        let (mut num, mut den) = (0.0, 0.0);
+        for (x, y) in ys.iter().enumerate() {
+            let dx = x as f64 - mx;
+            num += dx * (y - my);
+            den += dx * dx;
+        }
+        Some(num / den)
+    };
+    let recent = rows
+        .iter()
+        .take(RECENT)
+        .map(|(ok, _, _)| *ok)
+        .collect::<Vec<_>>();
+    let flips = recent.windows(2).filter(|w| w[0] != w[1]).count();
+    let flapping = flips >= FLAPPING;
+    let mut stability = alive as f64 / (checks + 2) as f64;
+    if flapping {
+        stability /= 2.0;
+    }
+    Some(Uptime {
+        key: key.to_string(),
+        checks,
+        alive,
+        uptime: alive as f64 / checks as f64,
+        first_checked,
+        last_checked,
+        last_alive,
+        latency,
+        latency_trend,
+        flapping,
+        stability,
+    })
+}
+
+#[test]
+fn test_uptime() {
+    let rows = |items: &[(bool, u64)]| {
+        items
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, (ok, l))| (*ok, Some(*l), i as i64))
+            .collect::<Vec<_>>()
+    };
+    let once = summarize("a", rows(&[(true, 100)])).unwrap();
+    let stable = summarize("b", rows(&[(true, 100); 20])).unwrap();
+    assert_eq!(once.uptime, 1.0);
+    assert!(stable.stability > once.stability);
+    assert!(once.stability > UNKNOWN_STABILITY);
+
+    let slow = summarize("c", rows(&[(true, 100), (true, 200), (true, 300)])).unwrap();
+    assert_eq!(slow.latency, Some(200));
+    assert!((slow.latency_trend.unwrap() - 100.0).abs() < 1e-6);
+    assert_eq!(slow.last_checked, 2);
+    assert_eq!(slow.first_checked, 0);
+
+    let flapping = summarize(
+        "d",
+        rows(&[(true, 1), (false, 0), (true, 1), (false, 0), (true, 1)]),
+    )
+    .unwrap();
+    assert!(flapping.flapping);
+    assert_eq!(flapping.last_alive, Some(4));
+
+    assert_eq!(
+        normalize("HTTP://Example.com:80/a.m3u8#x").unwrap(),
+        (
+            "http://example.com/a.m3u8".to_string(),
+            "example.com".to_string()
+        )
+    );
+
+    let path = std::env::temp_dir().join(format!("tvbox-uptime-{}.db", std::process::id()));
+    open(&path).unwrap();
+    record("http://example.com/a.m3u8", true, Some(10));
+    record("http://example.com/a.m3u8#1", false, None);
+    record("http://example.com/b.m3u8", true, Some(30));
+    let u = url("http://example.com/a.m3u8").unwrap();
+    assert_eq!((u.checks, u.alive), (2, 1));
+    let h = host("EXAMPLE.com").unwrap();
+    assert_eq!(h.checks, 3);
+    assert!(url("http://example.com/none.m3u8").is_none());
+    let urls = [
+        "http://EXAMPLE.com/a.m3u8".to_string(),
+        "http://example.com/none.m3u8".to_string(),
+    ];
+    let stabilities = query_stabilities(&urls).unwrap();
+    assert_eq!(stabilities.len(), 1);
+    assert_eq!(stabilities[&urls[0]], u.stability);
+    // 超过保留天数的记录会被删除
+    {
+        let guard = DB.lock().unwrap();
+        let db = guard.as_ref().unwrap();
+        db.execute(
+            "INSERT INTO checks (url, host, ok, latency, checked) VALUES ('http://old.com/', 'old.com', 1, NULL, 0)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(
+            prune(db, chrono::Local::now().timestamp_millis()).unwrap(),
+            1
+        );
+    }
+    *DB.lock().unwrap() = None;
+    std::fs::remove_file(&path).ok();
+}
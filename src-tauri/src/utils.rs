@@ -1,13 +1,24 @@
+use crate::mirror;
 use anyhow::Result;
 use cached::proc_macro::cached;
+use encoding_rs::Encoding;
+use once_cell::sync::Lazy;
 use reqwest::header::CONTENT_TYPE;
-use std::{net::ToSocketAddrs, time::Duration};
+use std::{io::Read, net::ToSocketAddrs, path::PathBuf, time::Duration};
 use url::Url;
 
 /// 检测url的服务器网络可连接性，并不检测实际url的内容
-#[cached(key = "String", result = true, convert = r#"{ format!("{}", uri) }"#)]
 pub async fn url_connectivity(uri: &str) -> Result<bool> {
-    let uri = url::Url::parse(&uri)?;
+    // 本地文件随时可能被创建或删除，不缓存结果
+    if uri.starts_with("file:") {
+        return Ok(local_path(uri).is_some());
+    }
+    server_connectivity(uri).await
+}
+
+#[cached(key = "String", result = true, convert = r#"{ format!("{}", uri) }"#)]
+async fn server_connectivity(uri: &str) -> Result<bool> {
+    let uri = url::Url::parse(uri)?;
     let host = uri.host().ok_or(anyhow!("无效主机"))?.to_string();
     let port = uri.port().unwrap_or(80);
     let origin = format!("{}:{}", host, port);
@@ -26,7 +37,11 @@ pub async fn url_connectivity(uri: &str) -> Result<bool> {
 /// 检测url的可访问性
 /// 超时时间被设置为1.5秒
 pub async fn url_accessibility(uri: &str) -> Result<bool> {
-    let ok = url_connectivity(uri).await?;
+    if uri.starts_with("file:") {
+        return Ok(local_path(uri).is_some());
+    }
+    // GitHub地址可能无法直连，由镜像兜底
+    let ok = mirror::github_origin(uri).is_some() || url_connectivity(uri).await?;
     if !ok {
         return Ok(false);
     }
@@ -37,13 +52,20 @@ pub async fn url_accessibility(uri: &str) -> Result<bool> {
         .connect_timeout(Duration::from_secs_f32(6.0))
         .timeout(Duration::from_secs_f32(10.0))
         .build()?;
-    let resp = client.get(uri).send().await?;
+    let resp = mirror::send(&client, uri, mirror::CHECK_RETRIES).await?;
     Ok(resp.status().is_success())
 }
 
 /// 检测tvbox中直播源的url的可访问性
 pub async fn url_txt_playlist_accessibility(uri: &str) -> Result<bool> {
-    let ok = url_connectivity(uri).await?;
+    if uri.starts_with("file:") {
+        let content = read_content(uri).await?;
+        return Ok(content
+            .lines()
+            .any(|line| line.contains("http://") || line.contains("https://")));
+    }
+    // GitHub地址可能无法直连，由镜像兜底
+    let ok = mirror::github_origin(uri).is_some() || url_connectivity(uri).await?;
     if !ok {
         return Ok(false);
     }
@@ -54,16 +76,16 @@ pub async fn url_txt_playlist_accessibility(uri: &str) -> Result<bool> {
         .connect_timeout(Duration::from_secs_f32(6.0))
         .timeout(Duration::from_secs_f32(10.0))
         .build()?;
-    let resp = client.get(uri).send().await?;
+    let resp = mirror::send(&client, uri, mirror::CHECK_RETRIES).await?;
     if resp.status().is_success() {
         let text_plain = resp
             .headers()
             .get(CONTENT_TYPE)
             .and_then(|contnet_type| contnet_type.to_str().ok())
-            .and_then(|contnet_type| Some(contnet_type.contains("text/plain")))
+            .map(|contnet_type| contnet_type.contains("text/plain"))
             .unwrap_or_default();
         if text_plain {
-            let content = resp.text().await?;
+            let content = response_text(resp, None).await?;
             let checked = content
                 .lines()
                 .any(|line| line.contains("http://") || line.contains("https://"));
@@ -75,7 +97,8 @@ pub async fn url_txt_playlist_accessibility(uri: &str) -> Result<bool> {
 
 /// 检测m3u8直播地址url的可访问性
 pub async fn url_m3u8_accessibility(uri: &str) -> Result<bool> {
-    let ok = url_connectivity(uri).await?;
+    // GitHub地址可能无法直连，由镜像兜底
+    let ok = mirror::github_origin(uri).is_some() || url_connectivity(uri).await?;
     if !ok {
         return Ok(false);
     }
@@ -86,7 +109,7 @@ pub async fn url_m3u8_accessibility(uri: &str) -> Result<bool> {
         .connect_timeout(Duration::from_secs_f32(6.0))
         .timeout(Duration::from_secs_f32(10.0))
         .build()?;
-    let resp = client.get(uri).send().await?;
+    let resp = mirror::send(&client, uri, mirror::CHECK_RETRIES).await?;
     let uri = Url::parse(uri)?;
     if resp.status().is_success() {
         // 假定所有请求的url都必须是m3u8
@@ -103,10 +126,10 @@ pub async fn url_m3u8_accessibility(uri: &str) -> Result<bool> {
                 .headers()
                 .get(CONTENT_TYPE)
                 .and_then(|contnet_type| contnet_type.to_str().ok())
-                .and_then(|contnet_type| Some(contnet_type.contains("mpegURL")))
+                .map(|contnet_type| contnet_type.contains("mpegURL"))
                 .unwrap_or_default();
             if is_m3u8 {
-                let content = resp.text().await?;
+                let content = response_text(resp, None).await?;
                 let checked = m3u8_rs::parse_playlist(content.as_bytes()).is_ok();
                 return Ok(checked);
             }
@@ -115,7 +138,14 @@ pub async fn url_m3u8_accessibility(uri: &str) -> Result<bool> {
     Ok(false)
 }
 
+/// 内容的xxh3 hash，16位大写十六进制
+pub fn hash(content: &[u8]) -> String {
+    let value = xxhash_rust::xxh3::xxh3_64_with_seed(content, 42);
+    format!("{:0>16X}", value)
+}
+
 /// ipv6下待测试
+#[allow(dead_code)]
 pub async fn ipv6_connectable() -> bool {
     // Ali DNS
     let url = "tcp://[2400:3200::1]:53";
@@ -123,34 +153,156 @@ pub async fn ipv6_connectable() -> bool {
 }
 
 pub fn is_http_url(i: &str) -> bool {
-    return i.to_lowercase().starts_with("http://") || i.to_lowercase().starts_with("https://");
+    i.to_lowercase().starts_with("http://") || i.to_lowercase().starts_with("https://")
 }
 
+/// 读取内容使用的客户端
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::ClientBuilder::new()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(60))
+        .build()
+        .unwrap()
+});
+
 /// 读取uri中的内容
 pub async fn read_content(uri: &str) -> anyhow::Result<String> {
+    read_content_with(uri, None).await
+}
+
+/// 读取uri中的内容，`charset`为指定的编码，优先于BOM与声明的编码，为空时自动检测
+/// 支持http(s)、file://、data:地址以及本地路径
+pub async fn read_content_with(uri: &str, charset: Option<&str>) -> anyhow::Result<String> {
+    if uri.starts_with("data:") {
+        let (buff, declared) = data_url(uri)?;
+        return Ok(decode(&buff, charset, declared.as_deref()));
+    }
+    if let Some(path) = local_path(uri) {
+        let buff = gunzip(tokio::fs::read(path).await?)?;
+        return Ok(decode(&buff, charset, None));
+    }
     let href = url::Url::parse(uri)
         .ok()
         .and_then(|uri| if uri.has_host() { Some(uri) } else { None });
     if let Some(uri) = href {
-        let content = reqwest::get(uri).await?.text().await?;
-        Ok(content)
-    } else if std::path::Path::new(&uri).exists() {
-        let content = std::fs::read_to_string(&uri)?;
-
-        Ok(content)
+        let resp = mirror::send(&CLIENT, uri.as_str(), mirror::RETRIES).await?;
+        response_text(resp, charset).await
     } else {
         Err(anyhow!("无效资源!"))
     }
 }
 
+/// 本地文件路径，支持file://地址与本地路径，文件不存在时为空
+pub fn local_path(uri: &str) -> Option<PathBuf> {
+    let path = if uri.starts_with("file:") {
+        Url::parse(uri).ok()?.to_file_path().ok()?
+    } else if is_http_url(uri) || uri.starts_with("data:") {
+        return None;
+    } else {
+        PathBuf::from(uri)
+    };
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// 配置所在的位置，用于解析配置中的相对地址，本地文件转换为file://地址
+pub fn base_uri(uri: &str) -> Option<String> {
+    if is_http_url(uri) {
+        return Some(uri.to_string());
+    }
+    let path = local_path(uri)?.canonicalize().ok()?;
+    Url::from_file_path(path).ok().map(|u| u.to_string())
+}
+
+/// 解析data:地址，返回内容与声明的编码
+/// data:[<mediatype>][;charset=<charset>][;base64],<data>
+fn data_url(uri: &str) -> Result<(Vec<u8>, Option<String>)> {
+    use base64::{engine::general_purpose, Engine};
+    let (meta, payload) = uri["data:".len()..]
+        .split_once(',')
+        .ok_or(anyhow!("无效的data地址"))?;
+    let buff = percent_encoding::percent_decode_str(payload).collect::<Vec<u8>>();
+    let buff = if meta
+        .split(';')
+        .any(|p| p.trim().eq_ignore_ascii_case("base64"))
+    {
+        let mut text = String::from_utf8_lossy(&buff).to_string();
+        text.retain(|c| !c.is_whitespace());
+        general_purpose::STANDARD_NO_PAD.decode(text.trim_end_matches('='))?
+    } else {
+        buff
+    };
+    Ok((buff, charset_param(meta)))
+}
+
+/// 媒体类型中的charset参数, 如: text/plain; charset=gbk
+fn charset_param(mime: &str) -> Option<String> {
+    mime.split(';')
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, v)| v.trim().trim_matches('"').to_string())
+}
+
+/// 读取响应内容，编码优先使用`charset`，其次为Content-Type中的charset
+async fn response_text(resp: reqwest::Response, charset: Option<&str>) -> Result<String> {
+    let header = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(charset_param);
+    let buff = gunzip(resp.bytes().await?.to_vec())?;
+    Ok(decode(&buff, charset, header.as_deref()))
+}
+
+/// gzip压缩的内容(如: .gz文件)需要先解压
+fn gunzip(buff: Vec<u8>) -> Result<Vec<u8>> {
+    if buff.starts_with(&[0x1f, 0x8b]) {
+        let mut out = vec![];
+        flate2::read::GzDecoder::new(&buff[..]).read_to_end(&mut out)?;
+        Ok(out)
+    } else {
+        Ok(buff)
+    }
+}
+
+/// 按 指定编码 > BOM > 声明的编码 > UTF-8 > 自动检测 的顺序解码，结果不包含BOM
+/// `charset`为用户指定的编码，`declared`为Content-Type或data:地址中声明的编码
+/// 常见于GBK/GB18030编码的txt直播源
+pub fn decode(buff: &[u8], charset: Option<&str>, declared: Option<&str>) -> String {
+    let label = |c: &str| Encoding::for_label(c.trim().as_bytes());
+    let (stripped, bom) = match Encoding::for_bom(buff) {
+        Some((encoding, len)) => (&buff[len..], Some(encoding)),
+        None => (buff, None),
+    };
+    if let Some(encoding) = charset.and_then(label) {
+        let buff = if bom == Some(encoding) { stripped } else { buff };
+        return encoding.decode_without_bom_handling(buff).0.into_owned();
+    }
+    let buff = stripped;
+    let declared = declared
+        .and_then(label)
+        .filter(|e| *e != encoding_rs::UTF_8 || std::str::from_utf8(buff).is_ok());
+    let encoding = bom.or(declared).unwrap_or_else(|| {
+        if std::str::from_utf8(buff).is_ok() {
+            encoding_rs::UTF_8
+        } else {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(buff, true);
+            detector.guess(Some(b"cn"), true)
+        }
+    });
+    encoding.decode_without_bom_handling(buff).0.into_owned()
+}
+
 pub fn lan_ip() -> Option<Vec<String>> {
-    default_net::get_default_interface().ok().and_then(|i| {
-        Some(
-            i.ipv4
-                .into_iter()
-                .map(|ip| ip.addr.to_string())
-                .collect::<Vec<_>>(),
-        )
+    default_net::get_default_interface().ok().map(|i| {
+        i.ipv4
+            .into_iter()
+            .map(|ip| ip.addr.to_string())
+            .collect::<Vec<_>>()
     })
 }
 
@@ -161,7 +313,7 @@ pub fn is_installed(app: &str) -> bool {
             .into_string()
             .unwrap_or_default()
             .split(s)
-            .map(|p| {
+            .any(|p| {
                 if cfg!(windows) {
                     std::path::Path::new(p)
                         .join(format!("{}.exe", app))
@@ -170,7 +322,6 @@ pub fn is_installed(app: &str) -> bool {
                     std::path::Path::new(p).join(app).exists()
                 }
             })
-            .any(|exist| exist)
     } else {
         false
     }
@@ -195,3 +346,49 @@ fn test_mpv_installed() {
     let exist = is_installed(app);
     assert!(exist)
 }
+
+#[test]
+fn test_decode() {
+    let (gbk, _, _) = encoding_rs::GBK.encode("央视频道,#genre#\nCCTV1综合,http://a/1.m3u8");
+    assert_eq!(
+        decode(&gbk, None, None),
+        "央视频道,#genre#\nCCTV1综合,http://a/1.m3u8"
+    );
+    assert_eq!(decode(&gbk, None, Some("gb18030")).lines().count(), 2);
+    assert_eq!(decode("\u{feff}{}".as_bytes(), None, None), "{}");
+    // 声明为utf-8但实际不是时重新检测
+    assert!(decode(&gbk, None, Some("utf-8")).starts_with("央视频道"));
+    // 指定的编码优先于BOM与声明的编码
+    let utf8 = "\u{feff}直播".as_bytes();
+    assert_eq!(decode(utf8, Some("utf-8"), Some("gbk")), "直播");
+    assert_ne!(decode(utf8, Some("gbk"), None), "直播");
+    assert_eq!(decode(&gbk, Some("gbk"), Some("utf-8")).lines().count(), 2);
+
+    let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    std::io::Write::write_all(&mut gz, "直播".as_bytes()).unwrap();
+    let buff = gunzip(gz.finish().unwrap()).unwrap();
+    assert_eq!(decode(&buff, None, None), "直播");
+}
+
+#[tokio::test]
+async fn test_read_local_content() {
+    let content = read_content("data:text/plain;charset=utf-8,CCTV1%2Chttp%3A%2F%2Fa%2F1.m3u8")
+        .await
+        .unwrap();
+    assert_eq!(content, "CCTV1,http://a/1.m3u8");
+    let content = read_content("data:application/json;base64,eyJzaXRlcyI6W119")
+        .await
+        .unwrap();
+    assert_eq!(content, r#"{"sites":[]}"#);
+
+    let dir = std::env::temp_dir().join("tvbox-provider-local");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("local config.json");
+    std::fs::write(&path, "{}").unwrap();
+    let base = base_uri(path.to_str().unwrap()).unwrap();
+    assert!(base.starts_with("file://"));
+    assert_eq!(read_content(&base).await.unwrap(), "{}");
+    assert!(url_accessibility(&base).await.unwrap());
+    let jar = Url::parse(&base).unwrap().join("./jar/a.jar").unwrap();
+    assert!(local_path(jar.as_str()).is_none());
+}
@@ -2,6 +2,7 @@ import { defineStore } from "pinia";
 import { ref, computed } from "vue";
 import { invoke } from "@tauri-apps/api/tauri";
 import localstorage from "./localstorage";
+import { createJobs } from "../utils";
 async function loadResource(uri: string) {
   uri = uri.trim();
   if (!uri) {
@@ -18,20 +19,29 @@ async function loadResource(uri: string) {
   });
   return content;
 }
+// 正在执行的检测任务
+const jobs = createJobs();
 async function inlineCheckResource(items: any[], method: string) {
   if (!items.length) {
     return [];
   }
   console.time("check");
+  const jobId = jobs.start(method);
   const value = await invoke<ConnectionStatus[]>(method, {
     items,
-  }).catch((e) => {
-    console.log(e);
-    return [];
-  });
+    jobId,
+  })
+    .catch((e) => {
+      console.log(e);
+      return [];
+    })
+    .finally(() => jobs.finish(jobId));
   console.timeEnd("check");
   return value;
 }
+async function cancelChecks() {
+  await jobs.cancel();
+}
 async function checkVods(items: TvBoxVod[]) {
   return inlineCheckResource(items, "vods_connectivity");
 }
@@ -100,83 +110,19 @@ const useTvBoxStore = defineStore("tvbox", () => {
       return;
     }
     if (value) {
-      value.sites.forEach((item) => {
-        if (!item.jar) {
-          item.jar = value.spider;
-        }
-      });
-      // 合并点播
-      const sites = source.value.sites.concat(value.sites);
-      const sitesMap = {} as Record<string, TvBoxVod>;
-      sites.forEach((item) => {
-        const key = item.api + item.ext || "";
-        if (!sitesMap[key]) {
-          sitesMap[key] = item;
-        }
+      // 历史上更稳定的站点与直播排在前面
+      const merged = await invoke<TvBoxSource>("merge_tvbox", {
+        sources: [source.value, value],
+        preferStable: true,
+      }).catch((e) => {
+        console.log(e);
+        return undefined;
       });
-      source.value.sites = Object.values(sitesMap);
-      // 合并直播
-      const lives = source.value.lives.concat(value.lives);
-      const livesMap = {} as Record<string, TvBoxLive>;
-      lives.forEach((item) => {
-        const key =
-          item.url +
-          (item.channels || []).map((chn) => chn.urls.join(",")).join(",");
-        if (!livesMap[key]) {
-          livesMap[key] = item;
-        }
-      });
-      source.value.lives = Object.values(livesMap);
-      // 合并解析器
-      const parses = source.value.parses
-        ? source.value.parses.concat(value.parses || [])
-        : value.parses || [];
-      const parsesMap = {} as Record<string, TvBoxParse>;
-      parses.forEach((item) => {
-        const key = item.url;
-        if (!parsesMap[key]) {
-          parsesMap[key] = item;
-        }
-      });
-      source.value.parses = Object.values(parsesMap);
-      // 合并vip标识
-      if (value.flags) {
-        if (source.value.flags) {
-          const items = source.value.flags.concat(value.flags);
-          source.value.flags = Array.from(new Set(items));
-        } else {
-          source.value.flags = value.flags;
-        }
-      }
-      // 合并广告过滤规则
-      if (value.ads) {
-        if (source.value.ads) {
-          const items = source.value.ads.concat(value.ads);
-          source.value.ads = Array.from(new Set(items));
-        } else {
-          source.value.ads = value.ads;
-        }
-      }
-      // 合并提取规则
-      if (value.rules) {
-        if (source.value.rules) {
-          const items = source.value.rules.concat(value.rules);
-          const rulesMap = {} as Record<string, TvBoxRule>;
-          items.forEach((item) => {
-            const key =
-              item.host ||
-              "" +
-                (item.hosts || []).join(",") +
-                (item.regex || []).join(",") +
-                (item.rule || []).join(",");
-            if (!rulesMap[key]) {
-              rulesMap[key] = item;
-            }
-          });
-          source.value.rules = Object.values(rulesMap);
-        } else {
-          source.value.rules = value.rules;
-        }
+      if (merged) {
+        merged.sites.forEach((item) => {
+          item.features = features(item);
+        });
+        source.value = merged;
       }
     }
     await done();
@@ -338,9 +284,17 @@ const useTvBoxStore = defineStore("tvbox", () => {
         i.jar = src.spider;
       }
     });
+    // 按参考配置的顺序输出，省略空值与来源
+    // 格式化失败时仍然缓存原始内容
+    const value = await invoke<string>("format_tvbox", { source: src }).catch(
+      (e) => {
+        console.log("format_tvbox.error", e);
+        return JSON.stringify(src);
+      }
+    );
     await invoke("cache", {
       key: "tvbox",
-      value: JSON.stringify(src, undefined, " "),
+      value,
     });
   };
   return {
@@ -366,6 +320,8 @@ const useTvBoxStore = defineStore("tvbox", () => {
     update_merginSource,
     mergin,
     cache,
+    cancel: cancelChecks,
+    ownsJob: jobs.owns,
   };
 });
 
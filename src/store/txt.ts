@@ -1,9 +1,8 @@
 import { defineStore } from "pinia";
 import { ref, computed } from "vue";
 import { invoke } from "@tauri-apps/api/tauri";
-import { open } from "@tauri-apps/api/dialog";
-import { dirname, basename, sep } from "@tauri-apps/api/path";
-import { confirm } from "../utils";
+import { listen } from "@tauri-apps/api/event";
+import { confirm, createJobs } from "../utils";
 async function loadResource(uri: string) {
   uri = uri.trim();
   if (!uri) {
@@ -17,66 +16,60 @@ async function loadResource(uri: string) {
   });
   return content;
 }
+// 正在执行的检测任务
+const jobs = createJobs();
 async function checkResource(items: TxtPlaylist[]) {
   if (!items.length) {
     return [];
   }
   console.time("check");
+  const jobId = jobs.start("urls");
   const value = await invoke<string[]>("urls_accessibility", {
     urls: items.map((item) => item.url),
     check_m3u8: true,
-  }).catch((e) => {
-    console.log(e);
-    return [];
-  });
+    jobId,
+  })
+    .catch((e) => {
+      console.log(e);
+      return [] as string[];
+    })
+    .finally(() => jobs.finish(jobId));
   console.timeEnd("check");
   return value;
 }
+async function cancelChecks() {
+  await jobs.cancel();
+}
+// 播放器退出时返回退出码与错误输出
+listen("play://exit", (e) => {
+  console.log("play.exit", e.payload);
+});
 async function try_play(url: string) {
   const exist = await invoke("is_install", { application: "mpv" });
   if (exist) {
-    await invoke("exec", { args: `start mpv ${url}` });
+    await invoke("play", { url, profile: { player: "mpv" } }).catch((e) => {
+      console.log("play.error", e);
+    });
     return;
   }
-  let exec = window.localStorage.getItem("native_player");
+  let exec = await invoke<string | null>("custom_player");
   if (!exec) {
     await confirm("请配置mpv播放器");
-    const selected = await open({
-      multiple: true,
-      filters: [
-        {
-          name: "mpv",
-          extensions: ["exe", ""],
-        },
-      ],
+    exec = await invoke<string | null>("choose_player").catch((e) => {
+      console.log("choose_player.error", e);
+      return null;
     });
-    if (Array.isArray(selected)) {
-      exec = selected[0];
-    } else if (typeof selected == "string") {
-      exec = selected;
-    }
-    window.localStorage.setItem("native_player", exec || "");
   }
   if (!exec) {
     return;
   }
-  const dir = await dirname(exec);
-  const dir_fixed = dir
-    .split(sep)
-    .map((name) => {
-      if (/\s/.test(name)) {
-        return `"${name}"`;
-      }
-      return name;
-    })
-    .join(sep);
-  const name = await basename(exec);
-  const args = `start /d ${dir_fixed} ${name.split(".")[0]} ${url}`;
-  // TODO 仅对windows做了处理
-  const info = await invoke("exec", { args }).catch((e) => {
-    console.log("exec.error", e);
+  const info = await invoke("play", {
+    url,
+    profile: { player: "custom" },
+  }).catch((e) => {
+    console.log("play.error", e);
   });
-  console.log("exec.info", info);
+  console.log("play.info", info);
 }
 async function to_playlist(content: string) {
   const items = content
@@ -124,6 +117,23 @@ function dedup_by_url(items: TxtPlaylist[]) {
 
 const useTxtPlaylistStore = defineStore("txt_playlist", () => {
   const content = ref([] as TxtPlaylist[]);
+  // 检测结果逐条返回，无需等待全部完成
+  listen("urls_accessibility://item", (e) => {
+    const { job, connectable, extra } = e.payload as {
+      job: string;
+      connectable: boolean;
+      extra: { url: string };
+    };
+    // 忽略其他检测任务的结果
+    if (!jobs.owns(job)) {
+      return;
+    }
+    content.value.forEach((item) => {
+      if (item.url == extra.url) {
+        item.online = connectable ? 1 : -1;
+      }
+    });
+  });
   const content_text = computed(() => {
     return to_string();
   });
@@ -260,6 +270,8 @@ const useTxtPlaylistStore = defineStore("txt_playlist", () => {
     update,
     cache,
     group_move,
+    cancel: cancelChecks,
+    ownsJob: jobs.owns,
   };
 });
 
@@ -1,4 +1,6 @@
 import { Modal } from "@arco-design/web-vue";
+import { invoke } from "@tauri-apps/api/tauri";
+import { listen } from "@tauri-apps/api/event";
 function confirm(content: string) {
   return new Promise((ok, fail) => {
     Modal.confirm({
@@ -13,4 +15,35 @@ function confirm(content: string) {
   });
 }
 
-export { confirm };
+// 检测任务，id重复时后端会追加序号，以job://started通知的实际id为准
+function createJobs() {
+  // 请求的id -> 实际的id
+  const jobs = new Map<string, string>();
+  listen("job://started", (e) => {
+    const { requested, id } = e.payload as { requested?: string; id: string };
+    if (requested && jobs.has(requested)) {
+      jobs.set(requested, id);
+    }
+  });
+  return {
+    start(kind: string) {
+      const id = `${kind}-${Date.now()}`;
+      jobs.set(id, id);
+      return id;
+    },
+    finish(id: string) {
+      jobs.delete(id);
+    },
+    // 事件是否属于正在执行的任务
+    owns(job?: string) {
+      return !!job && [...jobs.values()].includes(job);
+    },
+    async cancel() {
+      for (const id of jobs.values()) {
+        await invoke("cancel_job", { id }).catch((e) => console.log(e));
+      }
+    },
+  };
+}
+
+export { confirm, createJobs };